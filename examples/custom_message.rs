@@ -0,0 +1,91 @@
+//! Previews a message type that `live_preview` does not know about.
+//!
+//! A thermal camera publishes `sensor_msgs/Image` with `16UC1` pixels holding the
+//! temperature in centi-Kelvin. The stock conversion would show them as plain mono16; this
+//! wraps the same wire layout in its own type that maps 20–40 °C onto a grey ramp and
+//! reports the temperature under the cursor.
+//!
+//! ```sh
+//! cargo run --example custom_message -- /thermal/image_raw
+//! ```
+
+use live_preview::{
+    block_on, live_preview_as, ConvertOptions, Header, ImageType, PreviewConfig, PreviewError,
+    PreviewImage,
+};
+use serde::Deserialize;
+
+/// Same fields in the same order as `sensor_msgs/Image`, so the CDR payload deserializes
+/// into it directly.
+#[derive(Debug, Deserialize)]
+struct ThermalImage {
+    header: Header,
+    height: u32,
+    width: u32,
+    #[allow(dead_code)]
+    encoding: String,
+    is_bigendian: u8,
+    step: u32,
+    data: Vec<u8>,
+}
+
+impl ThermalImage {
+    fn celsius(&self, x: u32, y: u32) -> Option<f32> {
+        let offset = (y * self.step + x * 2) as usize;
+        let bytes = [*self.data.get(offset)?, *self.data.get(offset + 1)?];
+        let centi_kelvin = if self.is_bigendian != 0 {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        };
+        Some(centi_kelvin as f32 / 100.0 - 273.15)
+    }
+}
+
+impl PreviewImage for ThermalImage {
+    fn to_image(&self, _options: &ConvertOptions) -> Result<image::DynamicImage, PreviewError> {
+        let expected = self.step as usize * self.height as usize;
+        if self.data.len() < expected || (self.step as usize) < self.width as usize * 2 {
+            return Err(PreviewError::SizeMismatch {
+                expected,
+                actual: self.data.len(),
+                what: format!("{}x{} thermal image", self.width, self.height),
+            });
+        }
+        let img = image::GrayImage::from_fn(self.width, self.height, |x, y| {
+            let celsius = self.celsius(x, y).unwrap_or(0.0);
+            let level = ((celsius - 20.0) / 20.0).clamp(0.0, 1.0);
+            image::Luma([(level * 255.0) as u8])
+        });
+        Ok(image::DynamicImage::ImageLuma8(img))
+    }
+
+    fn header(&self) -> &Header {
+        &self.header
+    }
+
+    fn as_str() -> &'static str {
+        "Image"
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.data.len()
+    }
+
+    fn pixel_value(&self, x: u32, y: u32) -> Option<String> {
+        self.celsius(x, y).map(|c| format!("{:.1} C", c))
+    }
+}
+
+fn main() {
+    let topic = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "/thermal/image_raw".to_string());
+    show_image::run_context(move || {
+        let context = ros2_client::Context::new().unwrap();
+        let config = PreviewConfig::builder()
+            .topic(topic, ImageType::Raw)
+            .build();
+        block_on(live_preview_as::<ThermalImage>(&context, config));
+    });
+}
@@ -0,0 +1,1270 @@
+use crate::config::PreviewConfig;
+use crate::msg::{CompressedImage, RawImage};
+use crate::preview::{run_until_shutdown, show_topic, Companions};
+use crate::ros::ImageType;
+use crate::sqlite::{Database, Value};
+use async_io::Timer;
+use byteorder::{BigEndian, LittleEndian};
+use futures::channel::mpsc;
+use futures::future::{self, join_all, LocalBoxFuture};
+use futures::stream::{self, StreamExt};
+use futures::{Future, FutureExt, SinkExt};
+use log::info;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Cursor, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Leading and trailing magic of an MCAP file.
+const MCAP_MAGIC: &[u8; 8] = b"\x89MCAP0\r\n";
+
+const OP_FOOTER: u8 = 0x02;
+const OP_SCHEMA: u8 = 0x03;
+const OP_CHANNEL: u8 = 0x04;
+const OP_MESSAGE: u8 = 0x05;
+const OP_CHUNK: u8 = 0x06;
+const OP_MESSAGE_INDEX: u8 = 0x07;
+const OP_CHUNK_INDEX: u8 = 0x08;
+
+/// Channel id, sequence, log time and publish time precede the data of a message record.
+const MESSAGE_HEADER_LEN: u64 = 2 + 4 + 8 + 8;
+
+/// Messages the reader thread of a topic may have read ahead of the one due.
+const READ_AHEAD: usize = 1;
+
+/// Where one recorded message is stored, so it is only read when it is due.
+struct Entry {
+    file: usize,
+    location: Location,
+    log_time: u64,
+}
+
+enum Location {
+    /// The message record at `offset` of an MCAP file, or of its compressed chunk number
+    /// `chunk` once that is decompressed.
+    Mcap { chunk: Option<usize>, offset: u64 },
+    /// The row of the `messages` table of a sqlite3 bag with this rowid.
+    Row(i64),
+}
+
+/// One storage file of a bag.
+struct BagFile {
+    path: PathBuf,
+    storage: Storage,
+}
+
+enum Storage {
+    Mcap {
+        chunks: Vec<Chunk>,
+    },
+    /// `messages` is the root page of the `messages` table.
+    Sqlite {
+        messages: u32,
+    },
+}
+
+/// A compressed MCAP chunk: its records take `len` bytes at `offset` of the file.
+struct Chunk {
+    offset: u64,
+    len: u64,
+    compression: String,
+    uncompressed_size: u64,
+}
+
+/// An image topic of the bag and its messages in recorded order.
+struct BagTopic {
+    image_type: ImageType,
+    entries: Vec<Entry>,
+}
+
+/// Plays back the image topics of `config` from a rosbag2 recording in a window each,
+/// without ROS: `path` is the bag directory or one of its `.mcap` or `.db3` files.
+/// Messages are shown with their recorded spacing divided by `speed` and go through the
+/// same render path as a live topic; the last frame of each topic stays up until its
+/// window is closed. Compressed MCAP chunks are decompressed with the `zstd` or `lz4`
+/// command when their first message is read.
+pub fn replay_bag(
+    path: &Path,
+    speed: f64,
+    config: PreviewConfig,
+) -> std::io::Result<impl Future<Output = ()>> {
+    let Bag { files, topics } = open_bag(path, &config)?;
+    let files = Arc::new(files);
+    let Some(first) = topics
+        .iter()
+        .filter_map(|(_, topic)| topic.entries.first())
+        .map(|entry| entry.log_time)
+        .min()
+    else {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "the bag has no messages on these topics",
+        ));
+    };
+
+    let config = Rc::new(config);
+    let companions = Companions::without_topics(&config);
+    let start = Instant::now();
+    let windows: Vec<LocalBoxFuture<()>> = topics
+        .into_iter()
+        .map(|(topic_name, topic)| {
+            let entries = Arc::new(topic.entries);
+            let timeline = Timeline {
+                start,
+                first,
+                speed,
+            };
+            let files = files.clone();
+            let companions = companions.clone();
+            match topic.image_type {
+                ImageType::Raw => show_topic(
+                    play::<RawImage>(timeline, files, entries, topic_name.clone()),
+                    None,
+                    None,
+                    &topic_name,
+                    config.clone(),
+                    companions,
+                )
+                .boxed_local(),
+                ImageType::Compressed => show_topic(
+                    play::<CompressedImage>(timeline, files, entries, topic_name.clone()),
+                    None,
+                    None,
+                    &topic_name,
+                    config.clone(),
+                    companions,
+                )
+                .boxed_local(),
+            }
+        })
+        .collect();
+    Ok(run_until_shutdown(
+        join_all(windows).map(|_| ()).boxed_local(),
+        config.shutdown.clone(),
+    ))
+}
+
+/// The files of a bag and the topics picked from them.
+struct Bag {
+    files: Vec<BagFile>,
+    topics: Vec<(String, BagTopic)>,
+}
+
+/// Indexes the bag at `path` and picks the topics of `config` from it, each with its
+/// messages in recorded order.
+fn open_bag(path: &Path, config: &PreviewConfig) -> std::io::Result<Bag> {
+    let mut files = Vec::new();
+    let mut topics = HashMap::new();
+    for (index, path) in bag_files(path)?.into_iter().enumerate() {
+        let storage = if is_sqlite(&path) {
+            scan_sqlite(&path, index, &mut topics)?
+        } else {
+            scan_mcap(&path, index, &mut topics)?
+        };
+        files.push(BagFile { path, storage });
+    }
+    let mut selected = Vec::new();
+    for (topic_name, _) in &config.topics {
+        let recorded = absolute(config.remapped(topic_name));
+        let Some(mut topic) = topics.remove(&recorded) else {
+            let mut available: Vec<&String> = topics.keys().collect();
+            available.sort();
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("{} is not in the bag; it has {:?}", recorded, available),
+            ));
+        };
+        // Stable, so messages logged at the same time keep their file order.
+        topic.entries.sort_by_key(|entry| entry.log_time);
+        info!(
+            "{}: {} messages in the bag",
+            topic_name,
+            topic.entries.len()
+        );
+        selected.push((topic_name.clone(), topic));
+    }
+    Ok(Bag {
+        files,
+        topics: selected,
+    })
+}
+
+/// Maps recorded log times onto the wall clock, shared by the topics of one bag so they
+/// stay in step.
+#[derive(Clone, Copy)]
+struct Timeline {
+    start: Instant,
+    first: u64,
+    speed: f64,
+}
+
+impl Timeline {
+    /// When the message logged at `log_time` is shown.
+    fn due(&self, log_time: u64) -> Instant {
+        let offset = (log_time - self.first) as f64 * 1e-9 / self.speed;
+        self.start + Duration::from_secs_f64(offset)
+    }
+}
+
+/// The `subscribe` callback of [`show_topic`] for one bag topic: each call starts the
+/// playback over. Messages are read, decompressed and deserialized on a thread of their
+/// own, a little ahead of when they are due, so a large chunk never stalls the window.
+fn play<T: DeserializeOwned + Send + 'static>(
+    timeline: Timeline,
+    files: Arc<Vec<BagFile>>,
+    entries: Arc<Vec<Entry>>,
+    topic_name: String,
+) -> impl FnMut() -> stream::LocalBoxStream<'static, Result<T, String>> {
+    move || {
+        let (mut sender, messages) = mpsc::channel(READ_AHEAD);
+        let mut reader = BagReader::new(files.clone());
+        let entries = entries.clone();
+        std::thread::Builder::new()
+            .name(format!("bag {}", topic_name))
+            .spawn(move || {
+                for entry in entries.iter() {
+                    let message = reader.read(entry).and_then(|data| deserialize(&data));
+                    // Resubscribing or closing the window drops the receiver.
+                    if futures::executor::block_on(sender.send((entry.log_time, message))).is_err()
+                    {
+                        break;
+                    }
+                }
+            })
+            .expect("failed to spawn the bag reader thread");
+        let frames = messages.then(move |(log_time, message)| async move {
+            Timer::at(timeline.due(log_time)).await;
+            message
+        });
+        let topic_name = topic_name.clone();
+        let hold = stream::once(async move {
+            info!("{}: end of the bag", topic_name);
+            future::pending().await
+        });
+        frames.chain(hold).boxed_local()
+    }
+}
+
+/// An open storage file of a bag.
+enum Handle {
+    Mcap(File),
+    Sqlite(Database),
+}
+
+/// Reads recorded messages, keeping each file open once read from and the last
+/// decompressed chunk for the messages after the first one in it.
+struct BagReader {
+    files: Arc<Vec<BagFile>>,
+    /// The open files, by file number.
+    handles: Vec<Option<Handle>>,
+    /// File and chunk number and records of the last decompressed chunk.
+    chunk: Option<(usize, usize, Vec<u8>)>,
+}
+
+impl BagReader {
+    fn new(files: Arc<Vec<BagFile>>) -> Self {
+        let handles = files.iter().map(|_| None).collect();
+        BagReader {
+            files,
+            handles,
+            chunk: None,
+        }
+    }
+
+    /// The serialized message stored at `entry`.
+    fn read(&mut self, entry: &Entry) -> Result<Vec<u8>, String> {
+        let file = &self.files[entry.file];
+        let cannot_read = |e: Error| format!("cannot read {}: {}", file.path.display(), e);
+        let handle = match &mut self.handles[entry.file] {
+            Some(handle) => handle,
+            empty => empty.insert(
+                match file.storage {
+                    Storage::Mcap { .. } => File::open(&file.path).map(Handle::Mcap),
+                    Storage::Sqlite { .. } => Database::open(&file.path).map(Handle::Sqlite),
+                }
+                .map_err(cannot_read)?,
+            ),
+        };
+        match (&entry.location, &file.storage, handle) {
+            (
+                &Location::Mcap {
+                    chunk: None,
+                    offset,
+                },
+                _,
+                Handle::Mcap(reader),
+            ) => read_message(reader, offset).map_err(cannot_read),
+            (
+                &Location::Mcap {
+                    chunk: Some(chunk),
+                    offset,
+                },
+                Storage::Mcap { chunks },
+                Handle::Mcap(reader),
+            ) => {
+                let cached =
+                    matches!(&self.chunk, Some((f, c, _)) if *f == entry.file && *c == chunk);
+                if !cached {
+                    let records = read_chunk(reader, &chunks[chunk]).map_err(cannot_read)?;
+                    self.chunk = Some((entry.file, chunk, records));
+                }
+                let (_, _, records) = self.chunk.as_ref().expect("the chunk was just read");
+                read_message(&mut Cursor::new(records), offset).map_err(cannot_read)
+            }
+            (&Location::Row(rowid), &Storage::Sqlite { messages }, Handle::Sqlite(db)) => {
+                let row = db.row(messages, rowid).map_err(cannot_read)?;
+                // rosbag2 creates messages(id, topic_id, timestamp, data).
+                match row.and_then(|values| values.into_iter().nth(3)) {
+                    Some(Value::Blob(data)) => Ok(data),
+                    _ => Err(format!(
+                        "{}: message {} has no data",
+                        file.path.display(),
+                        rowid
+                    )),
+                }
+            }
+            _ => unreachable!("entries are located in the storage of their file"),
+        }
+    }
+}
+
+/// The data of the message record at `offset` of `reader`.
+fn read_message(reader: &mut (impl Read + Seek), offset: u64) -> std::io::Result<Vec<u8>> {
+    reader.seek(SeekFrom::Start(offset))?;
+    let opcode = read_u8(reader)?;
+    let len = read_u64(reader)?;
+    if opcode != OP_MESSAGE || len < MESSAGE_HEADER_LEN {
+        return Err(chunk_error("no message record at its indexed offset"));
+    }
+    reader.seek(SeekFrom::Current(MESSAGE_HEADER_LEN as i64))?;
+    let mut data = vec![0; (len - MESSAGE_HEADER_LEN) as usize];
+    reader.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// Deserializes a CDR message as rosbag2 stores it.
+fn deserialize<T: DeserializeOwned>(data: &[u8]) -> Result<T, String> {
+    // A 4-byte encapsulation header precedes the payload; its second byte is 1 for
+    // little-endian CDR.
+    let (header, payload) = data
+        .split_at_checked(4)
+        .ok_or("message shorter than its CDR header")?;
+    let decoded = match header[1] {
+        0 => cdr_encoding::from_bytes::<T, BigEndian>(payload),
+        _ => cdr_encoding::from_bytes::<T, LittleEndian>(payload),
+    };
+    decoded
+        .map(|(message, _)| message)
+        .map_err(|e| format!("cannot deserialize a recorded message: {}", e))
+}
+
+/// Reads and decompresses the records of `chunk`.
+fn read_chunk(file: &mut File, chunk: &Chunk) -> std::io::Result<Vec<u8>> {
+    let mut data = vec![0; chunk.len as usize];
+    file.seek(SeekFrom::Start(chunk.offset))?;
+    file.read_exact(&mut data)?;
+    decompress(&chunk.compression, data, chunk.uncompressed_size)
+}
+
+/// Decompresses chunk records of `size` bytes with the `zstd` or `lz4` command, the two
+/// compressions MCAP defines.
+fn decompress(compression: &str, data: Vec<u8>, size: u64) -> std::io::Result<Vec<u8>> {
+    if !matches!(compression, "zstd" | "lz4") {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            format!("unknown chunk compression {:?}", compression),
+        ));
+    }
+    let mut child = Command::new(compression)
+        .args(["-d", "-c", "-q"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            Error::new(
+                e.kind(),
+                format!("cannot run {} to decompress a chunk: {}", compression, e),
+            )
+        })?;
+    // Fed from another thread, so a full output pipe cannot stall the input.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let feed = std::thread::spawn(move || stdin.write_all(&data));
+    let output = child.wait_with_output()?;
+    let fed = feed.join().expect("writing to a pipe does not panic");
+    if !output.status.success() {
+        return Err(Error::other(format!(
+            "{} cannot decompress a chunk: {}",
+            compression,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    fed?;
+    if output.stdout.len() as u64 != size {
+        return Err(chunk_error(&format!(
+            "chunk decompressed to {} bytes instead of {}",
+            output.stdout.len(),
+            size
+        )));
+    }
+    Ok(output.stdout)
+}
+
+fn chunk_error(what: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, what.to_string())
+}
+
+/// The storage files of a bag directory, which holds several when the recording was
+/// split, or `path` itself if it is a file.
+fn bag_files(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let file = entry?.path();
+        if file.extension().is_some_and(|e| e == "mcap") || is_sqlite(&file) {
+            files.push(file);
+        }
+    }
+    if files.is_empty() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            "no .mcap or .db3 files in the bag directory",
+        ));
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Whether `path` is a file of the sqlite3 storage rather than MCAP.
+fn is_sqlite(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "db3")
+}
+
+/// What a channel record of one file declares.
+struct Channel {
+    topic: String,
+    schema_id: i64,
+    message_encoding: String,
+}
+
+/// Records of one bag file that the messages in it are resolved against. A sqlite3 bag
+/// has a topic per channel and names its type there, so both use the topic id.
+#[derive(Default)]
+struct Records {
+    schemas: HashMap<i64, String>,
+    channels: HashMap<i64, Channel>,
+    /// Channel id and location of every message, in file order.
+    messages: Vec<(i64, Entry)>,
+}
+
+impl Records {
+    /// Reads the content of a schema or channel record with `opcode`; other records are
+    /// ignored.
+    fn definition(&mut self, opcode: u8, reader: &mut impl Read) -> std::io::Result<()> {
+        match opcode {
+            OP_SCHEMA => {
+                let id = read_u16(reader)?;
+                let name = read_string(reader)?;
+                self.schemas.insert(id.into(), name);
+            }
+            OP_CHANNEL => {
+                let id = read_u16(reader)?;
+                let schema_id = read_u16(reader)?;
+                let topic = read_string(reader)?;
+                let message_encoding = read_string(reader)?;
+                self.channels.insert(
+                    id.into(),
+                    Channel {
+                        topic,
+                        schema_id: schema_id.into(),
+                        message_encoding,
+                    },
+                );
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Adds the messages of the image topics to `topics`.
+    fn index(self, topics: &mut HashMap<String, BagTopic>) -> Result<(), String> {
+        for (channel_id, entry) in self.messages {
+            let Some(channel) = self.channels.get(&channel_id) else {
+                continue;
+            };
+            let schema = self.schemas.get(&channel.schema_id).map(String::as_str);
+            let image_type = match schema {
+                Some("sensor_msgs/msg/Image") => ImageType::Raw,
+                Some("sensor_msgs/msg/CompressedImage") => ImageType::Compressed,
+                _ => continue,
+            };
+            if channel.message_encoding != "cdr" {
+                return Err(format!(
+                    "{} is encoded as {}, not cdr",
+                    channel.topic, channel.message_encoding
+                ));
+            }
+            topics
+                .entry(channel.topic.clone())
+                .or_insert_with(|| BagTopic {
+                    image_type,
+                    entries: Vec::new(),
+                })
+                .entries
+                .push(entry);
+        }
+        Ok(())
+    }
+}
+
+/// Indexes the messages of the image topics in MCAP file number `index` into `topics`.
+fn scan_mcap(
+    path: &Path,
+    index: usize,
+    topics: &mut HashMap<String, BagTopic>,
+) -> std::io::Result<Storage> {
+    let invalid = |what: String| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), what),
+        )
+    };
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MCAP_MAGIC {
+        return Err(invalid("not an MCAP file".to_string()));
+    }
+    let end = reader.get_ref().metadata()?.len();
+    let indexed = summary(&mut reader, index, end).map_err(|e| invalid(e.to_string()))?;
+    let (records, chunks) = match indexed {
+        Some(indexed) => indexed,
+        None => {
+            // Without an index every chunk has to be read, and decompressed, to find its
+            // messages.
+            let mut records = Records::default();
+            let mut chunks = Vec::new();
+            let mut scan = Scan {
+                file: index,
+                chunk: None,
+                records: &mut records,
+                chunks: &mut chunks,
+            };
+            scan.records(&mut reader, MCAP_MAGIC.len() as u64, end)
+                .map_err(|e| invalid(e.to_string()))?;
+            (records, chunks)
+        }
+    };
+    records.index(topics).map_err(invalid)?;
+    Ok(Storage::Mcap { chunks })
+}
+
+/// A chunk index record of the summary section.
+struct ChunkIndex {
+    /// Offset of the chunk record in the file.
+    start: u64,
+    len: u64,
+    message_index_len: u64,
+    compression: String,
+    compressed_size: u64,
+    uncompressed_size: u64,
+}
+
+/// Indexes MCAP file number `index`, `end` bytes long, from its summary section and the
+/// message indexes after each chunk, without reading the chunks themselves. `None` when
+/// the file has no summary, or one that does not index every chunk's messages.
+fn summary(
+    reader: &mut (impl Read + Seek),
+    index: usize,
+    end: u64,
+) -> std::io::Result<Option<(Records, Vec<Chunk>)>> {
+    // Opcode, length, summary start, summary offset start and CRC, then the magic.
+    const FOOTER_LEN: u64 = 1 + 8 + 8 + 8 + 4;
+    let Some(footer) = end.checked_sub(FOOTER_LEN + MCAP_MAGIC.len() as u64) else {
+        return Ok(None);
+    };
+    reader.seek(SeekFrom::Start(footer))?;
+    if read_u8(reader)? != OP_FOOTER || read_u64(reader)? != FOOTER_LEN - 9 {
+        return Ok(None);
+    }
+    let summary_start = read_u64(reader)?;
+    if summary_start == 0 || summary_start >= footer {
+        return Ok(None);
+    }
+    let mut records = Records::default();
+    let mut indexes = Vec::new();
+    let mut position = summary_start;
+    while position + 9 <= footer {
+        reader.seek(SeekFrom::Start(position))?;
+        let opcode = read_u8(reader)?;
+        let len = read_u64(reader)?;
+        if opcode == OP_CHUNK_INDEX {
+            // Message start and end time precede the chunk's offset.
+            reader.seek(SeekFrom::Current(8 + 8))?;
+            let start = read_u64(reader)?;
+            let len = read_u64(reader)?;
+            // Offsets of the message index of each channel, taken together below.
+            let offsets_len = read_u32(reader)?;
+            reader.seek(SeekFrom::Current(offsets_len as i64))?;
+            indexes.push(ChunkIndex {
+                start,
+                len,
+                message_index_len: read_u64(reader)?,
+                compression: read_string(reader)?,
+                compressed_size: read_u64(reader)?,
+                uncompressed_size: read_u64(reader)?,
+            });
+        } else {
+            records.definition(opcode, reader)?;
+        }
+        position += 9 + len;
+    }
+    if indexes.is_empty() || indexes.iter().any(|chunk| chunk.message_index_len == 0) {
+        return Ok(None);
+    }
+    let mut chunks = Vec::new();
+    for chunk in indexes {
+        // Opcode and length, start and end time, uncompressed size and CRC, the
+        // compression and the records' length precede the records.
+        let records_start =
+            chunk.start + 9 + 8 + 8 + 8 + 4 + 4 + chunk.compression.len() as u64 + 8;
+        // Message offsets count from the start of the records, decompressed.
+        let (compressed, base) = if chunk.compression.is_empty() {
+            (None, records_start)
+        } else {
+            chunks.push(Chunk {
+                offset: records_start,
+                len: chunk.compressed_size,
+                compression: chunk.compression,
+                uncompressed_size: chunk.uncompressed_size,
+            });
+            (Some(chunks.len() - 1), 0)
+        };
+        let mut position = chunk.start + chunk.len;
+        let indexes_end = position + chunk.message_index_len;
+        while position + 9 <= indexes_end {
+            reader.seek(SeekFrom::Start(position))?;
+            let opcode = read_u8(reader)?;
+            let len = read_u64(reader)?;
+            if opcode == OP_MESSAGE_INDEX {
+                let channel_id = read_u16(reader)?;
+                // Log time and offset of each message.
+                let pairs = read_u32(reader)? / 16;
+                for _ in 0..pairs {
+                    let log_time = read_u64(reader)?;
+                    let offset = read_u64(reader)?;
+                    let entry = Entry {
+                        file: index,
+                        location: Location::Mcap {
+                            chunk: compressed,
+                            offset: base + offset,
+                        },
+                        log_time,
+                    };
+                    records.messages.push((channel_id.into(), entry));
+                }
+            }
+            position += 9 + len;
+        }
+    }
+    Ok(Some((records, chunks)))
+}
+
+/// What the records of one MCAP file are collected into.
+struct Scan<'a> {
+    file: usize,
+    /// The compressed chunk being read, whose offsets count from its decompressed start.
+    chunk: Option<usize>,
+    records: &'a mut Records,
+    chunks: &'a mut Vec<Chunk>,
+}
+
+impl Scan<'_> {
+    /// Reads the records between the offsets `start` and `end` of `reader`, descending
+    /// into chunks. Stops at the footer.
+    fn records(
+        &mut self,
+        reader: &mut (impl Read + Seek),
+        start: u64,
+        end: u64,
+    ) -> std::io::Result<()> {
+        let mut position = start;
+        while position + 9 <= end {
+            reader.seek(SeekFrom::Start(position))?;
+            let opcode = read_u8(reader)?;
+            let len = read_u64(reader)?;
+            let content = position + 9;
+            match opcode {
+                OP_FOOTER => break,
+                OP_MESSAGE => {
+                    let channel_id = read_u16(reader)?;
+                    let _sequence = read_u32(reader)?;
+                    let log_time = read_u64(reader)?;
+                    let entry = Entry {
+                        file: self.file,
+                        location: Location::Mcap {
+                            chunk: self.chunk,
+                            offset: position,
+                        },
+                        log_time,
+                    };
+                    self.records.messages.push((channel_id.into(), entry));
+                }
+                OP_CHUNK => {
+                    // Start and end time and CRC surround the uncompressed size.
+                    reader.seek(SeekFrom::Current(8 + 8))?;
+                    let uncompressed_size = read_u64(reader)?;
+                    reader.seek(SeekFrom::Current(4))?;
+                    let compression = read_string(reader)?;
+                    let records_len = read_u64(reader)?;
+                    let records_start = reader.stream_position()?;
+                    if compression.is_empty() {
+                        self.records(reader, records_start, records_start + records_len)?;
+                    } else {
+                        let mut data = vec![0; records_len as usize];
+                        reader.read_exact(&mut data)?;
+                        let decompressed = decompress(&compression, data, uncompressed_size)?;
+                        self.chunks.push(Chunk {
+                            offset: records_start,
+                            len: records_len,
+                            compression,
+                            uncompressed_size,
+                        });
+                        let outer = self.chunk.replace(self.chunks.len() - 1);
+                        self.records(&mut Cursor::new(decompressed), 0, uncompressed_size)?;
+                        self.chunk = outer;
+                    }
+                }
+                opcode => self.records.definition(opcode, reader)?,
+            }
+            position = content + len;
+        }
+        Ok(())
+    }
+}
+
+/// Indexes the messages of the image topics in the sqlite3 bag file number `index` into
+/// `topics`.
+fn scan_sqlite(
+    path: &Path,
+    index: usize,
+    topics: &mut HashMap<String, BagTopic>,
+) -> std::io::Result<Storage> {
+    let invalid = |what: String| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), what),
+        )
+    };
+    let tables = Database::open(path).and_then(|mut db| {
+        let roots = (db.table("topics")?, db.table("messages")?);
+        Ok((db, roots))
+    });
+    let (mut db, roots) = tables.map_err(|e| invalid(e.to_string()))?;
+    let (Some(topics_root), Some(messages)) = roots else {
+        return Err(invalid("not a rosbag2 database".to_string()));
+    };
+    let mut records = Records::default();
+    // rosbag2 creates topics(id, name, type, serialization_format, ...) and
+    // messages(id, topic_id, timestamp, data); the ids are rowids. The data is only read
+    // when it is due.
+    db.scan(topics_root, 4, |id, values| {
+        if let [_, Value::Text(topic), Value::Text(schema), Value::Text(encoding)] = &*values {
+            records.schemas.insert(id, schema.clone());
+            records.channels.insert(
+                id,
+                Channel {
+                    topic: topic.clone(),
+                    schema_id: id,
+                    message_encoding: encoding.clone(),
+                },
+            );
+        }
+        Ok(())
+    })
+    .and_then(|_| {
+        db.scan(messages, 3, |rowid, values| {
+            if let [_, Value::Integer(topic_id), Value::Integer(timestamp)] = &*values {
+                let entry = Entry {
+                    file: index,
+                    location: Location::Row(rowid),
+                    log_time: *timestamp as u64,
+                };
+                records.messages.push((*topic_id, entry));
+            }
+            Ok(())
+        })
+    })
+    .map_err(|e| invalid(e.to_string()))?;
+    records.index(topics).map_err(invalid)?;
+    Ok(Storage::Sqlite { messages })
+}
+
+/// `name` with a leading slash, the way rosbag2 records topic names.
+fn absolute(name: &str) -> String {
+    format!("/{}", name.trim_start_matches('/'))
+}
+
+fn read_u8(reader: &mut impl Read) -> std::io::Result<u8> {
+    let mut bytes = [0; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn read_u16(reader: &mut impl Read) -> std::io::Result<u16> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32(reader: &mut impl Read) -> std::io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> std::io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// A string prefixed with its `u32` byte length.
+fn read_string(reader: &mut impl Read) -> std::io::Result<String> {
+    let len = read_u32(reader)? as usize;
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::msg::Header;
+    use std::collections::BTreeMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const OP_HEADER: u8 = 0x01;
+    const OP_DATA_END: u8 = 0x0f;
+
+    /// Channel id, log time and data of a message to write.
+    type Written = (u16, u64, Vec<u8>);
+    const IMAGE_TOPIC: &str = "/camera/image_raw";
+
+    /// A file in the temporary directory, removed when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(extension: &str) -> Self {
+            static COUNT: AtomicUsize = AtomicUsize::new(0);
+            let name = format!(
+                "live_preview_bag_{}_{}.{}",
+                std::process::id(),
+                COUNT.fetch_add(1, Ordering::Relaxed),
+                extension
+            );
+            TempFile(std::env::temp_dir().join(name))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    /// A `width` x 2 mono8 image stamped `sec`, serialized as rosbag2 stores it.
+    fn image(sec: i32, width: u32) -> Vec<u8> {
+        let image = RawImage {
+            header: Header {
+                sec,
+                nanosec: 0,
+                frame_id: "camera".to_string(),
+            },
+            height: 2,
+            width,
+            encoding: "mono8".to_string(),
+            is_bigendian: 0,
+            step: width,
+            data: vec![sec as u8; 2 * width as usize],
+        };
+        let mut data = vec![0, 1, 0, 0];
+        data.extend(cdr_encoding::to_vec::<_, LittleEndian>(&image).unwrap());
+        data
+    }
+
+    fn string(s: &str) -> Vec<u8> {
+        let mut bytes = (s.len() as u32).to_le_bytes().to_vec();
+        bytes.extend_from_slice(s.as_bytes());
+        bytes
+    }
+
+    fn record(opcode: u8, content: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![opcode];
+        bytes.extend((content.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(content);
+        bytes
+    }
+
+    fn schema(id: u16, name: &str) -> Vec<u8> {
+        let mut content = id.to_le_bytes().to_vec();
+        content.extend(string(name));
+        content.extend(string("ros2msg"));
+        content.extend(0u32.to_le_bytes());
+        record(OP_SCHEMA, &content)
+    }
+
+    fn channel(id: u16, schema_id: u16, topic: &str) -> Vec<u8> {
+        let mut content = id.to_le_bytes().to_vec();
+        content.extend(schema_id.to_le_bytes());
+        content.extend(string(topic));
+        content.extend(string("cdr"));
+        content.extend(0u32.to_le_bytes());
+        record(OP_CHANNEL, &content)
+    }
+
+    fn message(channel_id: u16, log_time: u64, data: &[u8]) -> Vec<u8> {
+        let mut content = channel_id.to_le_bytes().to_vec();
+        content.extend(0u32.to_le_bytes());
+        content.extend(log_time.to_le_bytes());
+        content.extend(log_time.to_le_bytes());
+        content.extend_from_slice(data);
+        record(OP_MESSAGE, &content)
+    }
+
+    /// A chunk of `records`, stored as `compressed` by `compression`.
+    fn chunk(records: &[u8], compression: &str, compressed: &[u8]) -> Vec<u8> {
+        let mut content = [0u64, u64::MAX, records.len() as u64]
+            .iter()
+            .flat_map(|n| n.to_le_bytes())
+            .collect::<Vec<_>>();
+        content.extend(0u32.to_le_bytes());
+        content.extend(string(compression));
+        content.extend((compressed.len() as u64).to_le_bytes());
+        content.extend_from_slice(compressed);
+        record(OP_CHUNK, &content)
+    }
+
+    /// The magic and header record every MCAP file starts with.
+    fn mcap_start() -> Vec<u8> {
+        let mut bytes = MCAP_MAGIC.to_vec();
+        let mut header = string("ros2");
+        header.extend(string("test"));
+        bytes.extend(record(OP_HEADER, &header));
+        bytes
+    }
+
+    /// An MCAP file of `body` without a summary section.
+    fn unindexed_mcap(body: &[u8]) -> Vec<u8> {
+        let mut bytes = mcap_start();
+        bytes.extend_from_slice(body);
+        bytes.extend(record(OP_FOOTER, &[0; 20]));
+        bytes.extend_from_slice(MCAP_MAGIC);
+        bytes
+    }
+
+    /// An MCAP file of `definitions` followed by a chunk of each `(compression,
+    /// messages)`, compressed by `compress`, with the message indexes and summary section
+    /// rosbag2 writes.
+    fn indexed_mcap(
+        definitions: &[u8],
+        chunks: &[(&str, Vec<Written>)],
+        compress: impl Fn(&str, &[u8]) -> Vec<u8>,
+    ) -> Vec<u8> {
+        let mut bytes = mcap_start();
+        bytes.extend_from_slice(definitions);
+        let mut summary = definitions.to_vec();
+        for (compression, messages) in chunks {
+            let mut records = Vec::new();
+            let mut index: BTreeMap<u16, Vec<u8>> = BTreeMap::new();
+            for (channel_id, log_time, data) in messages {
+                let pairs = index.entry(*channel_id).or_default();
+                pairs.extend(log_time.to_le_bytes());
+                pairs.extend((records.len() as u64).to_le_bytes());
+                records.extend(message(*channel_id, *log_time, data));
+            }
+            let compressed = compress(compression, &records);
+            let chunk = chunk(&records, compression, &compressed);
+            let start = bytes.len() as u64;
+            bytes.extend_from_slice(&chunk);
+            let mut offsets = Vec::new();
+            let mut message_indexes = Vec::new();
+            for (channel_id, pairs) in index {
+                offsets.extend(channel_id.to_le_bytes());
+                offsets.extend(((bytes.len() + message_indexes.len()) as u64).to_le_bytes());
+                let mut content = channel_id.to_le_bytes().to_vec();
+                content.extend((pairs.len() as u32).to_le_bytes());
+                content.extend(pairs);
+                message_indexes.extend(record(OP_MESSAGE_INDEX, &content));
+            }
+            bytes.extend_from_slice(&message_indexes);
+            let mut content = [0, u64::MAX, start, chunk.len() as u64]
+                .iter()
+                .flat_map(|n| n.to_le_bytes())
+                .collect::<Vec<_>>();
+            content.extend((offsets.len() as u32).to_le_bytes());
+            content.extend(offsets);
+            content.extend((message_indexes.len() as u64).to_le_bytes());
+            content.extend(string(compression));
+            content.extend((compressed.len() as u64).to_le_bytes());
+            content.extend((records.len() as u64).to_le_bytes());
+            summary.extend(record(OP_CHUNK_INDEX, &content));
+        }
+        bytes.extend(record(OP_DATA_END, &0u32.to_le_bytes()));
+        let mut footer = (bytes.len() as u64).to_le_bytes().to_vec();
+        bytes.extend(summary);
+        footer.extend([0; 12]);
+        bytes.extend(record(OP_FOOTER, &footer));
+        bytes.extend_from_slice(MCAP_MAGIC);
+        bytes
+    }
+
+    fn write(bytes: &[u8], extension: &str) -> TempFile {
+        let file = TempFile::new(extension);
+        std::fs::write(&file.0, bytes).unwrap();
+        file
+    }
+
+    fn mcap(body: &[u8]) -> TempFile {
+        write(&unindexed_mcap(body), "mcap")
+    }
+
+    /// A file checked in under `testdata`, written by `write_fixtures`.
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata")
+            .join(name)
+    }
+
+    /// Schema and channel records for an image topic on channel 1.
+    fn image_channel() -> Vec<u8> {
+        let mut records = schema(1, "sensor_msgs/msg/Image");
+        records.extend(channel(1, 1, IMAGE_TOPIC));
+        records
+    }
+
+    fn config(topic_name: &str) -> PreviewConfig {
+        PreviewConfig::builder()
+            .topic(topic_name, ImageType::Raw)
+            .build()
+    }
+
+    /// The header stamps of the messages of the only topic picked from `path`.
+    fn stamps(path: &Path) -> Vec<i32> {
+        let Bag { files, mut topics } = open_bag(path, &config(IMAGE_TOPIC)).unwrap();
+        let (_, topic) = topics.pop().unwrap();
+        let mut reader = BagReader::new(Arc::new(files));
+        topic
+            .entries
+            .iter()
+            .map(|entry| {
+                let image: RawImage = reader.read(entry).and_then(|d| deserialize(&d)).unwrap();
+                image.header.sec
+            })
+            .collect()
+    }
+
+    /// `data` compressed by the `program` command.
+    fn compress(program: &str, data: &[u8]) -> Vec<u8> {
+        let mut child = Command::new(program)
+            .args(["-c", "-q"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap_or_else(|e| panic!("cannot run {}: {}", program, e));
+        let mut stdin = child.stdin.take().unwrap();
+        let data = data.to_vec();
+        let feed = std::thread::spawn(move || stdin.write_all(&data));
+        let output = child.wait_with_output().unwrap();
+        feed.join().unwrap().unwrap();
+        assert!(output.status.success());
+        output.stdout
+    }
+
+    /// The images stamped 1 to 4 of the compressed fixtures, logged out of order and
+    /// split over two chunks.
+    fn fixture_chunks() -> [Vec<Written>; 2] {
+        [
+            vec![(1, 200, image(2, 64)), (1, 100, image(1, 64))],
+            vec![(1, 400, image(4, 64)), (1, 300, image(3, 64))],
+        ]
+    }
+
+    /// Regenerates the fixtures under `testdata`: an indexed zstd MCAP file, an lz4 one
+    /// without a summary, and a sqlite3 bag whose rows overflow their pages.
+    #[test]
+    #[ignore = "rewrites testdata; needs the zstd, lz4 and sqlite3 commands"]
+    fn write_fixtures() {
+        let [first, second] = fixture_chunks();
+        let zstd = indexed_mcap(
+            &image_channel(),
+            &[("zstd", first.clone()), ("zstd", second.clone())],
+            compress,
+        );
+        std::fs::write(fixture("zstd.mcap"), zstd).unwrap();
+        // Channels may also be declared in the chunk itself.
+        let mut body = Vec::new();
+        for (i, messages) in [first, second].into_iter().enumerate() {
+            let mut records = if i == 0 { image_channel() } else { Vec::new() };
+            for (channel_id, log_time, data) in messages {
+                records.extend(message(channel_id, log_time, &data));
+            }
+            body.extend(chunk(&records, "lz4", &compress("lz4", &records)));
+        }
+        std::fs::write(fixture("lz4.mcap"), unindexed_mcap(&body)).unwrap();
+
+        // Small pages and images larger than them, so rows overflow and the table needs
+        // interior pages.
+        let mut sql = String::from(
+            "PRAGMA page_size = 1024;
+             CREATE TABLE topics(id INTEGER PRIMARY KEY, name TEXT NOT NULL,
+                 type TEXT NOT NULL, serialization_format TEXT NOT NULL,
+                 offered_qos_profiles TEXT NOT NULL);
+             CREATE TABLE messages(id INTEGER PRIMARY KEY, topic_id INTEGER NOT NULL,
+                 timestamp INTEGER NOT NULL, data BLOB NOT NULL);
+             CREATE INDEX timestamp_idx ON messages (timestamp ASC);
+             INSERT INTO topics VALUES (1, '/chatter', 'std_msgs/msg/String', 'cdr', '');
+             INSERT INTO topics VALUES (2, '/camera/image_raw', 'sensor_msgs/msg/Image',
+                 'cdr', '');
+             BEGIN;",
+        );
+        for sec in (0..SQLITE_FIXTURE_IMAGES).rev() {
+            let hex: String = image(sec, 520)
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            let timestamp = 1_700_000_000_000_000_000 + sec as i64 * 1_000_000;
+            sql += &format!(
+                "INSERT INTO messages VALUES (NULL, 2, {}, X'{}');",
+                timestamp, hex
+            );
+            sql += &format!(
+                "INSERT INTO messages VALUES (NULL, 1, {}, X'00010000');",
+                timestamp
+            );
+        }
+        sql += "COMMIT;";
+        let path = fixture("bag.db3");
+        let _ = std::fs::remove_file(&path);
+        let mut child = Command::new("sqlite3")
+            .arg(&path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .expect("cannot run sqlite3");
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(sql.as_bytes())
+            .unwrap();
+        assert!(child.wait().unwrap().success());
+    }
+
+    /// Images in `testdata/bag.db3`, stamped from 0.
+    const SQLITE_FIXTURE_IMAGES: i32 = 30;
+
+    #[test]
+    fn mcap_messages_play_in_log_time_order() {
+        let mut in_chunk = message(1, 100, &image(1, 4));
+        in_chunk.extend(message(2, 150, b"\0\x01\0\0"));
+        in_chunk.extend(message(1, 200, &image(2, 4)));
+        let mut body = schema(1, "sensor_msgs/msg/Image");
+        body.extend(schema(2, "std_msgs/msg/String"));
+        body.extend(channel(1, 1, IMAGE_TOPIC));
+        body.extend(channel(2, 2, "/chatter"));
+        body.extend(message(1, 300, &image(3, 4)));
+        body.extend(chunk(&in_chunk, "", &in_chunk));
+        body.extend(message(1, 50, &image(0, 4)));
+        let file = mcap(&body);
+        assert_eq!(stamps(&file.0), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn missing_topic_lists_the_image_topics() {
+        let mut body = schema(1, "sensor_msgs/msg/Image");
+        body.extend(schema(2, "std_msgs/msg/String"));
+        body.extend(channel(1, 1, IMAGE_TOPIC));
+        body.extend(channel(2, 2, "/chatter"));
+        body.extend(message(1, 100, &image(1, 4)));
+        body.extend(message(2, 100, b"\0\x01\0\0"));
+        let file = mcap(&body);
+        let Err(e) = open_bag(&file.0, &config("/chatter")) else {
+            panic!("/chatter is not an image topic");
+        };
+        assert_eq!(e.kind(), ErrorKind::NotFound);
+        assert!(e.to_string().ends_with("[\"/camera/image_raw\"]"), "{}", e);
+    }
+
+    #[test]
+    fn compressed_chunks_are_decompressed() {
+        for name in ["zstd.mcap", "lz4.mcap"] {
+            assert_eq!(stamps(&fixture(name)), [1, 2, 3, 4], "{}", name);
+        }
+    }
+
+    #[test]
+    fn indexed_mcap_messages_play_in_log_time_order() {
+        let mut definitions = image_channel();
+        definitions.extend(schema(2, "std_msgs/msg/String"));
+        definitions.extend(channel(2, 2, "/chatter"));
+        let chunks = [
+            (
+                "",
+                vec![(1, 300, image(3, 4)), (2, 150, b"\0\x01\0\0".to_vec())],
+            ),
+            ("", vec![(1, 100, image(1, 4)), (1, 200, image(2, 4))]),
+        ];
+        let file = write(
+            &indexed_mcap(&definitions, &chunks, |_, r| r.to_vec()),
+            "mcap",
+        );
+        assert_eq!(stamps(&file.0), [1, 2, 3]);
+    }
+
+    #[test]
+    fn indexed_chunks_are_decompressed_when_read() {
+        let chunks = [("brotli", vec![(1, 100, image(1, 4))])];
+        let file = write(
+            &indexed_mcap(&image_channel(), &chunks, |_, r| r.to_vec()),
+            "mcap",
+        );
+        // Opening only reads the index, so the compression is not looked at yet.
+        let Bag { files, mut topics } = open_bag(&file.0, &config(IMAGE_TOPIC)).unwrap();
+        let (_, topic) = topics.pop().unwrap();
+        let mut reader = BagReader::new(Arc::new(files));
+        let e = reader.read(&topic.entries[0]).unwrap_err();
+        assert!(e.contains("unknown chunk compression"), "{}", e);
+    }
+
+    #[test]
+    fn unknown_chunk_compression_is_an_error() {
+        let records = message(1, 100, &image(1, 4));
+        let file = mcap(&chunk(&records, "brotli", &records));
+        let Err(e) = open_bag(&file.0, &config(IMAGE_TOPIC)) else {
+            panic!("brotli chunks cannot be read");
+        };
+        assert!(e.to_string().contains("unknown chunk compression"), "{}", e);
+    }
+
+    #[test]
+    fn sqlite_messages_play_in_timestamp_order() {
+        assert_eq!(
+            stamps(&fixture("bag.db3")),
+            (0..SQLITE_FIXTURE_IMAGES).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn sqlite_file_without_bag_tables_is_an_error() {
+        let file = TempFile::new("db3");
+        std::fs::write(&file.0, b"SQLite format 3\0 but not really").unwrap();
+        assert!(open_bag(&file.0, &config(IMAGE_TOPIC)).is_err());
+    }
+}
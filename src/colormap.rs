@@ -0,0 +1,822 @@
+//! Colormaps that depth and mono16 images are drawn with, as 256-entry lookup tables
+//! indexed by the normalized sample.
+
+/// Colormap applied after a depth or mono16 image has been normalized to 0..=1.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Colormap {
+    /// Google's turbo, a perceptually smoother rainbow.
+    #[default]
+    Turbo,
+    /// The classic MATLAB rainbow.
+    Jet,
+    /// matplotlib's perceptually uniform default, readable in greyscale print.
+    Viridis,
+    /// Linear grey ramp, for reading off intensities.
+    Grayscale,
+}
+
+impl Colormap {
+    /// The 256 colors of the map, from the low to the high end.
+    pub fn lut(self) -> &'static [[u8; 3]; 256] {
+        match self {
+            Colormap::Turbo => &TURBO,
+            Colormap::Jet => &JET,
+            Colormap::Viridis => &VIRIDIS,
+            Colormap::Grayscale => &GRAYSCALE,
+        }
+    }
+
+    /// Color for `t` in 0..=1; values outside are clamped.
+    pub(crate) fn map(self, t: f32) -> [u8; 3] {
+        self.lut()[(t.clamp(0.0, 1.0) * 255.0).round() as usize]
+    }
+}
+
+const GRAYSCALE: [[u8; 3]; 256] = {
+    let mut lut = [[0; 3]; 256];
+    let mut i = 0;
+    while i < 256 {
+        lut[i] = [i as u8; 3];
+        i += 1;
+    }
+    lut
+};
+
+/// Sampled from Mikhailov's polynomial approximation of turbo.
+const TURBO: [[u8; 3]; 256] = [
+    [35, 23, 27],
+    [39, 26, 40],
+    [43, 28, 52],
+    [47, 30, 63],
+    [51, 32, 74],
+    [54, 35, 85],
+    [57, 37, 95],
+    [59, 40, 105],
+    [62, 42, 114],
+    [64, 44, 123],
+    [66, 47, 132],
+    [68, 49, 140],
+    [69, 52, 148],
+    [71, 55, 155],
+    [72, 57, 162],
+    [73, 60, 169],
+    [73, 62, 175],
+    [74, 65, 181],
+    [74, 68, 187],
+    [75, 70, 193],
+    [75, 73, 198],
+    [75, 76, 203],
+    [75, 79, 207],
+    [74, 81, 211],
+    [74, 84, 215],
+    [74, 87, 219],
+    [73, 89, 223],
+    [73, 92, 226],
+    [72, 95, 229],
+    [71, 98, 232],
+    [70, 101, 234],
+    [69, 103, 237],
+    [68, 106, 239],
+    [67, 109, 240],
+    [66, 112, 242],
+    [65, 114, 244],
+    [64, 117, 245],
+    [63, 120, 246],
+    [62, 123, 247],
+    [61, 125, 248],
+    [59, 128, 248],
+    [58, 131, 249],
+    [57, 134, 249],
+    [56, 136, 249],
+    [55, 139, 249],
+    [53, 142, 249],
+    [52, 144, 248],
+    [51, 147, 248],
+    [50, 150, 247],
+    [49, 152, 246],
+    [48, 155, 246],
+    [47, 158, 245],
+    [46, 160, 244],
+    [45, 163, 242],
+    [44, 165, 241],
+    [43, 168, 240],
+    [42, 170, 238],
+    [42, 173, 237],
+    [41, 175, 235],
+    [40, 178, 234],
+    [40, 180, 232],
+    [39, 182, 230],
+    [39, 185, 228],
+    [38, 187, 226],
+    [38, 189, 224],
+    [37, 192, 222],
+    [37, 194, 220],
+    [37, 196, 218],
+    [37, 198, 215],
+    [37, 200, 213],
+    [37, 202, 211],
+    [37, 205, 209],
+    [37, 207, 206],
+    [38, 209, 204],
+    [38, 210, 201],
+    [38, 212, 199],
+    [39, 214, 196],
+    [39, 216, 194],
+    [40, 218, 191],
+    [41, 220, 189],
+    [42, 221, 186],
+    [43, 223, 184],
+    [44, 225, 181],
+    [45, 226, 178],
+    [46, 228, 176],
+    [47, 229, 173],
+    [48, 231, 171],
+    [49, 232, 168],
+    [51, 234, 166],
+    [52, 235, 163],
+    [54, 236, 160],
+    [55, 238, 158],
+    [57, 239, 155],
+    [59, 240, 153],
+    [61, 241, 150],
+    [63, 242, 148],
+    [65, 243, 145],
+    [67, 244, 143],
+    [69, 245, 140],
+    [71, 246, 138],
+    [73, 247, 135],
+    [75, 248, 133],
+    [78, 249, 131],
+    [80, 249, 128],
+    [82, 250, 126],
+    [85, 250, 124],
+    [87, 251, 121],
+    [90, 251, 119],
+    [93, 252, 117],
+    [95, 252, 115],
+    [98, 253, 113],
+    [101, 253, 110],
+    [104, 253, 108],
+    [106, 253, 106],
+    [109, 254, 104],
+    [112, 254, 102],
+    [115, 254, 100],
+    [118, 254, 98],
+    [121, 254, 96],
+    [124, 253, 94],
+    [127, 253, 93],
+    [130, 253, 91],
+    [133, 253, 89],
+    [136, 252, 87],
+    [139, 252, 86],
+    [142, 252, 84],
+    [145, 251, 82],
+    [149, 251, 81],
+    [152, 250, 79],
+    [155, 249, 78],
+    [158, 249, 76],
+    [161, 248, 75],
+    [164, 247, 73],
+    [167, 246, 72],
+    [170, 246, 70],
+    [173, 245, 69],
+    [176, 244, 68],
+    [179, 243, 66],
+    [182, 242, 65],
+    [185, 240, 64],
+    [188, 239, 63],
+    [191, 238, 62],
+    [194, 237, 60],
+    [197, 235, 59],
+    [200, 234, 58],
+    [203, 233, 57],
+    [205, 231, 56],
+    [208, 230, 55],
+    [211, 228, 54],
+    [213, 227, 53],
+    [216, 225, 52],
+    [219, 223, 52],
+    [221, 222, 51],
+    [223, 220, 50],
+    [226, 218, 49],
+    [228, 216, 48],
+    [230, 214, 48],
+    [233, 212, 47],
+    [235, 210, 46],
+    [237, 208, 45],
+    [239, 206, 45],
+    [241, 204, 44],
+    [243, 202, 43],
+    [244, 200, 43],
+    [246, 198, 42],
+    [248, 196, 42],
+    [249, 193, 41],
+    [251, 191, 40],
+    [252, 189, 40],
+    [253, 186, 39],
+    [255, 184, 39],
+    [255, 181, 38],
+    [255, 179, 38],
+    [255, 177, 37],
+    [255, 174, 37],
+    [255, 172, 36],
+    [255, 169, 36],
+    [255, 166, 35],
+    [255, 164, 35],
+    [255, 161, 34],
+    [255, 159, 34],
+    [255, 156, 34],
+    [255, 153, 33],
+    [255, 151, 33],
+    [255, 148, 32],
+    [255, 145, 32],
+    [255, 142, 31],
+    [255, 140, 31],
+    [255, 137, 30],
+    [255, 134, 30],
+    [255, 131, 30],
+    [255, 129, 29],
+    [255, 126, 29],
+    [255, 123, 28],
+    [255, 120, 28],
+    [255, 117, 27],
+    [255, 115, 27],
+    [255, 112, 26],
+    [254, 109, 26],
+    [252, 106, 26],
+    [251, 104, 25],
+    [249, 101, 25],
+    [248, 98, 24],
+    [246, 95, 24],
+    [244, 92, 23],
+    [243, 90, 23],
+    [241, 87, 22],
+    [239, 84, 22],
+    [237, 82, 21],
+    [235, 79, 20],
+    [233, 76, 20],
+    [230, 74, 19],
+    [228, 71, 19],
+    [226, 69, 18],
+    [224, 66, 18],
+    [221, 64, 17],
+    [219, 61, 16],
+    [216, 59, 16],
+    [214, 56, 15],
+    [211, 54, 15],
+    [209, 52, 14],
+    [206, 49, 13],
+    [203, 47, 13],
+    [201, 45, 12],
+    [198, 43, 11],
+    [196, 41, 11],
+    [193, 39, 10],
+    [190, 37, 10],
+    [188, 35, 9],
+    [185, 33, 8],
+    [183, 31, 8],
+    [180, 29, 7],
+    [177, 28, 6],
+    [175, 26, 6],
+    [172, 24, 5],
+    [170, 23, 4],
+    [168, 22, 4],
+    [165, 20, 3],
+    [163, 19, 2],
+    [161, 18, 2],
+    [159, 17, 1],
+    [157, 16, 0],
+    [155, 15, 0],
+    [154, 14, 0],
+    [152, 14, 0],
+    [150, 13, 0],
+    [149, 12, 0],
+    [148, 12, 0],
+    [147, 12, 0],
+    [146, 12, 0],
+    [145, 11, 0],
+    [145, 12, 0],
+    [144, 12, 0],
+    [144, 12, 0],
+    [144, 12, 0],
+    [144, 13, 0],
+];
+
+const JET: [[u8; 3]; 256] = [
+    [0, 0, 128],
+    [0, 0, 132],
+    [0, 0, 136],
+    [0, 0, 140],
+    [0, 0, 144],
+    [0, 0, 147],
+    [0, 0, 152],
+    [0, 0, 156],
+    [0, 0, 160],
+    [0, 0, 163],
+    [0, 0, 168],
+    [0, 0, 172],
+    [0, 0, 176],
+    [0, 0, 179],
+    [0, 0, 184],
+    [0, 0, 188],
+    [0, 0, 192],
+    [0, 0, 195],
+    [0, 0, 200],
+    [0, 0, 204],
+    [0, 0, 208],
+    [0, 0, 211],
+    [0, 0, 216],
+    [0, 0, 220],
+    [0, 0, 224],
+    [0, 0, 227],
+    [0, 0, 232],
+    [0, 0, 236],
+    [0, 0, 240],
+    [0, 0, 243],
+    [0, 0, 248],
+    [0, 0, 252],
+    [0, 0, 255],
+    [0, 4, 255],
+    [0, 8, 255],
+    [0, 13, 255],
+    [0, 16, 255],
+    [0, 21, 255],
+    [0, 25, 255],
+    [0, 29, 255],
+    [0, 32, 255],
+    [0, 36, 255],
+    [0, 40, 255],
+    [0, 45, 255],
+    [0, 48, 255],
+    [0, 53, 255],
+    [0, 57, 255],
+    [0, 61, 255],
+    [0, 64, 255],
+    [0, 68, 255],
+    [0, 72, 255],
+    [0, 77, 255],
+    [0, 80, 255],
+    [0, 85, 255],
+    [0, 89, 255],
+    [0, 93, 255],
+    [0, 96, 255],
+    [0, 100, 255],
+    [0, 104, 255],
+    [0, 109, 255],
+    [0, 112, 255],
+    [0, 117, 255],
+    [0, 121, 255],
+    [0, 125, 255],
+    [0, 128, 255],
+    [0, 132, 255],
+    [0, 137, 255],
+    [0, 140, 255],
+    [0, 144, 255],
+    [0, 148, 255],
+    [0, 153, 255],
+    [0, 156, 255],
+    [0, 160, 255],
+    [0, 164, 255],
+    [0, 169, 255],
+    [0, 172, 255],
+    [0, 176, 255],
+    [0, 180, 255],
+    [0, 185, 255],
+    [0, 188, 255],
+    [0, 192, 255],
+    [0, 196, 255],
+    [0, 201, 255],
+    [0, 204, 255],
+    [0, 208, 255],
+    [0, 212, 255],
+    [0, 217, 255],
+    [0, 220, 255],
+    [0, 224, 255],
+    [0, 228, 255],
+    [0, 233, 255],
+    [0, 236, 255],
+    [0, 240, 255],
+    [0, 244, 255],
+    [0, 249, 255],
+    [0, 252, 255],
+    [1, 255, 254],
+    [5, 255, 250],
+    [10, 255, 245],
+    [14, 255, 242],
+    [17, 255, 238],
+    [21, 255, 234],
+    [26, 255, 229],
+    [30, 255, 226],
+    [33, 255, 222],
+    [37, 255, 218],
+    [42, 255, 213],
+    [46, 255, 210],
+    [49, 255, 206],
+    [53, 255, 202],
+    [58, 255, 197],
+    [62, 255, 194],
+    [66, 255, 190],
+    [69, 255, 186],
+    [74, 255, 181],
+    [78, 255, 178],
+    [82, 255, 174],
+    [85, 255, 170],
+    [90, 255, 165],
+    [94, 255, 162],
+    [98, 255, 158],
+    [101, 255, 154],
+    [106, 255, 149],
+    [110, 255, 146],
+    [114, 255, 142],
+    [117, 255, 138],
+    [122, 255, 133],
+    [126, 255, 130],
+    [130, 255, 126],
+    [133, 255, 122],
+    [137, 255, 118],
+    [141, 255, 114],
+    [146, 255, 109],
+    [150, 255, 105],
+    [154, 255, 101],
+    [158, 255, 98],
+    [162, 255, 94],
+    [165, 255, 90],
+    [169, 255, 86],
+    [173, 255, 82],
+    [178, 255, 77],
+    [182, 255, 73],
+    [186, 255, 69],
+    [190, 255, 66],
+    [194, 255, 62],
+    [197, 255, 58],
+    [201, 255, 54],
+    [205, 255, 50],
+    [210, 255, 45],
+    [214, 255, 41],
+    [218, 255, 37],
+    [222, 255, 33],
+    [226, 255, 30],
+    [229, 255, 26],
+    [233, 255, 22],
+    [237, 255, 18],
+    [242, 255, 13],
+    [246, 255, 9],
+    [250, 255, 5],
+    [254, 255, 1],
+    [255, 252, 0],
+    [255, 249, 0],
+    [255, 245, 0],
+    [255, 241, 0],
+    [255, 236, 0],
+    [255, 232, 0],
+    [255, 228, 0],
+    [255, 224, 0],
+    [255, 220, 0],
+    [255, 217, 0],
+    [255, 213, 0],
+    [255, 209, 0],
+    [255, 204, 0],
+    [255, 200, 0],
+    [255, 196, 0],
+    [255, 192, 0],
+    [255, 188, 0],
+    [255, 185, 0],
+    [255, 181, 0],
+    [255, 177, 0],
+    [255, 172, 0],
+    [255, 168, 0],
+    [255, 164, 0],
+    [255, 160, 0],
+    [255, 156, 0],
+    [255, 153, 0],
+    [255, 149, 0],
+    [255, 145, 0],
+    [255, 140, 0],
+    [255, 136, 0],
+    [255, 132, 0],
+    [255, 128, 0],
+    [255, 125, 0],
+    [255, 121, 0],
+    [255, 117, 0],
+    [255, 113, 0],
+    [255, 108, 0],
+    [255, 104, 0],
+    [255, 100, 0],
+    [255, 96, 0],
+    [255, 93, 0],
+    [255, 89, 0],
+    [255, 85, 0],
+    [255, 81, 0],
+    [255, 76, 0],
+    [255, 72, 0],
+    [255, 68, 0],
+    [255, 64, 0],
+    [255, 61, 0],
+    [255, 57, 0],
+    [255, 53, 0],
+    [255, 49, 0],
+    [255, 44, 0],
+    [255, 40, 0],
+    [255, 36, 0],
+    [255, 32, 0],
+    [255, 29, 0],
+    [255, 25, 0],
+    [255, 21, 0],
+    [255, 17, 0],
+    [255, 12, 0],
+    [255, 8, 0],
+    [255, 4, 0],
+    [255, 0, 0],
+    [252, 0, 0],
+    [248, 0, 0],
+    [244, 0, 0],
+    [240, 0, 0],
+    [235, 0, 0],
+    [231, 0, 0],
+    [227, 0, 0],
+    [224, 0, 0],
+    [220, 0, 0],
+    [216, 0, 0],
+    [212, 0, 0],
+    [208, 0, 0],
+    [203, 0, 0],
+    [199, 0, 0],
+    [195, 0, 0],
+    [192, 0, 0],
+    [188, 0, 0],
+    [184, 0, 0],
+    [180, 0, 0],
+    [176, 0, 0],
+    [171, 0, 0],
+    [167, 0, 0],
+    [163, 0, 0],
+    [160, 0, 0],
+    [156, 0, 0],
+    [152, 0, 0],
+    [148, 0, 0],
+    [144, 0, 0],
+    [139, 0, 0],
+    [135, 0, 0],
+    [132, 0, 0],
+    [128, 0, 0],
+];
+
+/// Sampled from a degree-6 polynomial fit of viridis.
+const VIRIDIS: [[u8; 3]; 256] = [
+    [71, 1, 85],
+    [71, 3, 87],
+    [71, 4, 88],
+    [71, 6, 89],
+    [71, 7, 91],
+    [71, 8, 92],
+    [71, 10, 93],
+    [71, 11, 95],
+    [72, 13, 96],
+    [72, 14, 97],
+    [72, 15, 99],
+    [72, 17, 100],
+    [72, 18, 101],
+    [72, 20, 103],
+    [72, 21, 104],
+    [72, 22, 105],
+    [72, 24, 106],
+    [72, 25, 108],
+    [72, 26, 109],
+    [72, 28, 110],
+    [72, 29, 111],
+    [72, 31, 112],
+    [72, 32, 113],
+    [72, 33, 114],
+    [72, 35, 116],
+    [72, 36, 117],
+    [72, 37, 118],
+    [72, 39, 119],
+    [71, 40, 120],
+    [71, 41, 121],
+    [71, 42, 121],
+    [71, 44, 122],
+    [71, 45, 123],
+    [71, 46, 124],
+    [71, 48, 125],
+    [70, 49, 126],
+    [70, 50, 127],
+    [70, 51, 127],
+    [70, 53, 128],
+    [70, 54, 129],
+    [69, 55, 129],
+    [69, 56, 130],
+    [69, 58, 131],
+    [69, 59, 131],
+    [68, 60, 132],
+    [68, 61, 133],
+    [68, 62, 133],
+    [68, 63, 134],
+    [67, 65, 134],
+    [67, 66, 135],
+    [67, 67, 135],
+    [66, 68, 136],
+    [66, 69, 136],
+    [65, 70, 136],
+    [65, 72, 137],
+    [65, 73, 137],
+    [64, 74, 138],
+    [64, 75, 138],
+    [63, 76, 138],
+    [63, 77, 139],
+    [63, 78, 139],
+    [62, 79, 139],
+    [62, 80, 139],
+    [61, 81, 140],
+    [61, 82, 140],
+    [60, 84, 140],
+    [60, 85, 140],
+    [59, 86, 140],
+    [59, 87, 141],
+    [58, 88, 141],
+    [58, 89, 141],
+    [57, 90, 141],
+    [57, 91, 141],
+    [56, 92, 141],
+    [56, 93, 141],
+    [55, 94, 142],
+    [54, 95, 142],
+    [54, 96, 142],
+    [53, 97, 142],
+    [53, 98, 142],
+    [52, 99, 142],
+    [52, 100, 142],
+    [51, 101, 142],
+    [50, 102, 142],
+    [50, 103, 142],
+    [49, 104, 142],
+    [49, 105, 142],
+    [48, 106, 142],
+    [48, 107, 142],
+    [47, 108, 142],
+    [46, 109, 142],
+    [46, 110, 142],
+    [45, 111, 142],
+    [45, 112, 142],
+    [44, 113, 142],
+    [44, 114, 142],
+    [43, 115, 142],
+    [43, 116, 142],
+    [42, 116, 142],
+    [41, 117, 142],
+    [41, 118, 142],
+    [40, 119, 142],
+    [40, 120, 142],
+    [39, 121, 142],
+    [39, 122, 142],
+    [38, 123, 142],
+    [38, 124, 141],
+    [37, 125, 141],
+    [37, 126, 141],
+    [37, 127, 141],
+    [36, 128, 141],
+    [36, 129, 141],
+    [35, 130, 141],
+    [35, 131, 141],
+    [34, 132, 141],
+    [34, 133, 141],
+    [34, 134, 141],
+    [33, 134, 141],
+    [33, 135, 140],
+    [33, 136, 140],
+    [33, 137, 140],
+    [32, 138, 140],
+    [32, 139, 140],
+    [32, 140, 140],
+    [32, 141, 140],
+    [31, 142, 140],
+    [31, 143, 139],
+    [31, 144, 139],
+    [31, 145, 139],
+    [31, 146, 139],
+    [31, 147, 139],
+    [31, 148, 139],
+    [31, 148, 138],
+    [31, 149, 138],
+    [31, 150, 138],
+    [31, 151, 138],
+    [31, 152, 137],
+    [31, 153, 137],
+    [31, 154, 137],
+    [31, 155, 137],
+    [32, 156, 136],
+    [32, 157, 136],
+    [32, 158, 136],
+    [32, 159, 136],
+    [33, 160, 135],
+    [33, 161, 135],
+    [33, 162, 135],
+    [34, 162, 134],
+    [34, 163, 134],
+    [35, 164, 133],
+    [35, 165, 133],
+    [36, 166, 133],
+    [37, 167, 132],
+    [37, 168, 132],
+    [38, 169, 131],
+    [39, 170, 131],
+    [39, 171, 130],
+    [40, 172, 130],
+    [41, 172, 129],
+    [42, 173, 128],
+    [43, 174, 128],
+    [43, 175, 127],
+    [44, 176, 127],
+    [45, 177, 126],
+    [46, 178, 125],
+    [48, 179, 125],
+    [49, 180, 124],
+    [50, 180, 123],
+    [51, 181, 122],
+    [52, 182, 122],
+    [53, 183, 121],
+    [55, 184, 120],
+    [56, 185, 119],
+    [58, 186, 118],
+    [59, 186, 117],
+    [60, 187, 116],
+    [62, 188, 115],
+    [63, 189, 114],
+    [65, 190, 113],
+    [67, 191, 112],
+    [68, 191, 111],
+    [70, 192, 110],
+    [72, 193, 109],
+    [74, 194, 108],
+    [75, 195, 107],
+    [77, 195, 105],
+    [79, 196, 104],
+    [81, 197, 103],
+    [83, 198, 102],
+    [85, 198, 100],
+    [87, 199, 99],
+    [89, 200, 98],
+    [91, 201, 96],
+    [94, 201, 95],
+    [96, 202, 94],
+    [98, 203, 92],
+    [100, 204, 91],
+    [103, 204, 89],
+    [105, 205, 88],
+    [107, 206, 86],
+    [110, 206, 85],
+    [112, 207, 83],
+    [115, 208, 82],
+    [117, 208, 80],
+    [120, 209, 78],
+    [122, 210, 77],
+    [125, 210, 75],
+    [127, 211, 74],
+    [130, 211, 72],
+    [132, 212, 70],
+    [135, 213, 69],
+    [138, 213, 67],
+    [141, 214, 65],
+    [143, 214, 64],
+    [146, 215, 62],
+    [149, 215, 61],
+    [152, 216, 59],
+    [154, 217, 57],
+    [157, 217, 56],
+    [160, 218, 54],
+    [163, 218, 52],
+    [166, 219, 51],
+    [168, 219, 49],
+    [171, 220, 48],
+    [174, 220, 46],
+    [177, 220, 45],
+    [180, 221, 43],
+    [183, 221, 42],
+    [186, 222, 41],
+    [188, 222, 39],
+    [191, 223, 38],
+    [194, 223, 37],
+    [197, 223, 36],
+    [200, 224, 35],
+    [202, 224, 33],
+    [205, 225, 32],
+    [208, 225, 32],
+    [210, 225, 31],
+    [213, 226, 30],
+    [216, 226, 29],
+    [218, 226, 29],
+    [221, 227, 28],
+    [224, 227, 28],
+    [226, 227, 27],
+    [228, 228, 27],
+    [231, 228, 27],
+    [233, 228, 27],
+    [236, 229, 27],
+    [238, 229, 27],
+    [240, 229, 28],
+    [242, 230, 28],
+    [244, 230, 29],
+    [246, 230, 30],
+    [248, 231, 31],
+    [250, 231, 32],
+    [252, 231, 33],
+];
@@ -0,0 +1,297 @@
+use crate::colormap::Colormap;
+use crate::config::PreviewConfig;
+use crate::convert::{is_monochrome, PreviewImage};
+use crate::decode::decoded;
+use crate::msg::{CompressedImage, RawImage};
+use crate::overlay::{draw_lines, GREEN};
+use crate::preview::{
+    crop_and_orient, received, save_frame, scale_to_window, subscriber, track_inner_size,
+    window_closed, window_size_for,
+};
+use crate::ros::ImageType;
+use crate::tone::ToneLut;
+use futures::future::{self, join_all, LocalBoxFuture};
+use futures::{Future, FutureExt, StreamExt};
+use image::imageops::FilterType;
+use image::RgbImage;
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+use show_image::event::{VirtualKeyCode, WindowEvent};
+use show_image::glam::UVec2;
+use show_image::{create_window, WindowOptions, WindowProxy};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+/// Latest frame of each side of the comparison, already cropped, oriented and labelled.
+type Sides = Rc<RefCell<[Option<RgbImage>; 2]>>;
+
+/// How the two sides are combined into one frame.
+#[derive(Clone, Copy)]
+enum Mode {
+    SideBySide,
+    Overlay(f32),
+    Diff,
+}
+
+impl Mode {
+    fn of(config: &PreviewConfig) -> Self {
+        match config.overlay_alpha {
+            Some(alpha) => Mode::Overlay(alpha),
+            None if config.diff => Mode::Diff,
+            None => Mode::SideBySide,
+        }
+    }
+}
+
+/// Shows the first two topics of `config` side by side in one window, each scaled to the
+/// same height, with `overlay_alpha` the second blended over the first, or with `diff`
+/// their absolute difference. Every new frame on either side redraws the pair with the
+/// other side's most recent frame.
+pub(crate) fn compare_topics(
+    node: Rc<RefCell<ros2_client::Node>>,
+    config: Rc<PreviewConfig>,
+) -> impl Future<Output = ()> {
+    let (a, b) = (&config.topics[0].0, &config.topics[1].0);
+    let (title, save_name) = match Mode::of(&config) {
+        Mode::Overlay(_) => (format!("{} + {}", a, b), format!("{}_over_{}", b, a)),
+        Mode::Diff => (format!("{} - {}", a, b), format!("{}_minus_{}", a, b)),
+        Mode::SideBySide => (format!("{} | {}", a, b), format!("{}_vs_{}", a, b)),
+    };
+    let options = WindowOptions {
+        preserve_aspect_ratio: true,
+        start_hidden: true,
+        ..Default::default()
+    };
+    let window = create_window(&title, options).unwrap();
+
+    let latest_frame: Arc<Mutex<Option<image::DynamicImage>>> = Arc::new(Mutex::new(None));
+    {
+        let latest_frame = latest_frame.clone();
+        window
+            .add_event_handler(move |_window, event, _control_flow| {
+                if let WindowEvent::KeyboardInput(event) = event {
+                    if event.input.state.is_pressed()
+                        && event.input.key_code == Some(VirtualKeyCode::S)
+                    {
+                        if let Some(frame) = latest_frame.lock().unwrap().as_ref() {
+                            save_frame(&save_name, frame);
+                        }
+                    }
+                }
+            })
+            .unwrap();
+    }
+
+    let inner_size = track_inner_size(&window);
+    let sides: Sides = Rc::new(RefCell::new([None, None]));
+    let encodings = Rc::new(RefCell::new([None, None]));
+    let futures: Vec<LocalBoxFuture<()>> = config.topics[..2]
+        .iter()
+        .enumerate()
+        .map(|(side, (topic_name, image_type))| {
+            let view = SideView {
+                side,
+                sides: sides.clone(),
+                encodings: encodings.clone(),
+                window: window.clone(),
+                inner_size: inner_size.clone(),
+                latest_frame: latest_frame.clone(),
+                config: config.clone(),
+            };
+            match image_type {
+                ImageType::Raw => {
+                    compare_side::<RawImage>(node.clone(), topic_name, view).boxed_local()
+                }
+                ImageType::Compressed => {
+                    compare_side::<CompressedImage>(node.clone(), topic_name, view).boxed_local()
+                }
+            }
+        })
+        .collect();
+    future::select(
+        join_all(futures),
+        window_closed(&window, &title).boxed_local(),
+    )
+    .map(|_| ())
+}
+
+/// What one side needs to update its half and redraw the shared window.
+struct SideView {
+    side: usize,
+    sides: Sides,
+    /// Encoding of the latest frame of each side, to warn when a diff mixes encodings.
+    encodings: Rc<RefCell<[Option<String>; 2]>>,
+    window: WindowProxy,
+    inner_size: Arc<Mutex<Option<(u32, u32)>>>,
+    latest_frame: Arc<Mutex<Option<image::DynamicImage>>>,
+    config: Rc<PreviewConfig>,
+}
+
+fn compare_side<T: DeserializeOwned + PreviewImage + Send + 'static>(
+    node: Rc<RefCell<ros2_client::Node>>,
+    topic_name: &str,
+    view: SideView,
+) -> impl Future<Output = ()> {
+    let subscribe = subscriber::<T>(node, topic_name, view.config.clone());
+    let messages = received(subscribe, topic_name, view.config.clone());
+    let mut frames = decoded(messages, topic_name, view.config.convert.clone());
+    let topic_name = topic_name.to_string();
+    let tone_lut = ToneLut::new(view.config.brightness, view.config.gamma, view.config.gain);
+    let mode = Mode::of(&view.config);
+    let mut warned_roi = false;
+    async move {
+        while let Some((msg, img)) = frames.next().await {
+            if let (Mode::Diff, Some(encoding)) = (mode, msg.encoding()) {
+                let mut encodings = view.encodings.borrow_mut();
+                if encodings[view.side].as_deref() != Some(encoding) {
+                    encodings[view.side] = Some(encoding.to_string());
+                    if let Some(other) = encodings[1 - view.side].as_deref() {
+                        if other != encoding {
+                            warn!(
+                                "{}: diffing {} against {}, the difference may be meaningless",
+                                topic_name, encoding, other
+                            );
+                        }
+                    }
+                }
+            }
+            let img = match img {
+                Ok(img) => img,
+                Err(e) => {
+                    warn!("{}: skipping frame: {}", topic_name, e);
+                    continue;
+                }
+            };
+            let Some((mut img, _crop)) =
+                crop_and_orient(img, &view.config, &topic_name, &mut warned_roi)
+            else {
+                continue;
+            };
+            if let Some(lut) = &tone_lut {
+                img = lut.apply(img, is_monochrome(msg.encoding()));
+            }
+            let mut half = img.into_rgb8();
+            if let Mode::SideBySide = mode {
+                draw_lines(&mut half, &[(topic_name.clone(), GREEN)]);
+            }
+            view.sides.borrow_mut()[view.side] = Some(half);
+
+            let [Some(left), Some(right)] = &*view.sides.borrow() else {
+                continue;
+            };
+            let filter = view.config.resample_filter();
+            let pair = match mode {
+                Mode::Overlay(alpha) => {
+                    let mut blended = blend(left, right, alpha, filter);
+                    let label = format!(
+                        "{} + {} @ {:.2}",
+                        view.config.topics[0].0, view.config.topics[1].0, alpha
+                    );
+                    draw_lines(&mut blended, &[(label, GREEN)]);
+                    blended
+                }
+                Mode::Diff => {
+                    let (mut diff, max) =
+                        difference(left, right, view.config.convert.colormap, filter);
+                    let label = format!(
+                        "|{} - {}| max {}",
+                        view.config.topics[0].0, view.config.topics[1].0, max
+                    );
+                    draw_lines(&mut diff, &[(label, GREEN)]);
+                    diff
+                }
+                Mode::SideBySide => side_by_side(left, right, filter),
+            };
+            let mut pair = image::DynamicImage::ImageRgb8(pair);
+            *view.latest_frame.lock().unwrap() = Some(pair.clone());
+            let (window_w, window_h) = window_size_for(&view.config, pair.width(), pair.height());
+            if let Some(window_filter) = view.config.filter {
+                let size = view
+                    .inner_size
+                    .lock()
+                    .unwrap()
+                    .unwrap_or((window_w, window_h));
+                pair = scale_to_window(pair, window_filter, size);
+            }
+            view.window.run_function(move |mut w| {
+                if w.image_info().is_none() {
+                    w.set_inner_size(UVec2::new(window_w, window_h));
+                    w.set_visible(true);
+                }
+            });
+            if let Err(e) = view.window.set_image("compare", pair) {
+                info!("{}: stopping preview: {}", topic_name, e);
+                break;
+            }
+        }
+    }
+}
+
+/// `img` resized to the size of `base` with `filter` unless it already matches.
+fn resized_to(img: &RgbImage, base: &RgbImage, filter: FilterType) -> RgbImage {
+    if img.dimensions() == base.dimensions() {
+        img.clone()
+    } else {
+        image::imageops::resize(img, base.width(), base.height(), filter)
+    }
+}
+
+/// Draws `top`, resized to the size of `base`, over `base` with opacity `alpha`.
+fn blend(base: &RgbImage, top: &RgbImage, alpha: f32, filter: FilterType) -> RgbImage {
+    let top = resized_to(top, base, filter);
+    let mut blended = base.clone();
+    for (out, over) in blended.pixels_mut().zip(top.pixels()) {
+        for (c, &o) in out.0.iter_mut().zip(&over.0) {
+            let mixed = *c as f32 * (1.0 - alpha) + o as f32 * alpha;
+            *c = mixed.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    blended
+}
+
+/// Absolute per-channel difference of `a` and `b`, resized to `a`, plus the largest
+/// difference. With a colormap, the largest channel difference of each pixel is mapped
+/// relative to that maximum instead, so faint differences are still visible.
+fn difference(
+    a: &RgbImage,
+    b: &RgbImage,
+    colormap: Option<Colormap>,
+    filter: FilterType,
+) -> (RgbImage, u8) {
+    let b = resized_to(b, a, filter);
+    let mut diff = RgbImage::new(a.width(), a.height());
+    for ((out, pa), pb) in diff.pixels_mut().zip(a.pixels()).zip(b.pixels()) {
+        for ((c, &x), &y) in out.0.iter_mut().zip(&pa.0).zip(&pb.0) {
+            *c = x.abs_diff(y);
+        }
+    }
+    let max = diff.pixels().flat_map(|p| p.0).max().unwrap_or(0);
+    if let Some(colormap) = colormap {
+        let scale = 1.0 / max.max(1) as f32;
+        for p in diff.pixels_mut() {
+            let largest = p.0.into_iter().max().unwrap_or(0);
+            p.0 = colormap.map(largest as f32 * scale);
+        }
+    }
+    (diff, max)
+}
+
+/// Places `left` and `right` next to each other, scaling the shorter one up to the
+/// height of the taller one.
+fn side_by_side(left: &RgbImage, right: &RgbImage, filter: FilterType) -> RgbImage {
+    let height = left.height().max(right.height());
+    let scale = |img: &RgbImage| {
+        if img.height() == height {
+            img.clone()
+        } else {
+            let width = (img.width() as u64 * height as u64 / img.height() as u64) as u32;
+            image::imageops::resize(img, width.max(1), height, filter)
+        }
+    };
+    let (left, right) = (scale(left), scale(right));
+    let mut pair = RgbImage::new(left.width() + right.width(), height);
+    image::imageops::replace(&mut pair, &left, 0, 0);
+    image::imageops::replace(&mut pair, &right, left.width() as i64, 0);
+    pair
+}
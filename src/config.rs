@@ -0,0 +1,546 @@
+use crate::colormap::Colormap;
+use crate::convert::{ConvertOptions, Origin};
+use crate::preview::{Filter, Orientation, Roi, Rotation};
+use crate::ros::{
+    build_qos, remap_topic, Durability, History, ImageType, Reliability, DEFAULT_MAX_BLOCKING_TIME,
+};
+use ros2_client::ros2::QosPolicies;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Everything [`live_preview`](crate::live_preview) needs to know: which topics to
+/// subscribe to, with which QoS, and how to show them. Usually put together with
+/// [`PreviewConfig::builder`].
+#[derive(Debug, Clone)]
+pub struct PreviewConfig {
+    /// Name of the node that owns the subscriptions.
+    pub node_name: String,
+    pub namespace: String,
+    /// `(from, to)` topic remapping rules, applied when subscribing.
+    pub remaps: Vec<(String, String)>,
+    /// Topics to preview, each in its own window.
+    pub topics: Vec<(String, ImageType)>,
+    /// Show the first two topics side by side in one window instead.
+    pub compare: bool,
+    /// Show the absolute difference of the first two topics in a single window instead.
+    pub diff: bool,
+    /// Tile all topics into a single window instead.
+    pub grid: bool,
+    /// Show one topic at a time in a single window instead, switched with the arrow keys.
+    pub cycle: bool,
+    /// Blend the second topic over the first in a single window with this opacity
+    /// instead.
+    pub overlay_alpha: Option<f32>,
+    pub qos: QosPolicies,
+    /// Policies that replace those of `qos` for single topics, by topic name as given in
+    /// `topics`.
+    pub topic_qos: Vec<(String, QosPolicies)>,
+    /// Initial window width in pixels; the height follows the image aspect ratio.
+    pub window_width: u32,
+    /// Upper bound for the initial window height in pixels.
+    pub max_window_height: Option<u32>,
+    /// Work area of the screen in pixels. Windows are shrunk to fit it, keeping the image
+    /// aspect ratio, when they are created and when the frame size changes.
+    pub screen_size: Option<(u32, u32)>,
+    /// Open the windows fullscreen; `f` toggles it.
+    pub fullscreen: bool,
+    /// Scale frames to the window with this filter; the window scales them bilinearly
+    /// when `None`. Composites are resampled with it too, with a triangle filter by default.
+    pub filter: Option<Filter>,
+    pub show_fps: bool,
+    /// Print rolling rate, bandwidth and latency statistics to stdout every second.
+    pub stats: bool,
+    /// Render at most this many frames per second; frames arriving faster are dropped.
+    pub max_fps: Option<f64>,
+    /// Convert and show only every this many-th received message, 1 for all of them. The
+    /// others are dropped before conversion.
+    pub decimate: u64,
+    /// Drop frames whose header stamp is older than the last one shown, so reordering
+    /// on the way never makes the preview jump back. Frames stamped zero are always shown.
+    pub monotonic: bool,
+    /// Whether the header overlay is on at startup; `t` toggles it.
+    pub show_header: bool,
+    /// Whether the center crosshair and rule-of-thirds lines are on at startup; `c`
+    /// toggles them.
+    pub grid_overlay: bool,
+    /// Show a sharpness score of the visible part of each frame and its running maximum,
+    /// for focusing a lens; `r` resets the maximum.
+    pub focus_meter: bool,
+    /// Whether a frame that fails to convert replaces the last good one with a red frame
+    /// naming the error at startup; `e` toggles it.
+    pub error_frame: bool,
+    pub orientation: Orientation,
+    /// Crop applied to source frames before the orientation.
+    pub roi: Option<Roi>,
+    /// Region of the displayed frame, after `roi` and the orientation, shown at native
+    /// resolution in a corner inset over a downscaled view of the whole frame. Shift +
+    /// drag selects another one and `p` removes it.
+    pub pip: Option<Roi>,
+    pub convert: ConvertOptions,
+    /// Gain applied to displayed 8-bit channels after `gamma`; 1.0 leaves them unchanged.
+    pub brightness: f32,
+    /// Gamma applied to displayed 8-bit channels; above 1.0 brightens dark regions.
+    pub gamma: f32,
+    /// Red, green and blue multipliers applied with `brightness` to RGB frames, to correct
+    /// a color cast. Single-channel frames ignore it.
+    pub gain: [f32; 3],
+    /// Recreate the subscription after this long without a frame.
+    pub timeout: Option<Duration>,
+    /// Draw a red border around the last frame once no message has arrived for this long,
+    /// keeping the subscription as it is.
+    pub stale: Option<Duration>,
+    /// Video file the displayed frames are encoded to with `ffmpeg`. With several topics
+    /// the topic name is appended to the file stem.
+    pub record: Option<PathBuf>,
+    /// Fixed frame rate of the recording; frames are stamped with their arrival time when
+    /// `None`.
+    pub record_fps: Option<f64>,
+    /// Directory that every displayed frame is written to as a numbered PNG sequence with
+    /// a `timestamps.csv`. With several topics each gets a subdirectory.
+    pub sequence_dir: Option<PathBuf>,
+    /// Allow `sequence_dir` to be a non-empty directory.
+    pub force: bool,
+    /// How much of the stream `g` writes to a GIF.
+    pub gif_length: Duration,
+    /// Frame rate of the GIF written by `g`.
+    pub gif_fps: f64,
+    /// How many recent frames the arrow keys step through while paused.
+    pub buffer_frames: usize,
+    /// sensor_msgs/CameraInfo topic whose intrinsics are overlaid.
+    pub camera_info_topic: Option<String>,
+    /// Distance in meters assumed for the ruler, which with `camera_info_topic` turns its
+    /// pixel length into an approximate metric one.
+    pub ruler_depth: Option<f32>,
+    /// vision_msgs/Detection2DArray topic whose boxes are drawn over the frames.
+    pub detections_topic: Option<String>,
+    /// Publish the displayed frames, after crop, orientation, colormap and tone but
+    /// without overlays, as `sensor_msgs/Image` on this topic. With several topics each
+    /// one's name is appended, e.g. `/preview/camera/image`.
+    pub republish_topic: Option<String>,
+    /// Also log subscriptions, first frames and decode errors to `/rosout`.
+    pub rosout: bool,
+    /// Port on which the displayed frames are also served as an MJPEG stream over HTTP.
+    /// Ignored in compare, diff, overlay, grid and cycle mode.
+    pub serve_port: Option<u16>,
+    /// When this flag is set the preview stops and drops its node, e.g. from a Ctrl-C
+    /// handler.
+    pub shutdown: Option<Arc<AtomicBool>>,
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        PreviewConfig {
+            node_name: default_node_name(),
+            namespace: "/rustdds".to_string(),
+            remaps: Vec::new(),
+            topics: Vec::new(),
+            compare: false,
+            diff: false,
+            grid: false,
+            cycle: false,
+            overlay_alpha: None,
+            qos: build_qos(
+                Reliability::BestEffort,
+                Durability::Volatile,
+                History::KeepLast,
+                2,
+                DEFAULT_MAX_BLOCKING_TIME,
+            ),
+            topic_qos: Vec::new(),
+            window_width: 1280,
+            max_window_height: None,
+            screen_size: None,
+            fullscreen: false,
+            filter: None,
+            show_fps: true,
+            stats: false,
+            max_fps: None,
+            decimate: 1,
+            show_header: false,
+            grid_overlay: false,
+            focus_meter: false,
+            error_frame: false,
+            monotonic: false,
+            orientation: Orientation::default(),
+            roi: None,
+            pip: None,
+            convert: ConvertOptions::default(),
+            brightness: 1.0,
+            gamma: 1.0,
+            gain: [1.0; 3],
+            timeout: None,
+            stale: None,
+            record: None,
+            record_fps: None,
+            sequence_dir: None,
+            force: false,
+            gif_length: Duration::from_secs(5),
+            gif_fps: 10.0,
+            buffer_frames: 30,
+            camera_info_topic: None,
+            ruler_depth: None,
+            detections_topic: None,
+            republish_topic: None,
+            rosout: false,
+            serve_port: None,
+            shutdown: None,
+        }
+    }
+}
+
+/// `rustdds_listener_<pid>`, so several previewers on one domain do not collide in the
+/// ROS graph.
+fn default_node_name() -> String {
+    format!("rustdds_listener_{}", std::process::id())
+}
+
+impl PreviewConfig {
+    pub fn builder() -> PreviewConfigBuilder {
+        PreviewConfigBuilder::default()
+    }
+
+    /// Filter for resampling frames into composites such as the grid.
+    pub(crate) fn resample_filter(&self) -> image::imageops::FilterType {
+        self.filter.unwrap_or_default().filter_type()
+    }
+
+    /// The QoS to subscribe to `topic_name` with: `qos` with its [`Self::topic_qos`]
+    /// override applied.
+    pub fn qos_for(&self, topic_name: &str) -> QosPolicies {
+        self.topic_qos
+            .iter()
+            .find(|(topic, _)| topic == topic_name)
+            .map_or_else(|| self.qos.clone(), |(_, qos)| self.qos.modify_by(qos))
+    }
+
+    /// The topic actually subscribed to for `topic_name` after applying [`Self::remaps`].
+    pub fn remapped<'a>(&'a self, topic_name: &'a str) -> &'a str {
+        remap_topic(&self.remaps, topic_name)
+    }
+
+    /// Checks that the selected mode can run with these topics.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if (self.compare || self.diff || self.overlay_alpha.is_some()) && self.topics.len() < 2 {
+            return Err(ConfigError::TwoTopicsNeeded {
+                topics: self.topics.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Why [`live_preview`](crate::live_preview) cannot run a [`PreviewConfig`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// Compare, diff and overlay modes show two topics in one window.
+    TwoTopicsNeeded { topics: usize },
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::TwoTopicsNeeded { topics } => write!(
+                f,
+                "compare, diff and overlay modes need two topics, got {}",
+                topics
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Builder for [`PreviewConfig`]; unset fields keep their [`Default`] values.
+#[derive(Debug, Clone, Default)]
+pub struct PreviewConfigBuilder {
+    config: PreviewConfig,
+}
+
+impl PreviewConfigBuilder {
+    pub fn node_name(mut self, node_name: impl Into<String>) -> Self {
+        self.config.node_name = node_name.into();
+        self
+    }
+
+    pub fn namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.config.namespace = namespace.into();
+        self
+    }
+
+    /// Adds a `from:=to` remapping rule. May be called several times.
+    pub fn remap(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.config.remaps.push((from.into(), to.into()));
+        self
+    }
+
+    /// Adds a topic to preview. May be called several times.
+    pub fn topic(mut self, topic_name: impl Into<String>, image_type: ImageType) -> Self {
+        self.config.topics.push((topic_name.into(), image_type));
+        self
+    }
+
+    pub fn compare(mut self, compare: bool) -> Self {
+        self.config.compare = compare;
+        self
+    }
+
+    pub fn diff(mut self, diff: bool) -> Self {
+        self.config.diff = diff;
+        self
+    }
+
+    pub fn grid(mut self, grid: bool) -> Self {
+        self.config.grid = grid;
+        self
+    }
+
+    pub fn cycle(mut self, cycle: bool) -> Self {
+        self.config.cycle = cycle;
+        self
+    }
+
+    pub fn overlay_alpha(mut self, alpha: Option<f32>) -> Self {
+        self.config.overlay_alpha = alpha;
+        self
+    }
+
+    pub fn qos(mut self, qos: QosPolicies) -> Self {
+        self.config.qos = qos;
+        self
+    }
+
+    /// Overrides the policies set in `qos` for `topic_name` only. See
+    /// [`crate::qos_override`].
+    pub fn topic_qos(mut self, topic_name: impl Into<String>, qos: QosPolicies) -> Self {
+        self.config.topic_qos.push((topic_name.into(), qos));
+        self
+    }
+
+    pub fn window_width(mut self, width: u32) -> Self {
+        self.config.window_width = width;
+        self
+    }
+
+    pub fn max_window_height(mut self, height: Option<u32>) -> Self {
+        self.config.max_window_height = height;
+        self
+    }
+
+    pub fn screen_size(mut self, size: Option<(u32, u32)>) -> Self {
+        self.config.screen_size = size;
+        self
+    }
+
+    pub fn fullscreen(mut self, fullscreen: bool) -> Self {
+        self.config.fullscreen = fullscreen;
+        self
+    }
+
+    pub fn filter(mut self, filter: Option<Filter>) -> Self {
+        self.config.filter = filter;
+        self
+    }
+
+    pub fn show_fps(mut self, show_fps: bool) -> Self {
+        self.config.show_fps = show_fps;
+        self
+    }
+
+    pub fn stats(mut self, stats: bool) -> Self {
+        self.config.stats = stats;
+        self
+    }
+
+    pub fn max_fps(mut self, max_fps: Option<f64>) -> Self {
+        self.config.max_fps = max_fps;
+        self
+    }
+
+    pub fn decimate(mut self, decimate: u64) -> Self {
+        self.config.decimate = decimate.max(1);
+        self
+    }
+
+    pub fn monotonic(mut self, monotonic: bool) -> Self {
+        self.config.monotonic = monotonic;
+        self
+    }
+
+    pub fn show_header(mut self, show_header: bool) -> Self {
+        self.config.show_header = show_header;
+        self
+    }
+
+    pub fn grid_overlay(mut self, grid_overlay: bool) -> Self {
+        self.config.grid_overlay = grid_overlay;
+        self
+    }
+
+    pub fn focus_meter(mut self, focus_meter: bool) -> Self {
+        self.config.focus_meter = focus_meter;
+        self
+    }
+
+    pub fn error_frame(mut self, error_frame: bool) -> Self {
+        self.config.error_frame = error_frame;
+        self
+    }
+
+    pub fn rotation(mut self, rotation: Rotation) -> Self {
+        self.config.orientation.rotation = rotation;
+        self
+    }
+
+    pub fn flip_h(mut self, flip_h: bool) -> Self {
+        self.config.orientation.flip_h = flip_h;
+        self
+    }
+
+    pub fn flip_v(mut self, flip_v: bool) -> Self {
+        self.config.orientation.flip_v = flip_v;
+        self
+    }
+
+    pub fn roi(mut self, roi: Option<Roi>) -> Self {
+        self.config.roi = roi;
+        self
+    }
+
+    pub fn pip(mut self, region: Option<Roi>) -> Self {
+        self.config.pip = region;
+        self
+    }
+
+    /// Fixed depth range in meters for the depth colormap; auto-scaled when `None`.
+    pub fn depth_range(mut self, range: Option<(f32, f32)>) -> Self {
+        self.config.convert.depth_range = range;
+        self
+    }
+
+    /// Colormap for depth and mono16 images; see [`ConvertOptions::colormap`].
+    pub fn colormap(mut self, colormap: Option<Colormap>) -> Self {
+        self.config.convert.colormap = colormap;
+        self
+    }
+
+    pub fn brightness(mut self, brightness: f32) -> Self {
+        self.config.brightness = brightness;
+        self
+    }
+
+    pub fn gamma(mut self, gamma: f32) -> Self {
+        self.config.gamma = gamma;
+        self
+    }
+
+    pub fn gain(mut self, gain: [f32; 3]) -> Self {
+        self.config.gain = gain;
+        self
+    }
+
+    pub fn assume_bgr(mut self, assume_bgr: bool) -> Self {
+        self.config.convert.assume_bgr = assume_bgr;
+        self
+    }
+
+    pub fn take_high_byte(mut self, take_high_byte: bool) -> Self {
+        self.config.convert.take_high_byte = take_high_byte;
+        self
+    }
+
+    pub fn origin(mut self, origin: Origin) -> Self {
+        self.config.convert.origin = origin;
+        self
+    }
+
+    pub fn auto_contrast(mut self, auto_contrast: bool) -> Self {
+        self.config.convert.auto_contrast = auto_contrast;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.config.timeout = timeout;
+        self
+    }
+
+    pub fn stale(mut self, stale: Option<Duration>) -> Self {
+        self.config.stale = stale;
+        self
+    }
+
+    pub fn record(mut self, path: Option<PathBuf>) -> Self {
+        self.config.record = path;
+        self
+    }
+
+    pub fn record_fps(mut self, fps: Option<f64>) -> Self {
+        self.config.record_fps = fps;
+        self
+    }
+
+    pub fn sequence_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.config.sequence_dir = dir;
+        self
+    }
+
+    pub fn force(mut self, force: bool) -> Self {
+        self.config.force = force;
+        self
+    }
+
+    pub fn gif_length(mut self, length: Duration) -> Self {
+        self.config.gif_length = length;
+        self
+    }
+
+    pub fn gif_fps(mut self, fps: f64) -> Self {
+        self.config.gif_fps = fps;
+        self
+    }
+
+    pub fn buffer_frames(mut self, frames: usize) -> Self {
+        self.config.buffer_frames = frames;
+        self
+    }
+
+    pub fn camera_info_topic(mut self, topic_name: Option<String>) -> Self {
+        self.config.camera_info_topic = topic_name;
+        self
+    }
+
+    pub fn ruler_depth(mut self, meters: Option<f32>) -> Self {
+        self.config.ruler_depth = meters;
+        self
+    }
+
+    pub fn detections_topic(mut self, topic_name: Option<String>) -> Self {
+        self.config.detections_topic = topic_name;
+        self
+    }
+
+    pub fn republish_topic(mut self, topic_name: Option<String>) -> Self {
+        self.config.republish_topic = topic_name;
+        self
+    }
+
+    pub fn rosout(mut self, rosout: bool) -> Self {
+        self.config.rosout = rosout;
+        self
+    }
+
+    pub fn serve_port(mut self, port: Option<u16>) -> Self {
+        self.config.serve_port = port;
+        self
+    }
+
+    pub fn shutdown(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.config.shutdown = Some(flag);
+        self
+    }
+
+    pub fn build(self) -> PreviewConfig {
+        self.config
+    }
+}
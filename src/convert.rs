@@ -0,0 +1,1323 @@
+use crate::colormap::Colormap;
+#[cfg(feature = "gpu-convert")]
+use crate::gpu::{self, YuvFrame, YuvLayout};
+use crate::msg::{CompressedImage, Header, RawImage};
+use clap::ValueEnum;
+use image::{ColorType, ImageBuffer, ImageFormat, ImageReader, Pixel, RgbImage, RgbaImage};
+use std::io::Cursor;
+
+#[derive(Debug)]
+pub enum PreviewError {
+    UnsupportedEncoding(String),
+    /// A CompressedImage carrying a video codec stream rather than a still image.
+    VideoFormat(String),
+    /// Fewer bytes than `what`, e.g. `"640x480 bgr8"`, needs.
+    SizeMismatch {
+        expected: usize,
+        actual: usize,
+        what: String,
+    },
+    /// A `width` or `height` of zero, which a malformed publisher may send.
+    EmptyImage {
+        width: u32,
+        height: u32,
+    },
+    Decode(image::ImageError),
+}
+
+impl std::fmt::Display for PreviewError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PreviewError::UnsupportedEncoding(encoding) => {
+                write!(
+                    f,
+                    "unsupported encoding {:?}, expected one of: {}",
+                    encoding,
+                    supported_encodings().collect::<Vec<_>>().join(", ")
+                )
+            }
+            PreviewError::VideoFormat(format) => {
+                write!(
+                    f,
+                    "compressed format {:?} is a video stream, which cannot be decoded frame by \
+                     frame; republish it as jpeg/png or subscribe to the raw topic",
+                    format
+                )
+            }
+            PreviewError::SizeMismatch {
+                expected,
+                actual,
+                what,
+            } => write!(
+                f,
+                "expected {} bytes for {}, got {}",
+                expected, what, actual
+            ),
+            PreviewError::EmptyImage { width, height } => {
+                write!(f, "image is {}x{} and has no pixels", width, height)
+            }
+            PreviewError::Decode(e) => write!(f, "decode failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PreviewError {}
+
+impl From<image::ImageError> for PreviewError {
+    fn from(e: image::ImageError) -> Self {
+        PreviewError::Decode(e)
+    }
+}
+
+impl From<std::io::Error> for PreviewError {
+    fn from(e: std::io::Error) -> Self {
+        PreviewError::Decode(image::ImageError::IoError(e))
+    }
+}
+
+/// Memory layout of a `sensor_msgs/Image` encoding and the kind of image it converts to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodingInfo {
+    name: &'static str,
+    channels: u8,
+    sample_size: u8,
+    color_type: ColorType,
+    monochrome: bool,
+}
+
+impl EncodingInfo {
+    /// The entry for `encoding`, or `None` if [`RawImage`] cannot convert it.
+    pub fn of(encoding: &str) -> Option<Self> {
+        ENCODINGS.iter().find(|info| info.name == encoding).copied()
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Samples per pixel in `data`. Packed YUV counts luma and the shared chroma sample
+    /// of each pixel; `nv12` only the full-resolution luma plane.
+    pub fn channels(&self) -> usize {
+        self.channels as usize
+    }
+
+    /// Bytes of one pixel in a row of `data`, before any row padding.
+    pub fn bytes_per_pixel(&self) -> usize {
+        self.channels as usize * self.sample_size as usize
+    }
+
+    /// Color type of the converted image without a colormap; mono16 turns RGB with one.
+    pub fn color_type(&self) -> ColorType {
+        self.color_type
+    }
+
+    /// Whether the image holds one value per pixel, mono or depth, even where a colormap
+    /// shows it in color. Bayer and YUV encodings are color.
+    pub fn is_monochrome(&self) -> bool {
+        self.monochrome
+    }
+}
+
+const fn encoding(
+    name: &'static str,
+    channels: u8,
+    sample_size: u8,
+    color_type: ColorType,
+) -> EncodingInfo {
+    EncodingInfo {
+        name,
+        channels,
+        sample_size,
+        color_type,
+        monochrome: false,
+    }
+}
+
+const fn mono(name: &'static str, sample_size: u8, color_type: ColorType) -> EncodingInfo {
+    EncodingInfo {
+        monochrome: true,
+        ..encoding(name, 1, sample_size, color_type)
+    }
+}
+
+/// Whether a frame published as `encoding`, a raw encoding or a compressed format, is mono
+/// or depth rather than color. A compressed mono JPEG is not recognized here but decodes
+/// to a single-channel image.
+pub(crate) fn is_monochrome(encoding: Option<&str>) -> bool {
+    encoding.is_some_and(|encoding| {
+        encoding.to_ascii_lowercase().contains("compresseddepth")
+            || EncodingInfo::of(encoding).is_some_and(|info| info.is_monochrome())
+    })
+}
+
+/// Every `sensor_msgs/Image` encoding that [`RawImage`] can convert. Adding one here and
+/// a conversion arm in [`RawImage::to_image`] is all a new encoding needs.
+const ENCODINGS: &[EncodingInfo] = &[
+    encoding("rgb8", 3, 1, ColorType::Rgb8),
+    encoding("bgr8", 3, 1, ColorType::Rgb8),
+    encoding("rgba8", 4, 1, ColorType::Rgba8),
+    encoding("bgra8", 4, 1, ColorType::Rgba8),
+    mono("mono8", 1, ColorType::L8),
+    mono("8UC1", 1, ColorType::L8),
+    encoding("8UC3", 3, 1, ColorType::Rgb8),
+    mono("mono16", 2, ColorType::L8),
+    encoding("rgb16", 3, 2, ColorType::Rgb8),
+    encoding("bgr16", 3, 2, ColorType::Rgb8),
+    mono("16UC1", 2, ColorType::Rgb8),
+    mono("32FC1", 4, ColorType::Rgb8),
+    encoding("bayer_rggb8", 1, 1, ColorType::Rgb8),
+    encoding("bayer_bggr8", 1, 1, ColorType::Rgb8),
+    encoding("bayer_gbrg8", 1, 1, ColorType::Rgb8),
+    encoding("bayer_grbg8", 1, 1, ColorType::Rgb8),
+    encoding("yuv422", 2, 1, ColorType::Rgb8),
+    encoding("uyvy", 2, 1, ColorType::Rgb8),
+    encoding("yuv422_yuy2", 2, 1, ColorType::Rgb8),
+    encoding("yuyv", 2, 1, ColorType::Rgb8),
+    encoding("nv12", 1, 1, ColorType::Rgb8),
+];
+
+/// Codec names that show up in `CompressedImage::format` for inter-frame video streams
+/// (theora_image_transport, ffmpeg_image_transport and friends).
+const VIDEO_FORMATS: &[&str] = &["theora", "h264", "h265", "hevc", "av1", "vp8", "vp9"];
+
+/// The raw image encodings handled by [`PreviewImage::to_image`] for [`RawImage`].
+pub fn supported_encodings() -> impl Iterator<Item = &'static str> {
+    ENCODINGS.iter().map(EncodingInfo::name)
+}
+
+/// Which corner of the image the first row of a raw buffer starts at.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Origin {
+    /// The ROS convention: rows run top to bottom.
+    #[default]
+    TopLeft,
+    /// Rows run bottom to top, as some camera drivers deliver them.
+    BottomLeft,
+}
+
+/// Settings that affect how a message is turned into a displayable image.
+#[derive(Debug, Clone, Default)]
+pub struct ConvertOptions {
+    /// Fixed depth range in meters mapped onto the colormap; auto min/max when `None`.
+    pub depth_range: Option<(f32, f32)>,
+    /// Stretch 16-bit and depth images between the 1st and 99th percentile of each frame
+    /// instead of its min/max, so a few outliers do not flatten the contrast.
+    pub auto_contrast: bool,
+    /// Read `8UC3` images as BGR, OpenCV's usual order, rather than RGB.
+    pub assume_bgr: bool,
+    /// Convert rgb16/bgr16 by taking the high byte of each sample, which is faster than
+    /// rescaling but leaves images that do not use the full 16 bits dark.
+    pub take_high_byte: bool,
+    /// Colormap for depth and mono16 images. `None` draws depth with turbo and keeps
+    /// mono16 grey.
+    pub colormap: Option<Colormap>,
+    /// Row order of raw buffers. Bottom-up rows are reversed while their padding is
+    /// stripped, so the decoded image is upright.
+    pub origin: Origin,
+}
+
+/// A message that can be shown in a preview window.
+///
+/// Implemented for [`RawImage`] and [`CompressedImage`]. To preview another image-like
+/// message, mirror its fields in order in a struct deriving `serde::Deserialize` (that is
+/// how the CDR payload is decoded), implement this trait for it and pass it to
+/// [`live_preview_as`](crate::live_preview_as). `examples/custom_message.rs` shows a
+/// complete one.
+pub trait PreviewImage {
+    /// Converts the message into a displayable image, or explains why it cannot.
+    fn to_image(&self, options: &ConvertOptions) -> Result<image::DynamicImage, PreviewError>;
+    fn header(&self) -> &Header;
+    /// ROS type name within [`Self::package`], e.g. `"Image"`; the subscription only
+    /// matches publishers of that type.
+    fn as_str() -> &'static str;
+
+    /// ROS package that defines the message, e.g. `"foxglove_msgs"`.
+    fn package() -> &'static str {
+        "sensor_msgs"
+    }
+
+    /// Size of the image payload as received, before decoding.
+    fn encoded_len(&self) -> usize;
+
+    /// Describes the source value at pixel (`x`, `y`) when it carries more meaning than
+    /// the displayed color, e.g. depth in meters. `y` counts rows in buffer order, which
+    /// is bottom-up with [`Origin::BottomLeft`].
+    fn pixel_value(&self, _x: u32, _y: u32) -> Option<String> {
+        None
+    }
+
+    /// Encoding or format string of the message as published, e.g. `bgr8` or `jpeg`,
+    /// shown in the window title.
+    fn encoding(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// A multi-byte sample type that can appear in a `RawImage` buffer.
+trait Sample: Sized {
+    const SIZE: usize;
+    fn from_be(bytes: &[u8]) -> Self;
+    fn from_ne(bytes: &[u8]) -> Self;
+}
+
+impl Sample for u16 {
+    const SIZE: usize = 2;
+    fn from_be(bytes: &[u8]) -> Self {
+        u16::from_be_bytes([bytes[0], bytes[1]])
+    }
+    fn from_ne(bytes: &[u8]) -> Self {
+        u16::from_ne_bytes([bytes[0], bytes[1]])
+    }
+}
+
+impl Sample for f32 {
+    const SIZE: usize = 4;
+    fn from_be(bytes: &[u8]) -> Self {
+        f32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+    fn from_ne(bytes: &[u8]) -> Self {
+        f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+}
+
+impl RawImage {
+    /// A `sensor_msgs/Image` holding `img` as `mono8`, `mono16`, `rgb8` or `rgba8`, the
+    /// closest of them for other formats.
+    pub(crate) fn from_image(img: &image::DynamicImage, header: Header) -> RawImage {
+        let (encoding, data) = match img {
+            image::DynamicImage::ImageLuma8(buf) => ("mono8", buf.as_raw().clone()),
+            image::DynamicImage::ImageLuma16(buf) => {
+                let data = buf.as_raw().iter().flat_map(|v| v.to_le_bytes()).collect();
+                ("mono16", data)
+            }
+            image::DynamicImage::ImageRgba8(buf) => ("rgba8", buf.as_raw().clone()),
+            img => ("rgb8", img.to_rgb8().into_raw()),
+        };
+        let bytes_per_pixel = EncodingInfo::of(encoding).map_or(3, |info| info.bytes_per_pixel());
+        RawImage {
+            header,
+            height: img.height(),
+            width: img.width(),
+            encoding: encoding.to_string(),
+            is_bigendian: 0,
+            step: img.width() * bytes_per_pixel as u32,
+            data,
+        }
+    }
+
+    /// Copies the rows out of `data` using `step` as the source stride, dropping any
+    /// per-row padding so the result is tightly packed, top row first. Fails unless `data`
+    /// holds `height` full rows.
+    fn packed_data(&self, bytes_per_pixel: usize, origin: Origin) -> Result<Vec<u8>, PreviewError> {
+        let row_len = self.width as usize * bytes_per_pixel;
+        let stride = (self.step as usize).max(row_len);
+        let expected = stride * self.height as usize;
+        if self.data.len() < expected {
+            return Err(self.size_mismatch(expected));
+        }
+        if stride == row_len && origin == Origin::TopLeft {
+            return Ok(self.data[..expected].to_vec());
+        }
+        let rows = self
+            .data
+            .chunks(stride.max(1))
+            .take(self.height as usize)
+            .map(|row| &row[..row_len]);
+        Ok(match origin {
+            Origin::TopLeft => rows.flatten().copied().collect(),
+            Origin::BottomLeft => rows.rev().flatten().copied().collect(),
+        })
+    }
+
+    /// The error for a `data` shorter than the `expected` bytes.
+    fn size_mismatch(&self, expected: usize) -> PreviewError {
+        PreviewError::SizeMismatch {
+            expected,
+            actual: self.data.len(),
+            what: format!("{}x{} {}", self.width, self.height, self.encoding),
+        }
+    }
+
+    /// Reads the packed buffer as `S` samples, big-endian when `is_bigendian` is set and
+    /// host order otherwise.
+    fn samples<S: Sample>(&self, channels: usize, origin: Origin) -> Result<Vec<S>, PreviewError> {
+        let data = self.packed_data(channels * S::SIZE, origin)?;
+        Ok(data
+            .chunks_exact(S::SIZE)
+            .map(|b| {
+                if self.is_bigendian != 0 {
+                    S::from_be(b)
+                } else {
+                    S::from_ne(b)
+                }
+            })
+            .collect())
+    }
+
+    /// Reads the sample for `channel` of pixel (`x`, `y`) straight from `data`.
+    fn sample_at<S: Sample>(&self, x: u32, y: u32, channels: usize) -> Option<S> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let bytes_per_pixel = channels * S::SIZE;
+        let step = (self.step as usize).max(self.width as usize * bytes_per_pixel);
+        let offset = y as usize * step + x as usize * bytes_per_pixel;
+        let bytes = self.data.get(offset..offset + S::SIZE)?;
+        Some(if self.is_bigendian != 0 {
+            S::from_be(bytes)
+        } else {
+            S::from_ne(bytes)
+        })
+    }
+
+    /// Converts packed 4:2:2 data, UYVY or YUYV: each group of four bytes holds two pixels
+    /// sharing their chroma. The unpaired last pixel of an odd-width row has only its luma
+    /// and U; it takes V from the pair to its left.
+    fn yuv422_to_rgb(&self, uyvy: bool, origin: Origin) -> Result<Vec<u8>, PreviewError> {
+        let row_len = self.width as usize * 2;
+        let packed = self.packed_data(2, origin)?;
+        let mut rgb = Vec::with_capacity(self.width as usize * self.height as usize * 3);
+        for row in packed.chunks_exact(row_len) {
+            let quads = row.chunks_exact(4);
+            let last = quads.remainder();
+            let mut v = 128;
+            for quad in quads {
+                let [y0, u, y1, pair_v] = if uyvy {
+                    [quad[1], quad[0], quad[3], quad[2]]
+                } else {
+                    [quad[0], quad[1], quad[2], quad[3]]
+                };
+                v = pair_v;
+                rgb.extend(yuv_to_rgb(y0, u, v));
+                rgb.extend(yuv_to_rgb(y1, u, v));
+            }
+            if let [a, b] = *last {
+                let (y, u) = if uyvy { (b, a) } else { (a, b) };
+                rgb.extend(yuv_to_rgb(y, u, v));
+            }
+        }
+        Ok(rgb)
+    }
+
+    /// Converts semi-planar 4:2:0 data: a full-resolution Y plane followed by a plane of
+    /// interleaved U/V pairs at half resolution in both directions, both with `step`
+    /// bytes per row. Each chroma pair covers a 2x2 block; odd sizes round the chroma
+    /// plane up.
+    fn nv12_to_rgb(&self, origin: Origin) -> Result<Vec<u8>, PreviewError> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let chroma_width = width.div_ceil(2) * 2;
+        let step = (self.step as usize).max(chroma_width);
+        let expected = step * (height + height.div_ceil(2));
+        if self.data.len() < expected {
+            return Err(self.size_mismatch(expected));
+        }
+        let (luma, chroma) = self.data.split_at(step * height);
+        let mut rgb = Vec::with_capacity(width * height * 3);
+        for y in 0..height {
+            let y = match origin {
+                Origin::TopLeft => y,
+                Origin::BottomLeft => height - 1 - y,
+            };
+            let luma_row = &luma[y * step..y * step + width];
+            let chroma_row = &chroma[(y / 2) * step..];
+            for (x, &luma) in luma_row.iter().enumerate() {
+                let uv = (x / 2) * 2;
+                rgb.extend(yuv_to_rgb(luma, chroma_row[uv], chroma_row[uv + 1]));
+            }
+        }
+        Ok(rgb)
+    }
+
+    /// Converts YUV `data` in a shader, after the size checks of the CPU path. `None` leaves
+    /// the conversion to the CPU.
+    #[cfg(feature = "gpu-convert")]
+    fn gpu_rgb(&self, layout: YuvLayout, origin: Origin) -> Result<Option<Vec<u8>>, PreviewError> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let (step, expected) = match layout {
+            YuvLayout::Nv12 => {
+                let step = (self.step as usize).max(width.div_ceil(2) * 2);
+                (step, step * (height + height.div_ceil(2)))
+            }
+            // Pixel pairs share their chroma; odd widths get the CPU path's result.
+            _ if !width.is_multiple_of(2) => return Ok(None),
+            _ => {
+                let step = (self.step as usize).max(width * 2);
+                (step, step * height)
+            }
+        };
+        if self.data.len() < expected {
+            return Err(self.size_mismatch(expected));
+        }
+        Ok(gpu::yuv_to_rgb(&YuvFrame {
+            data: &self.data[..expected],
+            layout,
+            width: self.width,
+            height: self.height,
+            step: step as u32,
+            flip: origin == Origin::BottomLeft,
+        }))
+    }
+
+    /// Wraps tightly packed 8-bit `data` in an image buffer of this message's size.
+    fn buffer<P: Pixel<Subpixel = u8>>(
+        &self,
+        data: Vec<u8>,
+    ) -> Result<ImageBuffer<P, Vec<u8>>, PreviewError> {
+        let expected = self.width as usize * self.height as usize * P::CHANNEL_COUNT as usize;
+        ImageBuffer::from_raw(self.width, self.height, data)
+            .ok_or_else(|| self.size_mismatch(expected))
+    }
+}
+
+/// Maps depth samples onto the configured colormap, turbo by default. Samples that are
+/// NaN, ±Inf or 0 carry no data and are drawn black. Without a fixed `depth_range` the
+/// valid samples are auto-scaled.
+fn colorize_depth(depths: &[f32], options: &ConvertOptions) -> Vec<u8> {
+    let is_valid = |d: f32| d.is_finite() && d != 0.0;
+    let valid = || depths.iter().copied().filter(|&d| is_valid(d));
+    let (min, max) = options.depth_range.unwrap_or_else(|| {
+        if options.auto_contrast {
+            percentile_range(valid().collect()).unwrap_or((0.0, 0.0))
+        } else {
+            valid().fold((f32::MAX, f32::MIN), |(lo, hi), d| (lo.min(d), hi.max(d)))
+        }
+    });
+    let span = if max > min { max - min } else { 1.0 };
+    let colormap = options.colormap.unwrap_or_default();
+    depths
+        .iter()
+        .flat_map(|&d| {
+            if is_valid(d) {
+                colormap.map((d - min) / span)
+            } else {
+                [0, 0, 0]
+            }
+        })
+        .collect()
+}
+
+/// The codec named in a `CompressedImage::format` (already lowercased), which
+/// image_transport writes as e.g. `jpeg` or `rgb8; jpeg compressed bgr8`. `None` when no
+/// known codec is named and the data has to be sniffed.
+fn image_format(format: &str) -> Option<ImageFormat> {
+    format
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .find_map(|word| match word {
+            "jpeg" | "jpg" => Some(ImageFormat::Jpeg),
+            "png" => Some(ImageFormat::Png),
+            "webp" => Some(ImageFormat::WebP),
+            _ => None,
+        })
+}
+
+/// Reduces interleaved 16-bit samples with `channels` channels to 8 bits. All channels
+/// share one min/max range so colors keep their balance; with `auto_contrast` each
+/// channel is stretched between its own percentiles instead.
+fn scale_16bit(samples: &[u16], channels: usize, options: &ConvertOptions) -> Vec<u8> {
+    if options.take_high_byte {
+        return samples.iter().map(|&v| (v >> 8) as u8).collect();
+    }
+    let channel = |c: usize| samples.iter().skip(c).step_by(channels).map(|&v| v as f32);
+    let ranges: Vec<(f32, f32)> = if options.auto_contrast {
+        (0..channels)
+            .map(|c| percentile_range(channel(c).collect()).unwrap_or((0.0, 0.0)))
+            .collect()
+    } else {
+        let min = samples.iter().copied().min().unwrap_or(0) as f32;
+        let max = samples.iter().copied().max().unwrap_or(0) as f32;
+        vec![(min, max); channels]
+    };
+    samples
+        .iter()
+        .zip(ranges.iter().cycle())
+        .map(|(&v, &(min, max))| {
+            let scale = if max > min { 255.0 / (max - min) } else { 0.0 };
+            ((v as f32 - min) * scale).clamp(0.0, 255.0) as u8
+        })
+        .collect()
+}
+
+/// The 1st and 99th percentile of `values`, a range that ignores isolated hot or dead
+/// pixels. `None` for an empty frame.
+fn percentile_range(mut values: Vec<f32>) -> Option<(f32, f32)> {
+    if values.is_empty() {
+        return None;
+    }
+    let last = values.len() - 1;
+    let low = last / 100;
+    let high = last - last / 100;
+    let (_, &mut lo, _) = values.select_nth_unstable_by(low, f32::total_cmp);
+    let (_, &mut hi, _) = values.select_nth_unstable_by(high, f32::total_cmp);
+    Some((lo, hi))
+}
+
+/// Channel (0 = R, 1 = G, 2 = B) of each cell in a 2x2 Bayer tile, row-major, from the
+/// pattern letters in the encoding name, e.g. `rggb`.
+fn bayer_pattern(letters: &str) -> [usize; 4] {
+    let mut pattern = [1; 4];
+    for (cell, letter) in pattern.iter_mut().zip(letters.chars()) {
+        *cell = match letter {
+            'r' => 0,
+            'b' => 2,
+            _ => 1,
+        };
+    }
+    pattern
+}
+
+/// Bilinear demosaic: each missing channel is the mean of the neighbours in the 3x3
+/// window that sampled that channel.
+fn demosaic(width: usize, height: usize, mosaic: &[u8], pattern: [usize; 4]) -> Vec<u8> {
+    let channel_at = |x: usize, y: usize| pattern[(y % 2) * 2 + x % 2];
+    let mut rgb = vec![0u8; width * height * 3];
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = [0u32; 3];
+            let mut count = [0u32; 3];
+            for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                    let c = channel_at(nx, ny);
+                    sum[c] += mosaic[ny * width + nx] as u32;
+                    count[c] += 1;
+                }
+            }
+            let own = channel_at(x, y);
+            sum[own] = mosaic[y * width + x] as u32;
+            count[own] = 1;
+            for c in 0..3 {
+                rgb[(y * width + x) * 3 + c] = (sum[c] / count[c].max(1)) as u8;
+            }
+        }
+    }
+    rgb
+}
+
+/// BT.601 studio-swing YUV to RGB.
+fn yuv_to_rgb(y: u8, u: u8, v: u8) -> [u8; 3] {
+    let y = 1.164 * (y as f32 - 16.0);
+    let u = u as f32 - 128.0;
+    let v = v as f32 - 128.0;
+    [y + 1.596 * v, y - 0.392 * u - 0.813 * v, y + 2.017 * u].map(|c| c.clamp(0.0, 255.0) as u8)
+}
+
+impl PreviewImage for RawImage {
+    fn to_image(&self, options: &ConvertOptions) -> Result<image::DynamicImage, PreviewError> {
+        let info = EncodingInfo::of(&self.encoding)
+            .ok_or_else(|| PreviewError::UnsupportedEncoding(self.encoding.clone()))?;
+        if self.width == 0 || self.height == 0 {
+            return Err(PreviewError::EmptyImage {
+                width: self.width,
+                height: self.height,
+            });
+        }
+        let (bytes_per_pixel, channels) = (info.bytes_per_pixel(), info.channels());
+        // cv_bridge's generic OpenCV types carry no channel order.
+        let encoding = match self.encoding.as_str() {
+            "8UC1" => "mono8",
+            "8UC3" if options.assume_bgr => "bgr8",
+            "8UC3" => "rgb8",
+            encoding => encoding,
+        };
+        match encoding {
+            "bgr8" => {
+                let mut bgr: RgbImage =
+                    self.buffer(self.packed_data(bytes_per_pixel, options.origin)?)?;
+                bgr.pixels_mut().for_each(|p| {
+                    p.0.reverse();
+                });
+                Ok(image::DynamicImage::ImageRgb8(bgr))
+            }
+            "rgb8" => Ok(image::DynamicImage::ImageRgb8(
+                self.buffer(self.packed_data(bytes_per_pixel, options.origin)?)?,
+            )),
+            "bgra8" => {
+                let mut bgra: RgbaImage =
+                    self.buffer(self.packed_data(bytes_per_pixel, options.origin)?)?;
+                bgra.pixels_mut().for_each(|p| {
+                    p.0.swap(0, 2);
+                });
+                Ok(image::DynamicImage::ImageRgba8(bgra))
+            }
+            "rgba8" => Ok(image::DynamicImage::ImageRgba8(
+                self.buffer(self.packed_data(bytes_per_pixel, options.origin)?)?,
+            )),
+            "mono8" => Ok(image::DynamicImage::ImageLuma8(
+                self.buffer(self.packed_data(bytes_per_pixel, options.origin)?)?,
+            )),
+            "mono16" => {
+                let samples: Vec<u16> = self.samples(channels, options.origin)?;
+                let (min, max) = if options.auto_contrast {
+                    percentile_range(samples.iter().map(|&v| v as f32).collect())
+                        .unwrap_or((0.0, 0.0))
+                } else {
+                    (
+                        samples.iter().copied().min().unwrap_or(0) as f32,
+                        samples.iter().copied().max().unwrap_or(0) as f32,
+                    )
+                };
+                let scale = if max > min { 255.0 / (max - min) } else { 0.0 };
+                let levels = samples
+                    .iter()
+                    .map(|&v| ((v as f32 - min) * scale).clamp(0.0, 255.0) as u8);
+                match options.colormap {
+                    None | Some(Colormap::Grayscale) => Ok(image::DynamicImage::ImageLuma8(
+                        self.buffer(levels.collect())?,
+                    )),
+                    Some(colormap) => {
+                        let lut = colormap.lut();
+                        let data = levels.flat_map(|level| lut[level as usize]).collect();
+                        Ok(image::DynamicImage::ImageRgb8(self.buffer(data)?))
+                    }
+                }
+            }
+            "rgb16" | "bgr16" => {
+                let mut samples: Vec<u16> = self.samples(channels, options.origin)?;
+                if encoding == "bgr16" {
+                    samples.chunks_exact_mut(3).for_each(|p| p.swap(0, 2));
+                }
+                Ok(image::DynamicImage::ImageRgb8(
+                    self.buffer(scale_16bit(&samples, 3, options))?,
+                ))
+            }
+            "16UC1" => {
+                let depths: Vec<f32> = self
+                    .samples::<u16>(channels, options.origin)?
+                    .into_iter()
+                    .map(|mm| mm as f32 / 1000.0)
+                    .collect();
+                Ok(image::DynamicImage::ImageRgb8(
+                    self.buffer(colorize_depth(&depths, options))?,
+                ))
+            }
+            "32FC1" => {
+                let depths: Vec<f32> = self.samples(channels, options.origin)?;
+                Ok(image::DynamicImage::ImageRgb8(
+                    self.buffer(colorize_depth(&depths, options))?,
+                ))
+            }
+            "bayer_rggb8" | "bayer_bggr8" | "bayer_gbrg8" | "bayer_grbg8" => {
+                let mut pattern = bayer_pattern(&self.encoding[6..10]);
+                // The pattern starts at the first row in memory, which is now the last.
+                if options.origin == Origin::BottomLeft && self.height.is_multiple_of(2) {
+                    pattern.rotate_left(2);
+                }
+                let mosaic = self.packed_data(bytes_per_pixel, options.origin)?;
+                Ok(image::DynamicImage::ImageRgb8(self.buffer(demosaic(
+                    self.width as usize,
+                    self.height as usize,
+                    &mosaic,
+                    pattern,
+                ))?))
+            }
+            "yuv422" | "uyvy" | "yuv422_yuy2" | "yuyv" => {
+                // ROS `yuv422` is UYVY; `yuv422_yuy2` is YUYV.
+                let uyvy = matches!(self.encoding.as_str(), "yuv422" | "uyvy");
+                #[cfg(feature = "gpu-convert")]
+                {
+                    let layout = if uyvy {
+                        YuvLayout::Uyvy
+                    } else {
+                        YuvLayout::Yuyv
+                    };
+                    if let Some(rgb) = self.gpu_rgb(layout, options.origin)? {
+                        return Ok(image::DynamicImage::ImageRgb8(self.buffer(rgb)?));
+                    }
+                }
+                Ok(image::DynamicImage::ImageRgb8(
+                    self.buffer(self.yuv422_to_rgb(uyvy, options.origin)?)?,
+                ))
+            }
+            "nv12" => {
+                #[cfg(feature = "gpu-convert")]
+                if let Some(rgb) = self.gpu_rgb(YuvLayout::Nv12, options.origin)? {
+                    return Ok(image::DynamicImage::ImageRgb8(self.buffer(rgb)?));
+                }
+                Ok(image::DynamicImage::ImageRgb8(
+                    self.buffer(self.nv12_to_rgb(options.origin)?)?,
+                ))
+            }
+            other => Err(PreviewError::UnsupportedEncoding(other.to_string())),
+        }
+    }
+
+    fn header(&self) -> &Header {
+        &self.header
+    }
+
+    fn as_str() -> &'static str {
+        "Image"
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.data.len()
+    }
+
+    fn encoding(&self) -> Option<&str> {
+        Some(&self.encoding)
+    }
+
+    fn pixel_value(&self, x: u32, y: u32) -> Option<String> {
+        match self.encoding.as_str() {
+            "16UC1" => self
+                .sample_at::<u16>(x, y, 1)
+                .map(|mm| format!("{:.3} m", mm as f32 / 1000.0)),
+            "32FC1" => self
+                .sample_at::<f32>(x, y, 1)
+                .map(|m| format!("{:.3} m", m)),
+            "mono16" => self.sample_at::<u16>(x, y, 1).map(|v| v.to_string()),
+            _ => None,
+        }
+    }
+}
+/// Size of the `ConfigHeader` (format enum plus two f32 quantization parameters) that
+/// compressed_depth_image_transport puts in front of the PNG.
+const COMPRESSED_DEPTH_HEADER_LEN: usize = 12;
+
+impl CompressedImage {
+    /// Decodes compressed_depth_image_transport output, e.g. `"16UC1; compressedDepth png"`,
+    /// into the depth colormap. 16UC1 is stored as millimeters; 32FC1 as quantized inverse
+    /// depth, `depth = a / (value - b)` with `a`, `b` from the header and 0 meaning no data.
+    fn decode_compressed_depth(
+        &self,
+        format: &str,
+        options: &ConvertOptions,
+    ) -> Result<image::DynamicImage, PreviewError> {
+        if self.data.len() < COMPRESSED_DEPTH_HEADER_LEN {
+            return Err(PreviewError::SizeMismatch {
+                expected: COMPRESSED_DEPTH_HEADER_LEN,
+                actual: self.data.len(),
+                what: "the compressedDepth header".to_string(),
+            });
+        }
+        let (header, png) = self.data.split_at(COMPRESSED_DEPTH_HEADER_LEN);
+        let quant =
+            |offset: usize| f32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+        let (quant_a, quant_b) = (quant(4), quant(8));
+        let raw = ImageReader::new(Cursor::new(png))
+            .with_guessed_format()?
+            .decode()?
+            .into_luma16();
+        let depths: Vec<f32> = if format.starts_with("32fc1") {
+            raw.iter()
+                .map(|&v| {
+                    if v == 0 {
+                        f32::NAN
+                    } else {
+                        quant_a / (v as f32 - quant_b)
+                    }
+                })
+                .collect()
+        } else {
+            raw.iter().map(|&mm| mm as f32 / 1000.0).collect()
+        };
+        let rgb = RgbImage::from_raw(raw.width(), raw.height(), colorize_depth(&depths, options))
+            .expect("colormap output matches the decoded size");
+        Ok(image::DynamicImage::ImageRgb8(rgb))
+    }
+}
+
+impl PreviewImage for CompressedImage {
+    fn to_image(&self, options: &ConvertOptions) -> Result<image::DynamicImage, PreviewError> {
+        let format = self.format.to_ascii_lowercase();
+        if VIDEO_FORMATS.iter().any(|codec| format.contains(codec)) {
+            return Err(PreviewError::VideoFormat(self.format.clone()));
+        }
+        if format.contains("compresseddepth") {
+            return self.decode_compressed_depth(&format, options);
+        }
+        let mut reader = ImageReader::new(Cursor::new(self.data.as_slice()));
+        match image_format(&format) {
+            Some(image_format) => reader.set_format(image_format),
+            None => reader = reader.with_guessed_format()?,
+        }
+        let img = reader.decode()?;
+        if img.width() == 0 || img.height() == 0 {
+            return Err(PreviewError::EmptyImage {
+                width: img.width(),
+                height: img.height(),
+            });
+        }
+        Ok(img)
+    }
+
+    fn header(&self) -> &Header {
+        &self.header
+    }
+
+    fn as_str() -> &'static str {
+        "CompressedImage"
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.data.len()
+    }
+
+    fn encoding(&self) -> Option<&str> {
+        Some(&self.format)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+
+    fn raw(encoding: &str, width: u32, height: u32, data: Vec<u8>) -> RawImage {
+        RawImage {
+            header: Header {
+                sec: 0,
+                nanosec: 0,
+                frame_id: String::new(),
+            },
+            height,
+            width,
+            encoding: encoding.to_string(),
+            is_bigendian: 0,
+            step: data.len() as u32 / height.max(1),
+            data,
+        }
+    }
+
+    fn convert(img: &RawImage) -> image::DynamicImage {
+        img.to_image(&ConvertOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn bgr8_red_becomes_rgb_red() {
+        let img = convert(&raw("bgr8", 2, 1, vec![0, 0, 255, 255, 0, 0]));
+        assert_eq!(img.color(), ColorType::Rgb8);
+        assert_eq!(img.dimensions(), (2, 1));
+        let rgb = img.to_rgb8();
+        assert_eq!(rgb.get_pixel(0, 0).0, [255, 0, 0]);
+        assert_eq!(rgb.get_pixel(1, 0).0, [0, 0, 255]);
+    }
+
+    #[test]
+    fn rgb8_is_passed_through() {
+        let img = convert(&raw("rgb8", 1, 2, vec![1, 2, 3, 4, 5, 6]));
+        assert_eq!(img.color(), ColorType::Rgb8);
+        assert_eq!(img.to_rgb8().get_pixel(0, 1).0, [4, 5, 6]);
+    }
+
+    #[test]
+    fn bgra8_swaps_color_and_keeps_alpha() {
+        let img = convert(&raw("bgra8", 1, 1, vec![10, 20, 30, 40]));
+        assert_eq!(img.color(), ColorType::Rgba8);
+        assert_eq!(img.to_rgba8().get_pixel(0, 0).0, [30, 20, 10, 40]);
+    }
+
+    #[test]
+    fn rgba8_is_passed_through() {
+        let img = convert(&raw("rgba8", 1, 1, vec![10, 20, 30, 40]));
+        assert_eq!(img.to_rgba8().get_pixel(0, 0).0, [10, 20, 30, 40]);
+    }
+
+    #[test]
+    fn generic_8uc3_channel_order_follows_assume_bgr() {
+        let msg = raw("8UC3", 1, 1, vec![1, 2, 3]);
+        assert_eq!(convert(&msg).to_rgb8().get_pixel(0, 0).0, [1, 2, 3]);
+        let options = ConvertOptions {
+            assume_bgr: true,
+            ..Default::default()
+        };
+        let img = msg.to_image(&options).unwrap();
+        assert_eq!(img.to_rgb8().get_pixel(0, 0).0, [3, 2, 1]);
+    }
+
+    #[test]
+    fn mono8_is_luma() {
+        let img = convert(&raw("mono8", 3, 1, vec![0, 128, 255]));
+        assert_eq!(img.color(), ColorType::L8);
+        assert_eq!(img.to_luma8().into_raw(), vec![0, 128, 255]);
+    }
+
+    #[test]
+    fn mono16_is_stretched_to_full_range() {
+        let data = [1000u16, 2000, 3000]
+            .iter()
+            .flat_map(|v| v.to_ne_bytes())
+            .collect();
+        let img = convert(&raw("mono16", 3, 1, data));
+        assert_eq!(img.color(), ColorType::L8);
+        assert_eq!(img.to_luma8().into_raw(), vec![0, 127, 255]);
+    }
+
+    #[test]
+    fn mono16_honors_big_endian() {
+        let data = [0u16, 256].iter().flat_map(|v| v.to_be_bytes()).collect();
+        let mut msg = raw("mono16", 2, 1, data);
+        msg.is_bigendian = 1;
+        assert_eq!(convert(&msg).to_luma8().into_raw(), vec![0, 255]);
+    }
+
+    #[test]
+    fn bgr16_is_reordered_and_scaled() {
+        let data = [0u16, 1000, 4000]
+            .iter()
+            .flat_map(|v| v.to_ne_bytes())
+            .collect();
+        let img = convert(&raw("bgr16", 1, 1, data));
+        assert_eq!(img.color(), ColorType::Rgb8);
+        assert_eq!(img.to_rgb8().get_pixel(0, 0).0, [255, 63, 0]);
+    }
+
+    #[test]
+    fn rgb16_high_byte_skips_scaling() {
+        let data = [0x1234u16, 0xff00, 0x00ff]
+            .iter()
+            .flat_map(|v| v.to_ne_bytes())
+            .collect();
+        let options = ConvertOptions {
+            take_high_byte: true,
+            ..Default::default()
+        };
+        let img = raw("rgb16", 1, 1, data).to_image(&options).unwrap();
+        assert_eq!(img.to_rgb8().get_pixel(0, 0).0, [0x12, 0xff, 0x00]);
+    }
+
+    #[test]
+    fn mono16_uses_the_chosen_colormap() {
+        let data = [0u16, 500].iter().flat_map(|v| v.to_ne_bytes()).collect();
+        let options = ConvertOptions {
+            colormap: Some(Colormap::Jet),
+            ..Default::default()
+        };
+        let img = raw("mono16", 2, 1, data).to_image(&options).unwrap();
+        assert_eq!(img.color(), ColorType::Rgb8);
+        let rgb = img.to_rgb8();
+        assert_eq!(rgb.get_pixel(0, 0).0, Colormap::Jet.lut()[0]);
+        assert_eq!(rgb.get_pixel(1, 0).0, Colormap::Jet.lut()[255]);
+    }
+
+    #[test]
+    fn depth_16uc1_draws_missing_samples_black() {
+        let data = [0u16, 1000, 2000]
+            .iter()
+            .flat_map(|v| v.to_ne_bytes())
+            .collect();
+        let img = convert(&raw("16UC1", 3, 1, data));
+        assert_eq!(img.color(), ColorType::Rgb8);
+        let rgb = img.to_rgb8();
+        assert_eq!(rgb.get_pixel(0, 0).0, [0, 0, 0]);
+        assert_ne!(rgb.get_pixel(1, 0).0, rgb.get_pixel(2, 0).0);
+    }
+
+    #[test]
+    fn depth_32fc1_is_colorized() {
+        let data = [0.5f32, 1.0, 1.5, 2.0]
+            .iter()
+            .flat_map(|v| v.to_ne_bytes())
+            .collect();
+        let img = convert(&raw("32FC1", 2, 2, data));
+        assert_eq!(img.color(), ColorType::Rgb8);
+        assert_eq!(img.dimensions(), (2, 2));
+    }
+
+    #[test]
+    fn depth_32fc1_ignores_non_finite_samples() {
+        let depths = [f32::NAN, f32::INFINITY, 1.0, f32::NEG_INFINITY, 3.0, 2.0];
+        let data = depths.iter().flat_map(|v| v.to_ne_bytes()).collect();
+        let rgb = convert(&raw("32FC1", 6, 1, data)).to_rgb8();
+        for x in [0, 1, 3] {
+            assert_eq!(rgb.get_pixel(x, 0).0, [0, 0, 0]);
+        }
+        // The finite samples still span the whole colormap.
+        assert_eq!(rgb.get_pixel(2, 0).0, Colormap::Turbo.map(0.0));
+        assert_eq!(rgb.get_pixel(4, 0).0, Colormap::Turbo.map(1.0));
+        assert_eq!(rgb.get_pixel(5, 0).0, Colormap::Turbo.map(0.5));
+    }
+
+    #[test]
+    fn bayer_rggb8_keeps_sampled_red() {
+        // Only the R cells of each 2x2 tile are lit.
+        let data = vec![
+            255, 0, 255, 0, //
+            0, 0, 0, 0, //
+            255, 0, 255, 0, //
+            0, 0, 0, 0,
+        ];
+        let img = convert(&raw("bayer_rggb8", 4, 4, data));
+        assert_eq!(img.color(), ColorType::Rgb8);
+        assert_eq!(img.to_rgb8().get_pixel(2, 2).0, [255, 0, 0]);
+    }
+
+    #[test]
+    fn nv12_odd_size_uses_rounded_up_chroma_plane() {
+        // 3x3 Y plane with step 4, then two rows of U/V pairs. The right column and the
+        // bottom row share the last chroma samples.
+        let mut data = vec![235; 12];
+        data.extend([128, 128, 128, 128, 128, 128, 255, 128]);
+        let mut msg = raw("nv12", 3, 3, data);
+        msg.step = 4;
+        let rgb = convert(&msg).to_rgb8();
+        assert!(rgb.get_pixel(0, 0).0.iter().all(|&c| c >= 254));
+        let [_, _, blue] = rgb.get_pixel(2, 2).0;
+        assert_eq!(blue, 255);
+        assert_eq!(rgb.get_pixel(0, 2).0, rgb.get_pixel(0, 0).0);
+    }
+
+    #[test]
+    fn bottom_left_origin_reverses_padded_rows() {
+        // Two rows of one rgb8 pixel, each padded to 4 bytes, stored bottom row first.
+        let mut msg = raw("rgb8", 1, 2, vec![1, 2, 3, 0, 4, 5, 6, 0]);
+        msg.step = 4;
+        let options = ConvertOptions {
+            origin: Origin::BottomLeft,
+            ..Default::default()
+        };
+        let rgb = msg.to_image(&options).unwrap().to_rgb8();
+        assert_eq!(rgb.get_pixel(0, 0).0, [4, 5, 6]);
+        assert_eq!(rgb.get_pixel(0, 1).0, [1, 2, 3]);
+    }
+
+    #[test]
+    fn bottom_left_bayer_keeps_the_pattern_on_its_rows() {
+        // The red sample is the first byte in memory, which is the bottom-left pixel.
+        let msg = raw("bayer_rggb8", 2, 2, vec![200, 0, 0, 0]);
+        let options = ConvertOptions {
+            origin: Origin::BottomLeft,
+            ..Default::default()
+        };
+        let rgb = msg.to_image(&options).unwrap().to_rgb8();
+        assert_eq!(rgb.get_pixel(0, 1).0[0], 200);
+        assert_eq!(rgb.get_pixel(0, 0).0[0], 200);
+        assert_eq!(rgb.get_pixel(1, 0).0, [200, 0, 0]);
+    }
+
+    #[test]
+    fn nv12_short_buffer_is_an_error() {
+        let msg = raw("nv12", 2, 2, vec![0; 5]);
+        assert!(matches!(
+            msg.to_image(&ConvertOptions::default()),
+            Err(PreviewError::SizeMismatch { expected: 6, .. })
+        ));
+    }
+
+    #[test]
+    fn zero_sized_image_is_an_error() {
+        for (width, height) in [(0, 480), (640, 0), (0, 0)] {
+            let msg = raw("mono8", width, height, vec![]);
+            assert!(matches!(
+                msg.to_image(&ConvertOptions::default()),
+                Err(PreviewError::EmptyImage { .. })
+            ));
+            let config = crate::config::PreviewConfig::default();
+            let (w, h) = crate::preview::window_size_for(&config, width, height);
+            assert!(w > 0 && h > 0);
+        }
+    }
+
+    #[test]
+    fn extreme_aspect_ratio_gets_a_window() {
+        let config = crate::config::PreviewConfig::default();
+        for (width, height) in [(1_000_000, 1), (1, 4_000_000), (u32::MAX, u32::MAX)] {
+            let (w, h) = crate::preview::window_size_for(&config, width, height);
+            assert!(w > 0 && h > 0, "{}x{} gave {}x{}", width, height, w, h);
+        }
+    }
+
+    #[test]
+    fn uyvy_white_is_white() {
+        let img = convert(&raw("uyvy", 2, 1, vec![128, 235, 128, 235]));
+        // Studio-swing Y=235 lands within a rounding step of full white.
+        assert!(img.to_rgb8().get_pixel(1, 0).0.iter().all(|&c| c >= 254));
+    }
+
+    #[test]
+    fn odd_width_yuyv_keeps_every_row_aligned() {
+        // Two 3-pixel rows: a white pair and a black unpaired pixel, then the reverse.
+        let data = vec![235, 128, 235, 128, 16, 128, 16, 128, 16, 128, 235, 128];
+        let img = convert(&raw("yuyv", 3, 2, data)).to_rgb8();
+        let white: Vec<bool> = img.pixels().map(|p| p[1] > 250).collect();
+        assert_eq!(white, [true, true, false, false, false, true]);
+    }
+
+    #[test]
+    fn yuyv_black_is_black() {
+        let img = convert(&raw("yuyv", 2, 1, vec![16, 128, 16, 128]));
+        assert_eq!(img.to_rgb8().get_pixel(0, 0).0, [0, 0, 0]);
+    }
+
+    #[test]
+    fn row_padding_is_skipped() {
+        let mut msg = raw("mono8", 2, 2, vec![1, 2, 99, 99, 3, 4, 99, 99]);
+        msg.step = 4;
+        assert_eq!(convert(&msg).to_luma8().into_raw(), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn short_buffer_is_an_error() {
+        let msg = raw("bgr8", 2, 2, vec![0; 5]);
+        assert!(matches!(
+            msg.to_image(&ConvertOptions::default()),
+            Err(PreviewError::SizeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn short_buffer_names_the_expected_size() {
+        let mut msg = raw("bgr8", 640, 480, vec![0; 1000]);
+        msg.step = 1920;
+        let err = msg.to_image(&ConvertOptions::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected 921600 bytes for 640x480 bgr8, got 1000"
+        );
+    }
+
+    #[test]
+    fn from_image_round_trips() {
+        let bgr = raw("bgr8", 2, 1, vec![0, 0, 255, 255, 0, 0]);
+        let img = convert(&bgr);
+        let msg = RawImage::from_image(&img, bgr.header.clone());
+        assert_eq!((msg.encoding.as_str(), msg.step), ("rgb8", 6));
+        assert_eq!(convert(&msg).to_rgb8(), img.to_rgb8());
+    }
+
+    #[test]
+    fn every_encoding_converts_to_its_color_type() {
+        for info in ENCODINGS {
+            // nv12 carries a half-height chroma plane after the luma rows.
+            let rows = if info.name() == "nv12" { 3 } else { 2 };
+            let row_len = 2 * info.bytes_per_pixel();
+            let mut msg = raw(info.name(), 2, 2, vec![0; rows * row_len]);
+            msg.step = row_len as u32;
+            let img = msg.to_image(&ConvertOptions::default()).unwrap();
+            assert_eq!(img.color(), info.color_type(), "{}", info.name());
+            assert_eq!(img.dimensions(), (2, 2), "{}", info.name());
+            assert_eq!(EncodingInfo::of(info.name()), Some(*info));
+        }
+        assert_eq!(supported_encodings().count(), ENCODINGS.len());
+    }
+
+    #[test]
+    fn unknown_encoding_is_an_error() {
+        let msg = raw("nv42", 1, 1, vec![0]);
+        assert!(matches!(
+            msg.to_image(&ConvertOptions::default()),
+            Err(PreviewError::UnsupportedEncoding(_))
+        ));
+    }
+
+    #[test]
+    fn compressed_png_is_decoded() {
+        let mut png = Vec::new();
+        image::DynamicImage::ImageRgb8(RgbImage::from_pixel(3, 2, image::Rgb([1, 2, 3])))
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let msg = CompressedImage {
+            header: raw("rgb8", 1, 1, vec![]).header,
+            format: "png".to_string(),
+            data: png,
+        };
+        let img = msg.to_image(&ConvertOptions::default()).unwrap();
+        assert_eq!(img.dimensions(), (3, 2));
+        assert_eq!(img.to_rgb8().get_pixel(2, 1).0, [1, 2, 3]);
+    }
+
+    #[test]
+    fn compressed_format_names_the_codec() {
+        assert_eq!(image_format("jpeg"), Some(ImageFormat::Jpeg));
+        assert_eq!(
+            image_format("rgb8; jpeg compressed bgr8"),
+            Some(ImageFormat::Jpeg)
+        );
+        assert_eq!(
+            image_format("bgr8; png compressed "),
+            Some(ImageFormat::Png)
+        );
+        assert_eq!(image_format("webp"), Some(ImageFormat::WebP));
+        assert_eq!(image_format(""), None);
+        assert_eq!(image_format("rgb8"), None);
+    }
+
+    #[test]
+    fn compressed_video_format_is_an_error() {
+        let msg = CompressedImage {
+            header: raw("rgb8", 1, 1, vec![]).header,
+            format: "h264".to_string(),
+            data: vec![0; 16],
+        };
+        assert!(matches!(
+            msg.to_image(&ConvertOptions::default()),
+            Err(PreviewError::VideoFormat(_))
+        ));
+    }
+
+    #[cfg(feature = "gpu-convert")]
+    #[test]
+    fn gpu_yuv_matches_the_cpu() {
+        // Odd sizes and row padding, so the tail quad and the strides are covered.
+        let (width, height, step) = (38, 7u32, 80);
+        let data: Vec<u8> = (0..step * (height + height.div_ceil(2)))
+            .map(|i| (i * 37 % 251) as u8)
+            .collect();
+        let mut image = raw("yuv422", width, height, data);
+        image.step = step;
+        let cases = [
+            (YuvLayout::Uyvy, image.yuv422_to_rgb(true, Origin::TopLeft)),
+            (YuvLayout::Yuyv, image.yuv422_to_rgb(false, Origin::TopLeft)),
+            (YuvLayout::Nv12, image.nv12_to_rgb(Origin::TopLeft)),
+            (YuvLayout::Nv12, image.nv12_to_rgb(Origin::BottomLeft)),
+        ];
+        for (i, (layout, cpu)) in cases.into_iter().enumerate() {
+            let origin = if i == 3 {
+                Origin::BottomLeft
+            } else {
+                Origin::TopLeft
+            };
+            let Some(gpu) = image.gpu_rgb(layout, origin).unwrap() else {
+                eprintln!("no GPU adapter, skipping");
+                return;
+            };
+            let cpu = cpu.unwrap();
+            assert_eq!(gpu.len(), cpu.len(), "{:?}", layout);
+            // The shader may round the last bit differently.
+            let off = gpu
+                .iter()
+                .zip(&cpu)
+                .filter(|(g, c)| g.abs_diff(**c) > 1)
+                .count();
+            assert_eq!(off, 0, "{:?}", layout);
+        }
+    }
+
+    /// Per-frame time of the CPU and GPU conversion of a 4K frame in each YUV layout,
+    /// including the GPU upload and readback. `cargo test --release --features
+    /// gpu-convert gpu_yuv_timing -- --ignored --nocapture` prints them.
+    #[cfg(feature = "gpu-convert")]
+    #[test]
+    #[ignore = "benchmark, run in release with --nocapture"]
+    fn gpu_yuv_timing() {
+        use std::time::Instant;
+        const FRAMES: u32 = 20;
+        let (width, height) = (3840, 2160u32);
+        let data: Vec<u8> = (0..width * height * 2)
+            .map(|i| (i * 37 % 251) as u8)
+            .collect();
+        let yuv422 = raw("yuv422", width, height, data.clone());
+        let mut nv12 = raw("nv12", width, height, data);
+        nv12.step = width;
+        let per_frame = |convert: &dyn Fn() -> Option<Vec<u8>>| {
+            convert()?;
+            let start = Instant::now();
+            for _ in 0..FRAMES {
+                convert()?;
+            }
+            Some(start.elapsed() / FRAMES)
+        };
+        for (layout, image) in [(YuvLayout::Uyvy, &yuv422), (YuvLayout::Nv12, &nv12)] {
+            let cpu = per_frame(&|| match layout {
+                YuvLayout::Nv12 => image.nv12_to_rgb(Origin::TopLeft).ok(),
+                _ => image.yuv422_to_rgb(true, Origin::TopLeft).ok(),
+            });
+            let gpu = per_frame(&|| image.gpu_rgb(layout, Origin::TopLeft).unwrap());
+            println!(
+                "{:?} 3840x2160: cpu {:?}, gpu {:?}",
+                layout,
+                cpu.unwrap(),
+                gpu
+            );
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use crate::config::PreviewConfig;
+use crate::convert::{is_monochrome, PreviewError, PreviewImage};
+use crate::decode::Decoder;
+use crate::msg::{CompressedImage, RawImage};
+use crate::preview::{
+    crop_and_orient, received, save_frame, scale_to_window, subscriber, track_inner_size,
+    window_closed, window_size_for,
+};
+use crate::ros::ImageType;
+use crate::tone::ToneLut;
+use async_io::Timer;
+use futures::future::{self, join_all, Either, LocalBoxFuture};
+use futures::{Future, FutureExt, StreamExt};
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+use show_image::event::{VirtualKeyCode, WindowEvent};
+use show_image::glam::UVec2;
+use show_image::{create_window, WindowOptions};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How often the active topic is checked for a new frame without `max_fps`.
+const CYCLE_REFRESH: Duration = Duration::from_millis(30);
+
+/// The newest received message of topic `index`, converted on the decoder thread once the
+/// topic is shown.
+struct Pending {
+    index: usize,
+    /// Whether the message is mono or depth, which the gain does not apply to.
+    monochrome: bool,
+    convert: Box<dyn Fn() -> Result<image::DynamicImage, PreviewError> + Send>,
+}
+
+/// Per topic, the newest message not converted yet and the last frame shown.
+struct Slots {
+    pending: Vec<Option<Pending>>,
+    frames: Vec<Option<image::DynamicImage>>,
+}
+
+/// Shows one topic of `config` at a time in a single window; the left and right arrow keys
+/// switch between them. Every topic stays subscribed and keeps only its newest message, so
+/// only the active topic is converted and a switch shows its latest frame right away.
+pub(crate) fn cycle_topics(
+    node: Rc<RefCell<ros2_client::Node>>,
+    config: Rc<PreviewConfig>,
+) -> impl Future<Output = ()> {
+    let topic_names: Vec<String> = config.topics.iter().map(|(t, _)| t.clone()).collect();
+    let options = WindowOptions {
+        preserve_aspect_ratio: true,
+        start_hidden: true,
+        fullscreen: config.fullscreen,
+        ..Default::default()
+    };
+    let window = create_window(cycle_title(&topic_names, 0), options).unwrap();
+
+    let active = Arc::new(AtomicUsize::new(0));
+    let latest_frame: Arc<Mutex<Option<image::DynamicImage>>> = Arc::new(Mutex::new(None));
+    {
+        let active = active.clone();
+        let latest_frame = latest_frame.clone();
+        let count = topic_names.len();
+        let save_name = topic_names.clone();
+        window
+            .add_event_handler(move |_window, event, _control_flow| {
+                let WindowEvent::KeyboardInput(event) = event else {
+                    return;
+                };
+                if !event.input.state.is_pressed() {
+                    return;
+                }
+                let current = active.load(Ordering::Relaxed);
+                match event.input.key_code {
+                    Some(VirtualKeyCode::Right) => {
+                        active.store((current + 1) % count, Ordering::Relaxed);
+                    }
+                    Some(VirtualKeyCode::Left) => {
+                        active.store((current + count - 1) % count, Ordering::Relaxed);
+                    }
+                    Some(VirtualKeyCode::S) => {
+                        if let Some(frame) = latest_frame.lock().unwrap().as_ref() {
+                            save_frame(&save_name[current], frame);
+                        }
+                    }
+                    _ => {}
+                }
+            })
+            .unwrap();
+    }
+
+    let closed = window_closed(&window, "cycle");
+    let inner_size = track_inner_size(&window);
+    let refresh = config
+        .max_fps
+        .map_or(CYCLE_REFRESH, |fps| Duration::from_secs_f64(1.0 / fps));
+    let slots = Rc::new(RefCell::new(Slots {
+        pending: topic_names.iter().map(|_| None).collect(),
+        frames: vec![None; topic_names.len()],
+    }));
+    let subscribers: Vec<LocalBoxFuture<()>> = config
+        .topics
+        .iter()
+        .enumerate()
+        .map(|(index, (topic_name, image_type))| {
+            let slots = slots.clone();
+            let config = config.clone();
+            match image_type {
+                ImageType::Raw => {
+                    cycle_slot::<RawImage>(node.clone(), topic_name, index, slots, config)
+                        .boxed_local()
+                }
+                ImageType::Compressed => {
+                    cycle_slot::<CompressedImage>(node.clone(), topic_name, index, slots, config)
+                        .boxed_local()
+                }
+            }
+        })
+        .collect();
+
+    let mut decoder = Decoder::spawn_with("cycle", |pending: &Pending| (pending.convert)());
+    let render = async move {
+        let tone_lut = ToneLut::new(config.brightness, config.gamma, config.gain);
+        let mut warned_roi = vec![false; topic_names.len()];
+        let mut shown = 0;
+        loop {
+            let index = match future::select(Timer::after(refresh), decoder.next()).await {
+                Either::Left(_) => {
+                    let index = active.load(Ordering::Relaxed);
+                    if let Some(pending) = slots.borrow_mut().pending[index].take() {
+                        decoder.push(pending);
+                    }
+                    if index == shown {
+                        continue;
+                    }
+                    // Show the last frame of the new topic until its next one is decoded.
+                    shown = index;
+                    let title = cycle_title(&topic_names, index);
+                    window.run_function(move |w| w.set_title(title));
+                    index
+                }
+                Either::Right((Some((pending, result)), _)) => {
+                    let index = pending.index;
+                    let topic_name = &topic_names[index];
+                    let img = match result {
+                        Ok(img) => img,
+                        Err(e) => {
+                            warn!("{}: skipping frame: {}", topic_name, e);
+                            continue;
+                        }
+                    };
+                    let Some((mut img, _crop)) =
+                        crop_and_orient(img, &config, topic_name, &mut warned_roi[index])
+                    else {
+                        continue;
+                    };
+                    if let Some(lut) = &tone_lut {
+                        img = lut.apply(img, pending.monochrome);
+                    }
+                    slots.borrow_mut().frames[index] = Some(img);
+                    if index != shown {
+                        continue;
+                    }
+                    index
+                }
+                Either::Right((None, _)) => break,
+            };
+            let Some(img) = slots.borrow().frames[index].clone() else {
+                continue;
+            };
+            *latest_frame.lock().unwrap() = Some(img.clone());
+            let (window_w, window_h) = window_size_for(&config, img.width(), img.height());
+            let img = match config.filter {
+                Some(filter) => {
+                    let size = inner_size.lock().unwrap().unwrap_or((window_w, window_h));
+                    scale_to_window(img, filter, size)
+                }
+                None => img,
+            };
+            window.run_function(move |mut w| {
+                if w.image_info().is_none() {
+                    w.set_inner_size(UVec2::new(window_w, window_h));
+                    w.set_visible(true);
+                }
+            });
+            if let Err(e) = window.set_image(&topic_names[index], img) {
+                info!("cycle: stopping preview: {}", e);
+                break;
+            }
+        }
+    };
+    let render = future::select(render.boxed_local(), join_all(subscribers));
+    future::select(render, closed.boxed_local()).map(|_| ())
+}
+
+/// `"/camera/image (2/4)"`.
+fn cycle_title(topic_names: &[String], index: usize) -> String {
+    format!(
+        "{} ({}/{})",
+        topic_names[index],
+        index + 1,
+        topic_names.len()
+    )
+}
+
+/// Keeps slot `index` holding the newest message of `topic_name`, unconverted.
+fn cycle_slot<T: DeserializeOwned + PreviewImage + Send + 'static>(
+    node: Rc<RefCell<ros2_client::Node>>,
+    topic_name: &str,
+    index: usize,
+    slots: Rc<RefCell<Slots>>,
+    config: Rc<PreviewConfig>,
+) -> impl Future<Output = ()> {
+    let subscribe = subscriber::<T>(node, topic_name, config.clone());
+    let mut messages = received(subscribe, topic_name, config.clone());
+    async move {
+        while let Some(msg) = messages.next().await {
+            let options = config.convert.clone();
+            slots.borrow_mut().pending[index] = Some(Pending {
+                index,
+                monochrome: is_monochrome(msg.encoding()),
+                convert: Box::new(move || msg.to_image(&options)),
+            });
+        }
+    }
+}
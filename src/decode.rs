@@ -0,0 +1,124 @@
+use crate::convert::{ConvertOptions, PreviewError, PreviewImage};
+use futures::channel::mpsc;
+use futures::future::{self, Either};
+use futures::stream::{self, LocalBoxStream, Next};
+use futures::{SinkExt, StreamExt};
+use std::sync::{Arc, Condvar, Mutex};
+
+/// A message together with the result of converting it.
+pub(crate) type Decoded<T> = (T, Result<image::DynamicImage, PreviewError>);
+
+/// Runs [`PreviewImage::to_image`] on a worker thread, so decoding a large frame does not
+/// keep the async task from draining the DDS reader. Holds at most one pending message:
+/// a newer one replaces it, which drops frames the decoder cannot keep up with instead
+/// of queueing them.
+pub(crate) struct Decoder<T> {
+    pending: Arc<Pending<T>>,
+    decoded: mpsc::Receiver<Decoded<T>>,
+}
+
+struct Pending<T> {
+    slot: Mutex<Slot<T>>,
+    ready: Condvar,
+}
+
+struct Slot<T> {
+    msg: Option<T>,
+    closed: bool,
+}
+
+impl<T: PreviewImage + Send + 'static> Decoder<T> {
+    pub(crate) fn spawn(topic_name: &str, options: ConvertOptions) -> Self {
+        Decoder::spawn_with(topic_name, move |msg: &T| msg.to_image(&options))
+    }
+}
+
+impl<T: Send + 'static> Decoder<T> {
+    /// A decoder that runs `convert` instead of [`PreviewImage::to_image`], for messages
+    /// of several types behind one window.
+    pub(crate) fn spawn_with(
+        topic_name: &str,
+        convert: impl Fn(&T) -> Result<image::DynamicImage, PreviewError> + Send + 'static,
+    ) -> Self {
+        let pending = Arc::new(Pending::<T> {
+            slot: Mutex::new(Slot {
+                msg: None,
+                closed: false,
+            }),
+            ready: Condvar::new(),
+        });
+        // A zero buffer still lets the worker hand over one frame while the next decodes.
+        let (mut sender, decoded) = mpsc::channel(0);
+        let worker = pending.clone();
+        std::thread::Builder::new()
+            .name(format!("decode {}", topic_name))
+            .spawn(move || {
+                while let Some(msg) = worker.take() {
+                    let img = convert(&msg);
+                    if futures::executor::block_on(sender.send((msg, img))).is_err() {
+                        break;
+                    }
+                }
+            })
+            .expect("failed to spawn the decode thread");
+        Decoder { pending, decoded }
+    }
+
+    /// Queues `msg` for decoding, replacing a message the worker has not picked up yet.
+    pub(crate) fn push(&self, msg: T) {
+        self.pending.slot.lock().unwrap().msg = Some(msg);
+        self.pending.ready.notify_one();
+    }
+
+    /// The next decoded message; `None` once the worker has stopped.
+    pub(crate) fn next(&mut self) -> Next<'_, mpsc::Receiver<Decoded<T>>> {
+        self.decoded.next()
+    }
+}
+
+impl<T> Pending<T> {
+    /// Blocks until a message is queued, or returns `None` once the decoder is dropped.
+    fn take(&self) -> Option<T> {
+        let mut slot = self.slot.lock().unwrap();
+        loop {
+            if slot.closed {
+                return None;
+            }
+            if let Some(msg) = slot.msg.take() {
+                return Some(msg);
+            }
+            slot = self.ready.wait(slot).unwrap();
+        }
+    }
+}
+
+impl<T> Drop for Decoder<T> {
+    fn drop(&mut self) {
+        self.pending.slot.lock().unwrap().closed = true;
+        self.pending.ready.notify_one();
+    }
+}
+
+/// Converts `messages` on a [`Decoder`] thread, yielding each message that got its turn
+/// with the result. Messages arriving while a frame decodes replace each other, so a slow
+/// decode drops frames instead of holding up the DDS reader. Ends with `messages`.
+pub(crate) fn decoded<T: PreviewImage + Send + 'static>(
+    messages: LocalBoxStream<'static, T>,
+    topic_name: &str,
+    options: ConvertOptions,
+) -> LocalBoxStream<'static, Decoded<T>> {
+    let decoder = Decoder::spawn(topic_name, options);
+    stream::unfold(
+        (messages, decoder),
+        |(mut messages, mut decoder)| async move {
+            loop {
+                match future::select(messages.next(), decoder.next()).await {
+                    Either::Left((Some(msg), _)) => decoder.push(msg),
+                    Either::Left((None, _)) => return None,
+                    Either::Right((decoded, _)) => return Some((decoded?, (messages, decoder))),
+                }
+            }
+        },
+    )
+    .boxed_local()
+}
@@ -0,0 +1,281 @@
+use futures::executor::block_on;
+use log::{info, warn};
+use std::sync::{mpsc, OnceLock};
+use wgpu::util::DeviceExt;
+
+/// Invocations per workgroup; each converts four pixels.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Most workgroups wgpu dispatches along one dimension.
+const MAX_WORKGROUPS: u32 = 65535;
+
+/// Converts 8-bit YUV to tightly packed RGB, four pixels per invocation so each writes
+/// whole words. BT.601 studio swing like the CPU path in `convert`; `flip` reads the rows
+/// bottom-up.
+const SHADER: &str = r#"
+struct Params {
+    width: u32,
+    height: u32,
+    step: u32,
+    format: u32,
+    flip: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> src: array<u32>;
+@group(0) @binding(2) var<storage, read_write> dst: array<u32>;
+
+fn byte_at(i: u32) -> f32 {
+    return f32((src[i >> 2u] >> ((i & 3u) * 8u)) & 0xffu);
+}
+
+fn rgb(x: u32, y: u32) -> vec3<u32> {
+    var row = y;
+    if params.flip != 0u {
+        row = params.height - 1u - y;
+    }
+    var luma: f32;
+    var u: f32;
+    var v: f32;
+    if params.format == 2u {
+        luma = byte_at(row * params.step + x);
+        let chroma = params.step * params.height + (row / 2u) * params.step + (x / 2u) * 2u;
+        u = byte_at(chroma);
+        v = byte_at(chroma + 1u);
+    } else {
+        let quad = row * params.step + (x / 2u) * 4u;
+        let odd = (x & 1u) * 2u;
+        if params.format == 0u {
+            u = byte_at(quad);
+            luma = byte_at(quad + 1u + odd);
+            v = byte_at(quad + 2u);
+        } else {
+            luma = byte_at(quad + odd);
+            u = byte_at(quad + 1u);
+            v = byte_at(quad + 3u);
+        }
+    }
+    let l = 1.164 * (luma - 16.0);
+    let cu = u - 128.0;
+    let cv = v - 128.0;
+    let c = vec3<f32>(l + 1.596 * cv, l - 0.392 * cu - 0.813 * cv, l + 2.017 * cu);
+    return vec3<u32>(clamp(c, vec3<f32>(0.0), vec3<f32>(255.0)));
+}
+
+@compute @workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) groups: vec3<u32>,
+) {
+    let quad = id.y * groups.x * 64u + id.x;
+    let pixels = params.width * params.height;
+    if quad * 4u >= pixels {
+        return;
+    }
+    var bytes: array<u32, 12>;
+    for (var i = 0u; i < 4u; i = i + 1u) {
+        let p = quad * 4u + i;
+        var c = vec3<u32>(0u);
+        if p < pixels {
+            c = rgb(p % params.width, p / params.width);
+        }
+        bytes[i * 3u] = c.x;
+        bytes[i * 3u + 1u] = c.y;
+        bytes[i * 3u + 2u] = c.z;
+    }
+    for (var w = 0u; w < 3u; w = w + 1u) {
+        dst[quad * 3u + w] = bytes[w * 4u] | (bytes[w * 4u + 1u] << 8u)
+            | (bytes[w * 4u + 2u] << 16u) | (bytes[w * 4u + 3u] << 24u);
+    }
+}
+"#;
+
+/// How the YUV samples of a frame are laid out, numbered as in the shader.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum YuvLayout {
+    Uyvy = 0,
+    Yuyv = 1,
+    Nv12 = 2,
+}
+
+/// A frame for [`yuv_to_rgb`]: `data` holds `height` rows of `step` bytes, followed by
+/// the chroma plane for `nv12`.
+pub(crate) struct YuvFrame<'a> {
+    pub(crate) data: &'a [u8],
+    pub(crate) layout: YuvLayout,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) step: u32,
+    pub(crate) flip: bool,
+}
+
+/// The compute pipeline, shared by the decoder threads of every window.
+struct Converter {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+}
+
+static CONVERTER: OnceLock<Option<Converter>> = OnceLock::new();
+
+/// Converts `frame` to tightly packed RGB8 on the GPU. `None` when there is no usable
+/// adapter or the frame exceeds its buffer limits, in which case the caller converts on
+/// the CPU.
+pub(crate) fn yuv_to_rgb(frame: &YuvFrame) -> Option<Vec<u8>> {
+    CONVERTER
+        .get_or_init(Converter::new)
+        .as_ref()?
+        .convert(frame)
+}
+
+impl Converter {
+    fn new() -> Option<Self> {
+        let instance = wgpu::Instance::default();
+        let options = wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        };
+        let Some(adapter) = block_on(instance.request_adapter(&options)) else {
+            warn!("gpu-convert: no GPU adapter found, converting YUV on the CPU");
+            return None;
+        };
+        let info = adapter.get_info();
+        // A software rasterizer converts slower than the CPU path it runs on, upload and
+        // readback included. Tests still take one, so the shader is checked without a GPU.
+        if info.device_type == wgpu::DeviceType::Cpu && !cfg!(test) {
+            warn!(
+                "gpu-convert: {} is a software adapter, converting YUV on the CPU",
+                info.name
+            );
+            return None;
+        }
+        let descriptor = wgpu::DeviceDescriptor {
+            label: Some("yuv"),
+            features: wgpu::Features::empty(),
+            limits: adapter.limits(),
+        };
+        let (device, queue) = match block_on(adapter.request_device(&descriptor, None)) {
+            Ok(device) => device,
+            Err(e) => {
+                warn!("gpu-convert: {}, converting YUV on the CPU", e);
+                return None;
+            }
+        };
+        info!("gpu-convert: converting YUV on {}", info.name);
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("yuv"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("yuv"),
+            layout: None,
+            module: &module,
+            entry_point: "main",
+        });
+        Some(Converter {
+            device,
+            queue,
+            pipeline,
+        })
+    }
+
+    fn convert(&self, frame: &YuvFrame) -> Option<Vec<u8>> {
+        let pixels = frame.width as u64 * frame.height as u64;
+        let quads = pixels.div_ceil(4);
+        let out_len = quads * 12;
+        let limit = self.device.limits().max_storage_buffer_binding_size as u64;
+        let groups = quads.div_ceil(WORKGROUP_SIZE as u64);
+        let max_groups = MAX_WORKGROUPS as u64 * MAX_WORKGROUPS as u64;
+        if frame.data.len() as u64 > limit || out_len > limit || groups > max_groups {
+            return None;
+        }
+        let params: Vec<u8> = [
+            frame.width,
+            frame.height,
+            frame.step,
+            frame.layout as u32,
+            frame.flip as u32,
+            0,
+            0,
+            0,
+        ]
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect();
+        let params = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("yuv params"),
+                contents: &params,
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+        let src = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("yuv"),
+                contents: frame.data,
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+        let dst = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("rgb"),
+            size: out_len,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("rgb readback"),
+            size: out_len,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("yuv"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: src.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: dst.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("yuv") });
+        {
+            let mut pass =
+                encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("yuv") });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            // Rows of workgroups once the frame needs more than one dimension holds.
+            let x = groups.min(MAX_WORKGROUPS as u64) as u32;
+            let y = groups.div_ceil(x as u64) as u32;
+            pass.dispatch_workgroups(x, y, 1);
+        }
+        encoder.copy_buffer_to_buffer(&dst, 0, &readback, 0, out_len);
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = readback.slice(..);
+        let (mapped, done) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = mapped.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        if let Err(e) = done.recv().ok()? {
+            warn!("gpu-convert: cannot read the frame back: {}", e);
+            return None;
+        }
+        let mut rgb = slice.get_mapped_range().to_vec();
+        readback.unmap();
+        rgb.truncate(pixels as usize * 3);
+        Some(rgb)
+    }
+}
@@ -0,0 +1,210 @@
+use crate::config::PreviewConfig;
+use crate::convert::{is_monochrome, PreviewImage};
+use crate::decode::decoded;
+use crate::msg::{CompressedImage, RawImage};
+use crate::overlay::{draw_lines, GREEN};
+use crate::preview::{
+    crop_and_orient, received, save_frame, scale_to_window, subscriber, throttled,
+    track_inner_size, window_closed, window_size_for,
+};
+use crate::ros::ImageType;
+use crate::tone::ToneLut;
+use async_io::Timer;
+use futures::future::{self, join_all, LocalBoxFuture};
+use futures::{Future, FutureExt, StreamExt};
+use image::imageops::FilterType;
+use image::RgbImage;
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+use show_image::event::{VirtualKeyCode, WindowEvent};
+use show_image::glam::UVec2;
+use show_image::{create_window, WindowOptions};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How often the montage is redrawn without `max_fps`.
+const GRID_REFRESH: Duration = Duration::from_millis(100);
+
+/// Latest frame of each topic in the grid, already cropped, oriented and toned, plus
+/// whether any of them changed since the last redraw.
+struct Cells {
+    frames: Vec<Option<image::DynamicImage>>,
+    dirty: bool,
+}
+
+/// Tiles the latest frames of all topics of `config` into one roughly square grid, each
+/// cell scaled to a common size and labelled with its topic. The montage is redrawn at a
+/// fixed rate from whatever frames have arrived, so a slow topic shows its last frame.
+pub(crate) fn grid_topics(
+    node: Rc<RefCell<ros2_client::Node>>,
+    config: Rc<PreviewConfig>,
+) -> impl Future<Output = ()> {
+    let options = WindowOptions {
+        preserve_aspect_ratio: true,
+        start_hidden: true,
+        fullscreen: config.fullscreen,
+        ..Default::default()
+    };
+    let window = create_window("grid", options).unwrap();
+
+    let latest_frame: Arc<Mutex<Option<image::DynamicImage>>> = Arc::new(Mutex::new(None));
+    {
+        let latest_frame = latest_frame.clone();
+        window
+            .add_event_handler(move |_window, event, _control_flow| {
+                if let WindowEvent::KeyboardInput(event) = event {
+                    if event.input.state.is_pressed()
+                        && event.input.key_code == Some(VirtualKeyCode::S)
+                    {
+                        if let Some(frame) = latest_frame.lock().unwrap().as_ref() {
+                            save_frame("grid", frame);
+                        }
+                    }
+                }
+            })
+            .unwrap();
+    }
+
+    let closed = window_closed(&window, "grid");
+    let inner_size = track_inner_size(&window);
+    let refresh = config
+        .max_fps
+        .map_or(GRID_REFRESH, |fps| Duration::from_secs_f64(1.0 / fps));
+    let cells = Rc::new(RefCell::new(Cells {
+        frames: vec![None; config.topics.len()],
+        dirty: false,
+    }));
+    let subscribers: Vec<LocalBoxFuture<()>> = config
+        .topics
+        .iter()
+        .enumerate()
+        .map(|(index, (topic_name, image_type))| {
+            let cells = cells.clone();
+            let config = config.clone();
+            match image_type {
+                ImageType::Raw => {
+                    grid_cell::<RawImage>(node.clone(), topic_name, index, cells, config, refresh)
+                        .boxed_local()
+                }
+                ImageType::Compressed => {
+                    let node = node.clone();
+                    grid_cell::<CompressedImage>(node, topic_name, index, cells, config, refresh)
+                        .boxed_local()
+                }
+            }
+        })
+        .collect();
+
+    let render = async move {
+        loop {
+            Timer::after(refresh).await;
+            let montage = {
+                let mut cells = cells.borrow_mut();
+                if !cells.dirty {
+                    continue;
+                }
+                cells.dirty = false;
+                let labels: Vec<&str> = config.topics.iter().map(|(t, _)| t.as_str()).collect();
+                let filter = config.resample_filter();
+                let Some(montage) = tile(&cells.frames, &labels, config.window_width, filter)
+                else {
+                    continue;
+                };
+                image::DynamicImage::ImageRgb8(montage)
+            };
+            *latest_frame.lock().unwrap() = Some(montage.clone());
+            let (window_w, window_h) = window_size_for(&config, montage.width(), montage.height());
+            let montage = match config.filter {
+                Some(filter) => {
+                    let size = inner_size.lock().unwrap().unwrap_or((window_w, window_h));
+                    scale_to_window(montage, filter, size)
+                }
+                None => montage,
+            };
+            window.run_function(move |mut w| {
+                if w.image_info().is_none() {
+                    w.set_inner_size(UVec2::new(window_w, window_h));
+                    w.set_visible(true);
+                }
+            });
+            if let Err(e) = window.set_image("grid", montage) {
+                info!("grid: stopping preview: {}", e);
+                break;
+            }
+        }
+    };
+    let render = future::select(render.boxed_local(), join_all(subscribers));
+    future::select(render, closed.boxed_local()).map(|_| ())
+}
+
+/// Keeps cell `index` updated with the latest frame of `topic_name`, converting at most
+/// one frame per `refresh`, on a decoder thread.
+fn grid_cell<T: DeserializeOwned + PreviewImage + Send + 'static>(
+    node: Rc<RefCell<ros2_client::Node>>,
+    topic_name: &str,
+    index: usize,
+    cells: Rc<RefCell<Cells>>,
+    config: Rc<PreviewConfig>,
+    refresh: Duration,
+) -> impl Future<Output = ()> {
+    let subscribe = subscriber::<T>(node, topic_name, config.clone());
+    let messages = throttled(received(subscribe, topic_name, config.clone()), refresh);
+    let mut frames = decoded(messages, topic_name, config.convert.clone());
+    let topic_name = topic_name.to_string();
+    let tone_lut = ToneLut::new(config.brightness, config.gamma, config.gain);
+    let mut warned_roi = false;
+    async move {
+        while let Some((msg, img)) = frames.next().await {
+            let img = match img {
+                Ok(img) => img,
+                Err(e) => {
+                    warn!("{}: skipping frame: {}", topic_name, e);
+                    continue;
+                }
+            };
+            let Some((mut img, _crop)) =
+                crop_and_orient(img, &config, &topic_name, &mut warned_roi)
+            else {
+                continue;
+            };
+            if let Some(lut) = &tone_lut {
+                img = lut.apply(img, is_monochrome(msg.encoding()));
+            }
+            let mut cells = cells.borrow_mut();
+            cells.frames[index] = Some(img);
+            cells.dirty = true;
+        }
+    }
+}
+
+/// Lays `frames` out in `ceil(sqrt(n))` columns, `width` pixels wide in total. Cells take
+/// the aspect ratio of the first frame; every frame is letterboxed into its cell and
+/// labelled, and topics without a frame yet stay black. `None` until any frame exists.
+fn tile(
+    frames: &[Option<image::DynamicImage>],
+    labels: &[&str],
+    width: u32,
+    filter: FilterType,
+) -> Option<RgbImage> {
+    let first = frames.iter().flatten().next()?;
+    let columns = (frames.len() as f64).sqrt().ceil().max(1.0) as u32;
+    let rows = (frames.len() as u32).div_ceil(columns);
+    let cell_w = (width / columns).max(1);
+    let cell_h = (cell_w as u64 * first.height() as u64 / first.width() as u64).max(1) as u32;
+    let mut montage = RgbImage::new(cell_w * columns, cell_h * rows);
+    for (i, (frame, label)) in frames.iter().zip(labels).enumerate() {
+        let (x, y) = ((i as u32 % columns) * cell_w, (i as u32 / columns) * cell_h);
+        let mut cell = RgbImage::new(cell_w, cell_h);
+        if let Some(frame) = frame {
+            let fitted = frame.resize(cell_w, cell_h, filter).into_rgb8();
+            let offset_x = (cell_w - fitted.width()) / 2;
+            let offset_y = (cell_h - fitted.height()) / 2;
+            image::imageops::replace(&mut cell, &fitted, offset_x as i64, offset_y as i64);
+        }
+        draw_lines(&mut cell, &[(label.to_string(), GREEN)]);
+        image::imageops::replace(&mut montage, &cell, x as i64, y as i64);
+    }
+    Some(montage)
+}
@@ -0,0 +1,245 @@
+use crate::config::PreviewConfig;
+use crate::convert::PreviewImage;
+use crate::decode::decoded;
+use crate::msg::{CompressedImage, Header, RawImage};
+use crate::preview::{
+    crop_and_orient, new_node, received, run_until_shutdown, subscriber, throttled,
+};
+use crate::ros::{watch_matches, ImageType};
+use crate::serve::MjpegServer;
+use async_io::Timer;
+use futures::future::{self, join_all, select_all};
+use futures::{Future, FutureExt, StreamExt};
+use log::{error, warn};
+use serde::de::DeserializeOwned;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Headless counterpart of [`live_preview`](crate::live_preview): instead of opening
+/// windows, every received frame is converted the same way and written to `out_dir` as
+/// `<topic>_<seq>_<stamp>.png`. `max_fps` throttles the writes; the window and overlay
+/// settings of `config` are ignored. Does not need a display, so it can run outside
+/// `show_image::run_context`. With `serve_port` the frames are streamed as well.
+pub fn record_frames(
+    context: &ros2_client::Context,
+    config: PreviewConfig,
+    out_dir: impl Into<PathBuf>,
+) -> std::io::Result<impl Future<Output = ()>> {
+    let out_dir = out_dir.into();
+    std::fs::create_dir_all(&out_dir)?;
+    run_headless(context, config, Some(Rc::new(out_dir)))
+}
+
+/// Like [`record_frames`], but only streams the frames as MJPEG on `serve_port` without
+/// writing them anywhere. Fails if `serve_port` is unset or cannot be bound.
+pub fn serve_frames(
+    context: &ros2_client::Context,
+    config: PreviewConfig,
+) -> std::io::Result<impl Future<Output = ()>> {
+    if config.serve_port.is_none() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "serve_frames needs a serve_port",
+        ));
+    }
+    run_headless(context, config, None)
+}
+
+fn run_headless(
+    context: &ros2_client::Context,
+    config: PreviewConfig,
+    out_dir: Option<Rc<PathBuf>>,
+) -> std::io::Result<impl Future<Output = ()>> {
+    let watch = watch_matches(context);
+    let mjpeg = match config.serve_port {
+        Some(port) => {
+            let topics: Vec<String> = config.topics.iter().map(|(t, _)| t.clone()).collect();
+            Some(MjpegServer::start(port, &topics)?)
+        }
+        None => None,
+    };
+    let node = Rc::new(RefCell::new(new_node(context, &config)));
+    let config = Rc::new(config);
+    let recorders: Vec<_> = config
+        .topics
+        .iter()
+        .map(|(topic_name, image_type)| {
+            let sinks = Sinks {
+                out_dir: out_dir.clone(),
+                mjpeg: mjpeg.clone(),
+            };
+            match image_type {
+                ImageType::Raw => {
+                    record_topic::<RawImage>(node.clone(), topic_name, config.clone(), sinks)
+                        .boxed_local()
+                }
+                ImageType::Compressed => {
+                    record_topic::<CompressedImage>(node.clone(), topic_name, config.clone(), sinks)
+                        .boxed_local()
+                }
+            }
+        })
+        .collect();
+    let recorders = future::select(join_all(recorders), watch.boxed_local()).map(|_| ());
+    Ok(run_until_shutdown(recorders, config.shutdown.clone()))
+}
+
+/// The frame [`grab_frame`] waited for.
+#[derive(Debug, Clone)]
+pub struct GrabbedFrame {
+    pub topic: String,
+    pub header: Header,
+    /// Encoding or format of the message as published, e.g. `bgr8` or `jpeg`.
+    pub encoding: Option<String>,
+    /// The converted frame, with the ROI and orientation applied.
+    pub image: image::DynamicImage,
+}
+
+/// Subscribes to the configured topics and resolves with the first frame that converts,
+/// from whichever topic delivers one first. `None` if nothing arrives within `timeout`
+/// or the shutdown flag is set first. Like [`record_frames`], needs no display.
+pub fn grab_frame(
+    context: &ros2_client::Context,
+    config: PreviewConfig,
+    timeout: Option<Duration>,
+) -> impl Future<Output = Option<GrabbedFrame>> {
+    let watch = watch_matches(context);
+    let node = Rc::new(RefCell::new(new_node(context, &config)));
+    let config = Rc::new(config);
+    let grabs: Vec<_> = config
+        .topics
+        .iter()
+        .map(|(topic_name, image_type)| match image_type {
+            ImageType::Raw => {
+                grab_topic::<RawImage>(node.clone(), topic_name, config.clone()).boxed_local()
+            }
+            ImageType::Compressed => {
+                grab_topic::<CompressedImage>(node.clone(), topic_name, config.clone())
+                    .boxed_local()
+            }
+        })
+        .collect();
+    let grabbed = Rc::new(RefCell::new(None));
+    let first = {
+        let grabbed = grabbed.clone();
+        let watch = watch.boxed_local();
+        async move {
+            if grabs.is_empty() {
+                return;
+            }
+            if let future::Either::Left(((frame, _, _), _)) =
+                future::select(select_all(grabs), watch).await
+            {
+                *grabbed.borrow_mut() = Some(frame);
+            }
+        }
+    };
+    let deadline = timeout.map_or_else(Timer::never, Timer::after);
+    let waiting = future::select(first.boxed_local(), deadline).map(|_| ());
+    async move {
+        run_until_shutdown(waiting, config.shutdown.clone()).await;
+        let frame = grabbed.borrow_mut().take();
+        frame
+    }
+}
+
+fn grab_topic<T: DeserializeOwned + PreviewImage + Send + 'static>(
+    node: Rc<RefCell<ros2_client::Node>>,
+    topic_name: &str,
+    config: Rc<PreviewConfig>,
+) -> impl Future<Output = GrabbedFrame> {
+    let subscribe = subscriber::<T>(node, topic_name, config.clone());
+    let messages = received(subscribe, topic_name, config.clone());
+    let mut frames = decoded(messages, topic_name, config.convert.clone());
+    let topic_name = topic_name.to_string();
+    let mut warned_roi = false;
+    async move {
+        loop {
+            let Some((msg, img)) = frames.next().await else {
+                return future::pending().await;
+            };
+            let img = match img {
+                Ok(img) => img,
+                Err(e) => {
+                    warn!("{}: skipping frame: {}", topic_name, e);
+                    continue;
+                }
+            };
+            if let Some((image, _crop)) =
+                crop_and_orient(img, &config, &topic_name, &mut warned_roi)
+            {
+                return GrabbedFrame {
+                    topic: topic_name,
+                    header: msg.header().clone(),
+                    encoding: msg.encoding().map(str::to_string),
+                    image,
+                };
+            }
+        }
+    }
+}
+
+/// Where the headless frames go.
+struct Sinks {
+    out_dir: Option<Rc<PathBuf>>,
+    mjpeg: Option<Arc<MjpegServer>>,
+}
+
+fn record_topic<T: DeserializeOwned + PreviewImage + Send + 'static>(
+    node: Rc<RefCell<ros2_client::Node>>,
+    topic_name: &str,
+    config: Rc<PreviewConfig>,
+    sinks: Sinks,
+) -> impl Future<Output = ()> {
+    let subscribe = subscriber::<T>(node, topic_name, config.clone());
+    let mut messages = received(subscribe, topic_name, config.clone());
+    if let Some(max_fps) = config.max_fps {
+        messages = throttled(messages, Duration::from_secs_f64(1.0 / max_fps));
+    }
+    let mut frames = decoded(messages, topic_name, config.convert.clone());
+    let topic_name = topic_name.to_string();
+    let mut warned_roi = false;
+    let mut written: u64 = 0;
+    async move {
+        while let Some((msg, img)) = frames.next().await {
+            let img = match img {
+                Ok(img) => img,
+                Err(e) => {
+                    warn!("{}: skipping frame: {}", topic_name, e);
+                    continue;
+                }
+            };
+            let Some((img, _crop)) = crop_and_orient(img, &config, &topic_name, &mut warned_roi)
+            else {
+                continue;
+            };
+            if let Some(mjpeg) = &sinks.mjpeg {
+                mjpeg.publish(&topic_name, &img);
+            }
+            if let Some(out_dir) = &sinks.out_dir {
+                let path = frame_path(out_dir, &topic_name, written, &msg);
+                written += 1;
+                if let Err(e) = img.save(&path) {
+                    error!("Failed to save {}: {}", path.display(), e);
+                }
+            }
+        }
+    }
+}
+
+/// Names a frame after its topic, its number `seq` among the frames written for the topic
+/// and its header stamp. The sequence number keeps frames with a zero or repeated stamp from
+/// overwriting each other and makes files sort in arrival order.
+fn frame_path(out_dir: &Path, topic_name: &str, seq: u64, msg: &impl PreviewImage) -> PathBuf {
+    let header = msg.header();
+    out_dir.join(format!(
+        "{}_{:06}_{}.{:09}.png",
+        topic_name.trim_start_matches('/').replace('/', "_"),
+        seq,
+        header.sec,
+        header.nanosec
+    ))
+}
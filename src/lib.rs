@@ -0,0 +1,45 @@
+//! Live preview of ROS 2 `sensor_msgs/Image` and `sensor_msgs/CompressedImage` topics.
+//!
+//! The message structs and their conversion to [`image::DynamicImage`] can be used on their
+//! own; [`live_preview`] subscribes to topics and renders them in `show_image` windows.
+
+mod bag;
+mod colormap;
+mod compare;
+mod config;
+mod convert;
+mod cycle;
+mod decode;
+#[cfg(feature = "gpu-convert")]
+mod gpu;
+mod grid;
+mod headless;
+mod msg;
+mod overlay;
+mod preview;
+mod record;
+mod replay;
+mod ros;
+mod serve;
+mod sqlite;
+mod stats;
+mod tone;
+
+pub use bag::replay_bag;
+pub use colormap::Colormap;
+pub use config::{ConfigError, PreviewConfig, PreviewConfigBuilder};
+pub use convert::{
+    supported_encodings, ConvertOptions, EncodingInfo, Origin, PreviewError, PreviewImage,
+};
+pub use headless::{grab_frame, record_frames, serve_frames, GrabbedFrame};
+pub use msg::{
+    BoundingBox2D, CameraInfo, CompressedImage, Detection2D, Detection2DArray, Header,
+    ObjectHypothesis, ObjectHypothesisWithPose, Point, Point2D, Pose, Pose2D, PoseWithCovariance,
+    Quaternion, RawImage, RegionOfInterest,
+};
+pub use preview::{block_on, live_preview, live_preview_as, Filter, Orientation, Roi, Rotation};
+pub use replay::replay_sequence;
+pub use ros::{
+    build_qos, discover_image_types, list_image_topics, qos_override, remap_topic, Durability,
+    History, ImageType, Reliability,
+};
@@ -1,139 +1,1218 @@
-use futures::StreamExt;
-use image::ImageReader;
-use image::RgbImage;
-use ros2_client::ros2::policy;
-use serde::de::DeserializeOwned;
-use serde::{Deserialize, Serialize};
-use show_image::create_window;
-use show_image::glam::UVec2;
-use show_image::WindowOptions;
-use std::io::Cursor;
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Header {
-    pub sec: i32,
-    pub nanosec: u32,
-    pub frame_id: String,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct RawImage {
-    pub header: Header,
-    pub height: u32,
-    pub width: u32,
-    pub encoding: String,
-    pub is_bigendian: u8,
-    pub step: u32,
-    pub data: Vec<u8>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CompressedImage {
-    pub header: Header,
-    pub format: String,
-    pub data: Vec<u8>,
-}
-
-pub trait PreviewImage {
-    fn to_image(&self) -> image::DynamicImage;
-    fn as_str() -> &'static str;
-}
-impl PreviewImage for RawImage {
-    fn to_image(&self) -> image::DynamicImage {
-        match self.encoding.as_str() {
-            "bgr8" => {
-                let mut bgr =
-                    RgbImage::from_raw(self.width, self.height, self.data.clone()).unwrap();
-                bgr.pixels_mut().for_each(|p| {
-                    p.0.reverse();
-                });
-                image::DynamicImage::ImageRgb8(bgr)
+use clap::parser::ValueSource;
+use clap::{CommandFactory, Parser, ValueEnum};
+use live_preview::{
+    block_on, build_qos, discover_image_types, grab_frame, list_image_topics, live_preview,
+    qos_override, record_frames, remap_topic, replay_bag, replay_sequence, serve_frames,
+    supported_encodings, Colormap, Durability, Filter, History, ImageType, Origin, PreviewConfig,
+    Reliability, Roi, Rotation,
+};
+use log::{error, warn};
+use serde::Deserialize;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long `--once` waits for a frame without `--timeout`.
+const ONCE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Live preview of ROS 2 image topics")]
+#[command(group = clap::ArgGroup::new("headless_output").args(["out_dir", "serve"]).multiple(true))]
+struct Args {
+    /// Image topics to preview, e.g. /camera/image_raw. Each opens its own window. A
+    /// `:POLICY[,POLICY]` suffix such as `/thermal/image_raw:reliable` overrides `--qos`
+    /// and `--durability` for that topic; it is also accepted by `--compare`, `--diff`
+    /// and `--overlay`. Policies: reliable, best-effort, volatile, transient-local.
+    #[arg(
+        required_unless_present_any = ["list", "list_encodings", "compare", "diff", "replay"],
+        value_parser = parse_topic
+    )]
+    topics: Vec<String>,
+
+    /// Print the image topics currently advertised in the ROS graph and exit.
+    #[arg(long)]
+    list: bool,
+
+    /// TOML file with presets for the other flags, keyed by their long names and written
+    /// as on the command line, e.g. `topics = ["/camera/image_raw"]`, `qos = "reliable"`,
+    /// `roi = "0,0,640,480"`. Flags given on the command line win over the file, and the
+    /// file wins over environment variables such as `ROS_DOMAIN_ID`; unknown keys are an
+    /// error.
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Show two topics side by side in one window, scaled to the same height.
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["TOPIC_A", "TOPIC_B"],
+        value_parser = parse_topic,
+        conflicts_with = "topics"
+    )]
+    compare: Option<Vec<String>>,
+
+    /// Show the absolute per-pixel difference of two topics, B resized to A. With
+    /// `--colormap` the difference is colormapped and stretched to the largest one in the
+    /// frame, so small changes stand out.
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["TOPIC_A", "TOPIC_B"],
+        value_parser = parse_topic,
+        conflicts_with_all = ["topics", "compare"]
+    )]
+    diff: Option<Vec<String>>,
+
+    /// Tile all topics into one window in a roughly square grid, each cell labelled with
+    /// its topic. Redrawn 10 times per second, or at `--max-fps`.
+    #[arg(long, conflicts_with_all = ["compare", "diff"])]
+    grid: bool,
+
+    /// Show one topic at a time in a single window and flip through them with the left
+    /// and right arrow keys. All topics stay subscribed, so switching is instant.
+    #[arg(long, conflicts_with_all = ["compare", "diff", "grid"])]
+    cycle: bool,
+
+    /// Blend this topic over the first positional topic in one window, resized to match,
+    /// e.g. a thermal image or a mask over a camera.
+    #[arg(
+        long,
+        value_name = "TOPIC",
+        value_parser = parse_topic,
+        conflicts_with_all = ["compare", "diff", "grid", "cycle"]
+    )]
+    overlay: Option<String>,
+
+    /// Opacity of the `--overlay` topic, from 0 (invisible) to 1 (opaque).
+    #[arg(long, default_value_t = 0.5, value_parser = parse_alpha, requires = "overlay")]
+    alpha: f32,
+
+    /// Print the raw image encodings that can be displayed and exit.
+    #[arg(long)]
+    list_encodings: bool,
+
+    /// Message type of the topics. When omitted the type is looked up in the ROS graph,
+    /// falling back to compressed for topics ending in `/compressed` and raw otherwise.
+    #[arg(long = "type", value_enum)]
+    image_type: Option<ImageType>,
+
+    /// Initial window width in pixels; the height follows the image aspect ratio.
+    #[arg(long, default_value_t = 1280)]
+    width: u32,
+
+    /// Upper bound for the initial window height in pixels.
+    #[arg(long)]
+    max_height: Option<u32>,
+
+    /// Usable screen area, e.g. `1920x1040`. Windows are shrunk to fit it, keeping their
+    /// aspect ratio, both when they open and when the resolution changes.
+    #[arg(long, value_name = "WxH", value_parser = parse_size)]
+    screen: Option<(u32, u32)>,
+
+    /// Start in fullscreen (toggle with `f`).
+    #[arg(long)]
+    fullscreen: bool,
+
+    /// Scale frames to the window with this filter instead of letting the window blend
+    /// them; `nearest` shows crisp pixels. Also used for the compare and grid composites.
+    #[arg(long, value_enum)]
+    filter: Option<Filter>,
+
+    /// Hide the received frame rate overlay.
+    #[arg(long)]
+    no_fps: bool,
+
+    /// Show the header frame_id, stamp and age overlay at startup (toggle with `t`).
+    #[arg(long)]
+    show_header: bool,
+
+    /// Draw a center crosshair and rule-of-thirds lines at startup, for aiming a camera
+    /// (toggle with `c`).
+    #[arg(long)]
+    grid_overlay: bool,
+
+    /// Show a Laplacian-variance sharpness score and its running maximum, green at the
+    /// peak, for focusing a lens. Zoom in to measure part of the frame; `r` resets the
+    /// maximum.
+    #[arg(long)]
+    focus_meter: bool,
+
+    /// Replace the last good frame with a red frame naming the error when a frame cannot
+    /// be converted, instead of leaving the stale image up (toggle with `e`).
+    #[arg(long)]
+    error_frame: bool,
+
+    /// Subscription reliability. Camera drivers usually publish best-effort, which a
+    /// reliable subscription will not match. Overridden per topic by a `TOPIC:POLICY`
+    /// suffix.
+    #[arg(long, value_enum, default_value_t = Reliability::BestEffort)]
+    qos: Reliability,
+
+    /// How long a reliable subscription may block waiting for retransmits from a flaky
+    /// publisher. Lower it to trade reliability for latency; irrelevant for best-effort.
+    #[arg(long, value_name = "MS", default_value_t = 100)]
+    max_blocking_ms: u64,
+
+    /// Remap a topic the ROS way, e.g. `--remap image:=/camera/image_raw`. May be repeated.
+    #[arg(long, value_name = "FROM:=TO", value_parser = parse_remap)]
+    remap: Vec<(String, String)>,
+
+    /// Name of the ROS node [default: rustdds_listener_<pid>].
+    #[arg(long)]
+    node_name: Option<String>,
+
+    /// Namespace of the ROS node.
+    #[arg(long, default_value = "/rustdds")]
+    namespace: String,
+
+    /// Subscription durability. Use transient-local to get the last frame of a latched
+    /// publisher right away. Overridden per topic by a `TOPIC:POLICY` suffix.
+    #[arg(long, value_enum, default_value_t = Durability::Volatile)]
+    durability: Durability,
+
+    /// ROS domain ID [default: $ROS_DOMAIN_ID, else 0].
+    #[arg(
+        long,
+        default_value_t = 0,
+        hide_default_value = true,
+        value_parser = clap::value_parser!(u16).range(0..=232)
+    )]
+    domain: u16,
+
+    /// Rotate frames clockwise by this many degrees.
+    #[arg(long, value_enum, default_value_t = Rotation::R0)]
+    rotate: Rotation,
+
+    /// Mirror frames left-right (after rotation).
+    #[arg(long)]
+    flip_h: bool,
+
+    /// Mirror frames top-bottom (after rotation).
+    #[arg(long)]
+    flip_v: bool,
+
+    /// Crop frames to this region of the source image before display.
+    #[arg(long, value_name = "X,Y,W,H", value_parser = parse_roi)]
+    roi: Option<Roi>,
+
+    /// Show this region of the displayed frame at native resolution in a corner inset,
+    /// over the whole frame scaled down to the window. Shift + drag selects a region in
+    /// the window and `p` removes the inset.
+    #[arg(long, value_name = "X,Y,W,H", value_parser = parse_roi)]
+    pip: Option<Roi>,
+
+    /// Recreate the subscription after this many seconds without a frame, so a restarted
+    /// publisher is picked up again. With `--once`, how long to wait for the frame, 10 s
+    /// by default.
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    timeout: Option<Duration>,
+
+    /// Outline the last frame in red once no message has arrived for this many
+    /// milliseconds, telling a slow publisher apart from one that is gone.
+    #[arg(long, value_name = "MS")]
+    stale_ms: Option<u64>,
+
+    /// sensor_msgs/CameraInfo topic whose principal point and intrinsics are overlaid.
+    #[arg(long, value_name = "TOPIC")]
+    camera_info: Option<String>,
+
+    /// Distance of the measured object in meters, so the right-click ruler also shows an
+    /// approximate metric length from the `--camera-info` focal lengths.
+    #[arg(long, value_name = "METERS", value_parser = parse_positive, requires = "camera_info")]
+    ruler_depth: Option<f32>,
+
+    /// vision_msgs/Detection2DArray topic whose bounding boxes and best class are drawn
+    /// over the frames.
+    #[arg(long, value_name = "TOPIC")]
+    detections: Option<String>,
+
+    /// Publish the processed frames (cropped, oriented, colormapped, without overlays) as
+    /// sensor_msgs/Image on this topic, e.g. a colormapped depth image for rviz. With
+    /// several topics each is published under `<TOPIC>/<its name>`.
+    #[arg(long, value_name = "TOPIC")]
+    republish: Option<String>,
+
+    /// Publish subscriptions, first frames and decode errors to /rosout, so they show up
+    /// in the aggregated ROS log, e.g. under a launch file.
+    #[arg(long)]
+    rosout: bool,
+
+    /// Subscription history. keep-all queues every frame until it is shown, for slow or
+    /// single-shot topics where none should be missed.
+    #[arg(long, value_enum, default_value_t = History::KeepLast)]
+    history: History,
+
+    /// Depth of the keep-last history queue [default: 2]. Not allowed with
+    /// `--history keep-all`.
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..))]
+    history_depth: Option<i32>,
+
+    /// Fixed depth range in meters for the 32FC1/16UC1 depth colormap, e.g. `0.3,5`.
+    /// Auto-scaled per frame when omitted.
+    #[arg(long, value_name = "MIN,MAX", value_parser = parse_range)]
+    depth_range: Option<(f32, f32)>,
+
+    /// Colormap for depth and mono16 images and `--diff`. Depth defaults to turbo; mono16
+    /// and differences stay grey/RGB unless a colormap is given.
+    #[arg(long, value_enum)]
+    colormap: Option<Colormap>,
+
+    /// Print the message rate, average encoded size, bandwidth, and the min/avg/max latency
+    /// between each frame's header stamp and its display to stdout every second.
+    #[arg(long)]
+    stats: bool,
+
+    /// Treat the generic OpenCV `8UC3` encoding as BGR instead of RGB.
+    #[arg(long)]
+    assume_bgr: bool,
+
+    /// Scale 16-bit and depth images between the 1st and 99th percentile of each frame
+    /// (per channel for rgb16/bgr16) rather than its min/max. `--depth-range` takes
+    /// precedence for depth.
+    #[arg(long)]
+    auto_contrast: bool,
+
+    /// Convert rgb16/bgr16 by taking the high byte of each sample instead of rescaling
+    /// them to the frame's range. Faster, but images using only the low bits stay dark.
+    #[arg(long)]
+    no_16bit_scale: bool,
+
+    /// Corner the first row of raw buffers starts at. The default, top-left, is the ROS
+    /// convention; bottom-left is for drivers that send rows bottom-up. Unlike `--flip-v`
+    /// it fixes the buffer itself, so inspected pixel coordinates stay correct.
+    #[arg(long, value_enum, default_value_t = Origin::TopLeft)]
+    origin: Origin,
+
+    /// Multiply displayed pixel values by this factor, after the colormap for mono and
+    /// depth images.
+    #[arg(long, default_value_t = 1.0, value_parser = parse_positive)]
+    brightness: f32,
+
+    /// Gamma correction of displayed pixel values; values above 1 brighten dark scenes.
+    #[arg(long, default_value_t = 1.0, value_parser = parse_positive)]
+    gamma: f32,
+
+    /// Multiply the red, green and blue channels of color frames by these factors, e.g.
+    /// `1.1,1,0.8` to correct a blue cast. Mono and depth frames ignore it with a warning,
+    /// also when they are shown with a colormap.
+    #[arg(long, value_name = "R,G,B", value_parser = parse_gain)]
+    gain: Option<[f32; 3]>,
+
+    /// Render at most this many frames per second. Frames arriving faster are dropped so
+    /// the newest one is shown; the fps overlay still reports the receive rate.
+    #[arg(long, value_name = "FPS", value_parser = parse_fps)]
+    max_fps: Option<f64>,
+
+    /// Convert and show only every Nth received message, dropping the others before they
+    /// are decoded. Unlike `--max-fps` it is independent of timing and saves the decoding
+    /// cost; `--stats` reports the displayed rate next to the received one.
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    decimate: u64,
+
+    /// Drop frames stamped earlier than the one already shown, which unreliable
+    /// transport can deliver out of order. Frames with a zero stamp are always shown.
+    #[arg(long)]
+    monotonic: bool,
+
+    /// Encode the displayed frames (without overlays) to this video file with `ffmpeg`,
+    /// e.g. `out.mp4`. With several topics the topic name is appended to the file name.
+    #[arg(long, value_name = "FILE")]
+    record: Option<PathBuf>,
+
+    /// Frame rate of the recording. When omitted, frames keep their arrival timing.
+    #[arg(long, value_name = "FPS", requires = "record", value_parser = parse_fps)]
+    record_fps: Option<f64>,
+
+    /// Write every displayed frame to this directory as 000001.png, 000002.png, … with a
+    /// timestamps.csv of header stamps and arrival times.
+    #[arg(long, value_name = "DIR")]
+    sequence_dir: Option<PathBuf>,
+
+    /// Length of the GIF that `g` writes from the most recent frames.
+    #[arg(long, value_name = "SECONDS", default_value = "5", value_parser = parse_seconds)]
+    gif_seconds: Duration,
+
+    /// Frame rate of the GIF written by `g`; frames are also shrunk to 480 pixels wide.
+    #[arg(long, value_name = "FPS", default_value_t = 10.0, value_parser = parse_fps)]
+    gif_fps: f64,
+
+    /// Recent frames kept so the left and right arrow keys can step through them while
+    /// paused. Each is a full decoded frame; 0 turns scrubbing off.
+    #[arg(long, value_name = "N", default_value_t = 30)]
+    buffer_frames: usize,
+
+    /// Write into a non-empty `--sequence-dir`, overwriting earlier frames.
+    #[arg(long, requires = "sequence_dir")]
+    force: bool,
+
+    /// Do not open any window; write every frame to `--out-dir` and/or stream it with
+    /// `--serve` instead. Combine with `--max-fps` to thin out the frames.
+    #[arg(long, requires = "headless_output")]
+    headless: bool,
+
+    /// Directory that `--headless` writes `<topic>_<seq>_<stamp>.png` files to.
+    #[arg(long, value_name = "DIR", requires = "headless")]
+    out_dir: Option<PathBuf>,
+
+    /// Also stream the frames as MJPEG over HTTP on this port, for a browser on another
+    /// machine: `http://<host>:<port>/` shows the first topic, `/<topic>` any of them.
+    #[arg(long, value_name = "PORT")]
+    serve: Option<u16>,
+
+    /// Wait for the first frame that converts, then exit without opening a window: 0
+    /// once it arrived, 1 if `--timeout` (10 s by default) passed first. For scripts and
+    /// health checks.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "compare", "diff", "grid", "cycle", "overlay", "headless", "replay", "bag",
+        ]
+    )]
+    once: bool,
+
+    /// Image file `--once` saves the frame to, in the format its extension names.
+    #[arg(long, value_name = "FILE", requires = "once")]
+    out: Option<PathBuf>,
+
+    /// Print the topic, size, encoding and header stamp of the `--once` frame to stdout.
+    #[arg(long, requires = "once")]
+    print_info: bool,
+
+    /// Play back a directory written with `--sequence-dir` instead of subscribing, with
+    /// the frames' original spacing. Needs no ROS.
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = [
+            "topics", "compare", "diff", "grid", "cycle", "overlay", "headless", "timeout",
+        ]
+    )]
+    replay: Option<PathBuf>,
+
+    /// Play the topics back from a rosbag2 recording, the bag directory or one of its
+    /// `.mcap` or `.db3` files, with their recorded timing. Needs no ROS; compressed MCAP
+    /// chunks need the `zstd` or `lz4` command.
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = [
+            "compare", "diff", "grid", "cycle", "overlay", "headless", "timeout", "replay",
+        ]
+    )]
+    bag: Option<PathBuf>,
+
+    /// Playback speed of `--replay` and `--bag`; 2 plays twice as fast.
+    #[arg(long, default_value_t = 1.0, value_parser = parse_positive)]
+    replay_speed: f32,
+}
+
+/// Values of a `--config` file. Every key is optional and named like its long flag;
+/// `--list`, `--list-encodings` and `--config` itself have no key.
+macro_rules! config_file {
+    ($($(#[$meta:meta])* $field:ident: $ty:ty,)*) => {
+        #[derive(Deserialize, Default, Debug)]
+        #[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+        struct ConfigFile {
+            topics: Vec<String>,
+            $($(#[$meta])* $field: Option<$ty>,)*
+        }
+
+        impl ConfigFile {
+            /// The `Args` ids of the flags a file may set.
+            #[cfg(test)]
+            const FLAGS: &[&str] = &[$(stringify!($field)),*];
+
+            /// The file's values as command-line arguments, leaving out those `given`.
+            fn to_args(&self, given: impl Fn(&str) -> bool) -> Result<Vec<String>, String> {
+                let command = Args::command();
+                let long = |id: &str| {
+                    command
+                        .get_arguments()
+                        .find(|arg| arg.get_id() == id)
+                        .and_then(|arg| arg.get_long())
+                        .ok_or_else(|| format!("config key for {} has no long flag", id))
+                };
+                let mut argv = Vec::new();
+                $(
+                    if let Some(value) = &self.$field {
+                        if !given(stringify!($field)) {
+                            value.push_args(long(stringify!($field))?, &mut argv);
+                        }
+                    }
+                )*
+                if !given("topics") {
+                    argv.extend(self.topics.iter().cloned());
+                }
+                Ok(argv)
+            }
+        }
+    };
+}
+
+config_file! {
+    compare: [String; 2],
+    diff: [String; 2],
+    grid: bool,
+    cycle: bool,
+    overlay: String,
+    alpha: f32,
+    #[serde(rename = "type")]
+    image_type: String,
+    width: u32,
+    max_height: u32,
+    screen: String,
+    fullscreen: bool,
+    filter: String,
+    no_fps: bool,
+    show_header: bool,
+    grid_overlay: bool,
+    focus_meter: bool,
+    error_frame: bool,
+    qos: String,
+    max_blocking_ms: u64,
+    remap: Vec<String>,
+    node_name: String,
+    namespace: String,
+    durability: String,
+    domain: u16,
+    rotate: u32,
+    flip_h: bool,
+    flip_v: bool,
+    roi: String,
+    pip: String,
+    timeout: f64,
+    stale_ms: u64,
+    camera_info: String,
+    ruler_depth: f32,
+    detections: String,
+    republish: String,
+    rosout: bool,
+    history: String,
+    history_depth: i32,
+    depth_range: String,
+    colormap: String,
+    stats: bool,
+    assume_bgr: bool,
+    auto_contrast: bool,
+    no_16bit_scale: bool,
+    origin: String,
+    brightness: f32,
+    gamma: f32,
+    gain: String,
+    max_fps: f64,
+    decimate: u64,
+    monotonic: bool,
+    record: String,
+    record_fps: f64,
+    sequence_dir: String,
+    gif_seconds: f64,
+    gif_fps: f64,
+    buffer_frames: usize,
+    force: bool,
+    headless: bool,
+    out_dir: String,
+    serve: u16,
+    once: bool,
+    out: String,
+    print_info: bool,
+    replay: String,
+    bag: String,
+    replay_speed: f32,
+}
+
+/// How a `--config` value is spelled on the command line.
+trait FlagValue {
+    fn push_args(&self, long: &str, argv: &mut Vec<String>);
+}
+
+impl FlagValue for bool {
+    fn push_args(&self, long: &str, argv: &mut Vec<String>) {
+        if *self {
+            argv.push(format!("--{}", long));
+        }
+    }
+}
+
+impl FlagValue for [String; 2] {
+    fn push_args(&self, long: &str, argv: &mut Vec<String>) {
+        argv.push(format!("--{}", long));
+        argv.extend(self.iter().cloned());
+    }
+}
+
+impl FlagValue for Vec<String> {
+    fn push_args(&self, long: &str, argv: &mut Vec<String>) {
+        for value in self {
+            value.push_args(long, argv);
+        }
+    }
+}
+
+macro_rules! display_flag_value {
+    ($($ty:ty),*) => {
+        $(impl FlagValue for $ty {
+            fn push_args(&self, long: &str, argv: &mut Vec<String>) {
+                // `=` keeps negative numbers from being read as flags.
+                argv.push(format!("--{}={}", long, self));
             }
-            _ => {
-                panic!()
+        })*
+    };
+}
+
+display_flag_value!(String, u16, u32, u64, usize, i32, f32, f64);
+
+/// Parses the command line on top of the `--config` file, if one is given.
+fn parse_args() -> Args {
+    let argv = translate_ros_args(std::env::args_os().collect())
+        .and_then(|cli| with_config_file(cli, read_config_file, |name| std::env::var_os(name)))
+        .unwrap_or_else(|e| {
+            Args::command()
+                .error(clap::error::ErrorKind::InvalidValue, e)
+                .exit()
+        });
+    Args::parse_from(argv)
+}
+
+/// Puts the values of the `--config` file named by `cli`, read with `read`, ahead of the
+/// command line, leaving out those the command line sets itself. `ROS_DOMAIN_ID`, looked up
+/// with `env`, comes last: it sets the domain only when neither the command line nor the
+/// file does.
+fn with_config_file(
+    cli: Vec<OsString>,
+    read: impl Fn(&Path) -> Result<ConfigFile, String>,
+    env: impl Fn(&str) -> Option<OsString>,
+) -> Result<Vec<OsString>, String> {
+    // A lenient pass to find the file and which flags the command line sets itself.
+    let Ok(lenient) = Args::command()
+        .ignore_errors(true)
+        .try_get_matches_from(&cli)
+    else {
+        return Ok(cli);
+    };
+    let file = match lenient.get_one::<PathBuf>("config") {
+        Some(path) => read(path).map_err(|e| format!("{}: {}", path.display(), e))?,
+        None => ConfigFile::default(),
+    };
+    let on_cli = |id: &str| lenient.value_source(id) == Some(ValueSource::CommandLine);
+    // Topics on the command line replace the file's, whether positional, `--compare` or
+    // `--diff`.
+    let given = |id: &str| match id {
+        "topics" | "compare" | "diff" => ["topics", "compare", "diff"].into_iter().any(on_cli),
+        id => on_cli(id),
+    };
+    let mut argv: Vec<OsString> = cli.iter().take(1).cloned().collect();
+    if file.domain.is_none() && !given("domain") {
+        if let Some(domain) = env("ROS_DOMAIN_ID") {
+            let mut flag = OsString::from("--domain=");
+            flag.push(domain);
+            argv.push(flag);
+        }
+    }
+    argv.extend(file.to_args(given)?.into_iter().map(OsString::from));
+    argv.extend(cli.iter().skip(1).cloned());
+    Ok(argv)
+}
+
+/// Replaces the `--ros-args ... [--]` blocks a launch file passes every node with the
+/// equivalent flags, so the viewer drops into launch files unchanged. Remaps become
+/// `--remap`, `__node:=` / `__ns:=` set the node name and namespace; parameters and
+/// logging options are accepted but ignored. The domain comes from `ROS_DOMAIN_ID`, as for
+/// any ROS 2 node.
+fn translate_ros_args(cli: Vec<OsString>) -> Result<Vec<OsString>, String> {
+    let mut flags = Vec::new();
+    let mut rest = Vec::new();
+    let mut args = cli.into_iter();
+    rest.extend(args.next());
+    while let Some(arg) = args.next() {
+        if arg != "--ros-args" {
+            rest.push(arg);
+            continue;
+        }
+        while let Some(arg) = args.next() {
+            let arg = arg.to_string_lossy().into_owned();
+            let mut value = |name: &str| {
+                args.next()
+                    .map(|v| v.to_string_lossy().into_owned())
+                    .ok_or_else(|| format!("--ros-args: {} needs a value", name))
+            };
+            match arg.as_str() {
+                "--" => break,
+                "-r" | "--remap" => {
+                    let remap = value(&arg)?;
+                    match parse_remap(&remap)? {
+                        (from, to) if from == "__node" || from == "__name" => {
+                            flags.push(format!("--node-name={}", to))
+                        }
+                        (from, to) if from == "__ns" => flags.push(format!("--namespace={}", to)),
+                        _ => flags.push(format!("--remap={}", remap)),
+                    }
+                }
+                "-p" | "--param" => {
+                    let param = value(&arg)?;
+                    match param.split_once(":=") {
+                        Some(("use_sim_time", _)) => warn!(
+                            "--ros-args: ignoring use_sim_time, frame ages are measured \
+                             against the wall clock"
+                        ),
+                        Some(_) => warn!("--ros-args: ignoring parameter {}", param),
+                        None => {
+                            return Err(format!(
+                                "--ros-args: expected NAME:=VALUE, got {:?}",
+                                param
+                            ))
+                        }
+                    }
+                }
+                "--params-file" | "--log-level" | "--log-config-file" | "-e" | "--enclave" => {
+                    let value = value(&arg)?;
+                    warn!("--ros-args: ignoring {} {}", arg, value);
+                }
+                _ if arg.starts_with("--") && arg.contains("log") => {
+                    warn!("--ros-args: ignoring {}", arg)
+                }
+                _ => return Err(format!("--ros-args: unsupported argument {:?}", arg)),
             }
         }
     }
+    // Ahead of the other arguments, in case they end with a `--` of their own.
+    let at = rest.len().min(1);
+    rest.splice(at..at, flags.into_iter().map(OsString::from));
+    Ok(rest)
+}
+
+fn read_config_file(path: &Path) -> Result<ConfigFile, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    toml::from_str(&text).map_err(|e| e.to_string())
+}
+
+fn parse_roi(s: &str) -> Result<Roi, String> {
+    let values = s
+        .split(',')
+        .map(|v| v.trim().parse::<u32>().map_err(|e| format!("{}: {}", v, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    match *values.as_slice() {
+        [x, y, w, h] if w > 0 && h > 0 => Ok(Roi { x, y, w, h }),
+        [_, _, _, _] => Err("ROI width and height must be positive".to_string()),
+        _ => Err(format!("expected X,Y,W,H, got {:?}", s)),
+    }
+}
+
+fn parse_gain(s: &str) -> Result<[f32; 3], String> {
+    let gains = s
+        .split(',')
+        .map(|v| match v.trim().parse::<f32>() {
+            Ok(gain) if gain.is_finite() && gain >= 0.0 => Ok(gain),
+            Ok(_) => Err(format!("gains cannot be negative, got {}", v)),
+            Err(e) => Err(format!("{}: {}", v, e)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    gains
+        .try_into()
+        .map_err(|_| format!("expected R,G,B, got {:?}", s))
+}
+
+fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    let (w, h) = s
+        .split_once('x')
+        .ok_or_else(|| format!("expected WIDTHxHEIGHT, got {}", s))?;
+    let parse = |v: &str| match v.trim().parse::<u32>() {
+        Ok(0) => Err(format!("size must be positive, got {}", s)),
+        Ok(v) => Ok(v),
+        Err(e) => Err(format!("{}: {}", v, e)),
+    };
+    Ok((parse(w)?, parse(h)?))
+}
+
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    let secs: f64 = s.parse().map_err(|e| format!("{}: {}", s, e))?;
+    Duration::try_from_secs_f64(secs)
+        .ok()
+        .filter(|d| !d.is_zero())
+        .ok_or_else(|| format!("expected a positive number of seconds, got {}", s))
+}
+
+fn parse_fps(s: &str) -> Result<f64, String> {
+    let fps: f64 = s.parse().map_err(|e| format!("{}: {}", s, e))?;
+    if fps.is_finite() && fps > 0.0 {
+        Ok(fps)
+    } else {
+        Err(format!("expected a positive frame rate, got {}", s))
+    }
+}
 
-    fn as_str() -> &'static str {
-        "Image"
+fn parse_positive(s: &str) -> Result<f32, String> {
+    let value: f32 = s.parse().map_err(|e| format!("{}: {}", s, e))?;
+    if value.is_finite() && value > 0.0 {
+        Ok(value)
+    } else {
+        Err(format!("expected a positive number, got {}", s))
     }
 }
-impl PreviewImage for CompressedImage {
-    fn to_image(&self) -> image::DynamicImage {
-        ImageReader::new(Cursor::new(self.data.clone()))
-            .with_guessed_format()
-            .unwrap()
-            .decode()
-            .unwrap()
+
+fn parse_alpha(s: &str) -> Result<f32, String> {
+    let value: f32 = s.parse().map_err(|e| format!("{}: {}", s, e))?;
+    if (0.0..=1.0).contains(&value) {
+        Ok(value)
+    } else {
+        Err(format!("expected a value between 0 and 1, got {}", s))
+    }
+}
+
+/// Checks the optional `:POLICY[,POLICY]` QoS suffix of a topic, keeping it for
+/// [`split_topic_qos`].
+fn parse_topic(s: &str) -> Result<String, String> {
+    split_topic_qos(s).map(|_| s.to_string())
+}
+
+/// Splits `/topic:best-effort,transient-local` into the topic and the reliability and
+/// durability it names. ROS names cannot contain `:`, so anything after one is QoS.
+fn split_topic_qos(s: &str) -> Result<(&str, Option<Reliability>, Option<Durability>), String> {
+    let Some((topic, policies)) = s.split_once(':') else {
+        return Ok((s, None, None));
+    };
+    let (mut reliability, mut durability) = (None, None);
+    for policy in policies.split(',') {
+        if let Ok(value) = Reliability::from_str(policy, true) {
+            reliability = Some(value);
+        } else if let Ok(value) = Durability::from_str(policy, true) {
+            durability = Some(value);
+        } else {
+            return Err(format!(
+                "unknown QoS policy {:?} for {}, expected reliable, best-effort, volatile or \
+                 transient-local",
+                policy, topic
+            ));
+        }
     }
+    Ok((topic, reliability, durability))
+}
 
-    fn as_str() -> &'static str {
-        "CompressedImage"
+fn parse_remap(s: &str) -> Result<(String, String), String> {
+    match s.split_once(":=") {
+        Some((from, to)) if !from.is_empty() && !to.is_empty() => {
+            Ok((from.to_string(), to.to_string()))
+        }
+        _ => Err(format!("expected FROM:=TO, got {:?}", s)),
     }
 }
 
-fn live_preview<T: DeserializeOwned + PreviewImage + 'static>(topic_name: &str) {
-    let context = ros2_client::Context::new().unwrap();
-    let mut node = context
-        .new_node(
-            ros2_client::NodeName::new("/rustdds", "rustdds_listener").unwrap(),
-            ros2_client::NodeOptions::new().enable_rosout(false),
+fn parse_range(s: &str) -> Result<(f32, f32), String> {
+    let (min, max) = s
+        .split_once(',')
+        .ok_or_else(|| format!("expected MIN,MAX, got {:?}", s))?;
+    let min: f32 = min.trim().parse().map_err(|e| format!("{}: {}", min, e))?;
+    let max: f32 = max.trim().parse().map_err(|e| format!("{}: {}", max, e))?;
+    if min >= max {
+        return Err(format!("min {} must be less than max {}", min, max));
+    }
+    Ok((min, max))
+}
+
+pub fn main() {
+    // Warnings from every crate and progress from this one unless RUST_LOG says otherwise.
+    env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or("warn,live_preview=info"),
+    )
+    .init();
+    let args = parse_args();
+    if args.history == History::KeepAll && args.history_depth.is_some() {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--history-depth only applies to --history keep-last",
+            )
+            .exit();
+    }
+    if args.list_encodings {
+        for encoding in supported_encodings() {
+            println!("{}", encoding);
+        }
+        return;
+    }
+    if args.list {
+        let context = ros2_client::Context::with_options(
+            ros2_client::ContextOptions::new().domain_id(args.domain),
         )
         .unwrap();
-
-    let reliable_qos = ros2_client::ros2::QosPolicyBuilder::new()
-        .history(policy::History::KeepLast { depth: 2 })
-        .reliability(policy::Reliability::Reliable {
-            max_blocking_time: ros2_client::ros2::Duration::from_millis(100),
+        list_image_topics(&context, Duration::from_secs(1));
+        return;
+    }
+    let shutdown = Arc::new(AtomicBool::new(false));
+    {
+        let shutdown = shutdown.clone();
+        ctrlc::set_handler(move || {
+            if shutdown.swap(true, Ordering::Relaxed) {
+                // Second Ctrl-C while the first one is still being handled.
+                std::process::exit(130);
+            }
         })
-        .durability(policy::Durability::Volatile)
-        .build();
-    let chatter_topic = node
-        .create_topic(
-            &ros2_client::Name::new("/", topic_name).unwrap(),
-            ros2_client::MessageTypeName::new("sensor_msgs", T::as_str()),
-            &ros2_client::DEFAULT_SUBSCRIPTION_QOS,
+        .expect("failed to install the Ctrl-C handler");
+    }
+    if let Some(dir) = args.replay.clone() {
+        show_image::run_context(move || {
+            let config = preview_config(&args, None, shutdown);
+            match replay_sequence(&dir, f64::from(args.replay_speed), config) {
+                Ok(replay) => block_on(replay),
+                Err(e) => error!("Cannot replay {}: {}", dir.display(), e),
+            }
+        });
+    }
+    if let Some(path) = args.bag.clone() {
+        show_image::run_context(move || {
+            let config = preview_config(&args, None, shutdown);
+            match replay_bag(&path, f64::from(args.replay_speed), config) {
+                Ok(replay) => block_on(replay),
+                Err(e) => error!("Cannot play {}: {}", path.display(), e),
+            }
+        });
+    }
+    if args.once {
+        let context = ros2_client::Context::with_options(
+            ros2_client::ContextOptions::new().domain_id(args.domain),
         )
         .unwrap();
-
-    let chatter_subscription = node
-        .create_subscription::<T>(&chatter_topic, Some(reliable_qos))
+        let config = preview_config(&args, Some(&context), shutdown.clone());
+        let timeout = args.timeout.unwrap_or(ONCE_TIMEOUT);
+        let Some(frame) = block_on(grab_frame(&context, config, Some(timeout))) else {
+            if !shutdown.load(Ordering::Relaxed) {
+                error!("No frame received within {:?}", timeout);
+            }
+            std::process::exit(1);
+        };
+        if let Some(out) = &args.out {
+            if let Err(e) = frame.image.save(out) {
+                error!("Failed to save {}: {}", out.display(), e);
+                std::process::exit(1);
+            }
+        }
+        if args.print_info {
+            println!(
+                "{} {}x{} {} {}.{:09}",
+                frame.topic,
+                frame.image.width(),
+                frame.image.height(),
+                frame.encoding.as_deref().unwrap_or("-"),
+                frame.header.sec,
+                frame.header.nanosec
+            );
+        }
+        return;
+    }
+    if args.headless {
+        let context = ros2_client::Context::with_options(
+            ros2_client::ContextOptions::new().domain_id(args.domain),
+        )
         .unwrap();
+        let config = preview_config(&args, Some(&context), shutdown);
+        match &args.out_dir {
+            Some(out_dir) => match record_frames(&context, config, out_dir) {
+                Ok(recording) => block_on(recording),
+                Err(e) => error!("Cannot write to {}: {}", out_dir.display(), e),
+            },
+            None => match serve_frames(&context, config) {
+                Ok(serving) => block_on(serving),
+                Err(e) => error!("Cannot serve on port {:?}: {}", args.serve, e),
+            },
+        }
+        return;
+    }
+    show_image::run_context(move || {
+        let context = ros2_client::Context::with_options(
+            ros2_client::ContextOptions::new().domain_id(args.domain),
+        )
+        .unwrap();
+        let config = preview_config(&args, Some(&context), shutdown);
+        match live_preview(&context, config) {
+            Ok(preview) => block_on(preview),
+            Err(e) => error!("{}", e),
+        }
+    });
+}
 
-    let options = WindowOptions {
-        preserve_aspect_ratio: true,
-        start_hidden: true,
-        ..Default::default()
+/// Builds the preview configuration from the command line, resolving the message type of
+/// each topic through `context` unless it is given.
+fn preview_config(
+    args: &Args,
+    context: Option<&ros2_client::Context>,
+    shutdown: Arc<AtomicBool>,
+) -> PreviewConfig {
+    let mut config = PreviewConfig::builder()
+        .namespace(args.namespace.clone())
+        .compare(args.compare.is_some())
+        .diff(args.diff.is_some())
+        .grid(args.grid)
+        .cycle(args.cycle)
+        .overlay_alpha(args.overlay.as_ref().map(|_| args.alpha))
+        .rosout(args.rosout)
+        .serve_port(args.serve)
+        .qos(build_qos(
+            args.qos,
+            args.durability,
+            args.history,
+            args.history_depth.unwrap_or(2),
+            Duration::from_millis(args.max_blocking_ms),
+        ))
+        .window_width(args.width)
+        .max_window_height(args.max_height)
+        .screen_size(args.screen)
+        .fullscreen(args.fullscreen)
+        .filter(args.filter)
+        .show_fps(!args.no_fps)
+        .max_fps(args.max_fps)
+        .decimate(args.decimate)
+        .monotonic(args.monotonic)
+        .stats(args.stats)
+        .show_header(args.show_header)
+        .grid_overlay(args.grid_overlay)
+        .focus_meter(args.focus_meter)
+        .error_frame(args.error_frame)
+        .rotation(args.rotate)
+        .flip_h(args.flip_h)
+        .flip_v(args.flip_v)
+        .roi(args.roi)
+        .pip(args.pip)
+        .depth_range(args.depth_range)
+        .colormap(args.colormap)
+        .auto_contrast(args.auto_contrast)
+        .take_high_byte(args.no_16bit_scale)
+        .origin(args.origin)
+        .assume_bgr(args.assume_bgr)
+        .brightness(args.brightness)
+        .gamma(args.gamma)
+        .gain(args.gain.unwrap_or([1.0; 3]))
+        .timeout(args.timeout)
+        .stale(args.stale_ms.map(Duration::from_millis))
+        .record(args.record.clone())
+        .record_fps(args.record_fps)
+        .sequence_dir(args.sequence_dir.clone())
+        .force(args.force)
+        .gif_length(args.gif_seconds)
+        .gif_fps(args.gif_fps)
+        .buffer_frames(args.buffer_frames)
+        .camera_info_topic(args.camera_info.clone())
+        .ruler_depth(args.ruler_depth)
+        .detections_topic(args.detections.clone())
+        .republish_topic(args.republish.clone())
+        .shutdown(shutdown);
+    if let Some(node_name) = &args.node_name {
+        config = config.node_name(node_name.clone());
+    }
+    for (from, to) in &args.remap {
+        config = config.remap(from.clone(), to.clone());
+    }
+    let pair = args.compare.as_ref().or(args.diff.as_ref());
+    let topics: Vec<&String> = match (pair, &args.overlay) {
+        (Some(pair), _) => pair.iter().collect(),
+        (None, Some(overlay)) => args.topics.iter().take(1).chain([overlay]).collect(),
+        (None, None) => args.topics.iter().collect(),
     };
-    let window = create_window(topic_name, options).unwrap();
-
-    let subscription_stream = chatter_subscription
-        .async_stream()
-        .for_each(|result| async {
-            match result {
-                Ok((msg, _info)) => {
-                    let img = msg.to_image();
-                    let window_w = 1280;
-                    let window_h = img.height() * window_w / img.width();
-
-                    window.run_function(move |mut w| {
-                        if w.image_info().is_none() {
-                            w.set_inner_size(UVec2::new(window_w, window_h));
-                            w.set_visible(true);
-                            println!("init");
-                        }
-                    });
-                    window.set_image(topic_name, msg.to_image()).unwrap();
-                }
-                Err(e) => eprintln!("Receive request error: {:?}", e),
-            }
-        });
-
-    smol::block_on(subscription_stream);
+    let topics: Vec<_> = topics
+        .into_iter()
+        .map(|topic| split_topic_qos(topic).expect("checked by parse_topic"))
+        .collect();
+    let subscribed: Vec<&str> = topics
+        .iter()
+        .map(|(topic, _, _)| remap_topic(&args.remap, topic))
+        .collect();
+    // One wait for all topics rather than one per topic.
+    let discovered = match (args.image_type, context) {
+        (None, Some(context)) => discover_image_types(context, &subscribed, Duration::from_secs(2)),
+        _ => vec![None; topics.len()],
+    };
+    for (((topic, reliability, durability), subscribed), discovered) in
+        topics.into_iter().zip(subscribed).zip(discovered)
+    {
+        if reliability.is_some() || durability.is_some() {
+            let blocking = Duration::from_millis(args.max_blocking_ms);
+            config = config.topic_qos(topic, qos_override(reliability, durability, blocking));
+        }
+        let image_type = args
+            .image_type
+            .or(discovered)
+            .unwrap_or_else(|| ImageType::from_topic(subscribed));
+        config = config.topic(topic, image_type);
+    }
+    config.build()
 }
 
-pub fn main() {
-    let topic_name = "ssbu_c";
-    show_image::run_context(move || live_preview::<CompressedImage>(topic_name));
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses `cli` on top of a `--config` file holding `toml` and an environment with
+    /// `ROS_DOMAIN_ID` set to `domain`.
+    fn parse_with(toml: &str, domain: Option<&str>, cli: &[&str]) -> Args {
+        let cli = cli.iter().map(OsString::from).collect();
+        let read = |_: &Path| toml::from_str(toml).map_err(|e| e.to_string());
+        let env = |name: &str| {
+            domain
+                .filter(|_| name == "ROS_DOMAIN_ID")
+                .map(OsString::from)
+        };
+        Args::try_parse_from(with_config_file(cli, read, env).unwrap()).unwrap()
+    }
+
+    fn parse_with_file(toml: &str, cli: &[&str]) -> Args {
+        parse_with(toml, None, cli)
+    }
+
+    #[test]
+    fn file_fills_in_flags_the_command_line_leaves_out() {
+        let args = parse_with_file(
+            "topics = [\"/front\"]\nqos = \"reliable\"\nmax-fps = 15",
+            &["live_preview", "--config", "preview.toml", "--max-fps", "5"],
+        );
+        assert_eq!(args.topics, ["/front"]);
+        assert_eq!(args.qos, Reliability::Reliable);
+        assert_eq!(args.max_fps, Some(5.0));
+    }
+
+    #[test]
+    fn command_line_topics_replace_the_files() {
+        let args = parse_with_file(
+            "topics = [\"/front\", \"/rear\"]",
+            &[
+                "live_preview",
+                "--config",
+                "preview.toml",
+                "--compare",
+                "/a",
+                "/b",
+            ],
+        );
+        assert!(args.topics.is_empty());
+        assert_eq!(args.compare, Some(vec!["/a".to_string(), "/b".to_string()]));
+    }
+
+    #[test]
+    fn file_domain_wins_over_the_environment() {
+        let env = Some("7");
+        let inherited = parse_with("", env, &["live_preview", "/front"]);
+        let from_file = parse_with(
+            "domain = 3",
+            env,
+            &["live_preview", "--config", "f", "/front"],
+        );
+        let from_cli = parse_with(
+            "domain = 3",
+            env,
+            &["live_preview", "--config", "f", "--domain", "5", "/front"],
+        );
+        assert_eq!(inherited.domain, 7);
+        assert_eq!(from_file.domain, 3);
+        assert_eq!(from_cli.domain, 5);
+        assert_eq!(parse_with("", None, &["live_preview", "/front"]).domain, 0);
+    }
+
+    #[test]
+    fn config_file_keys_are_the_long_flags() {
+        let command = Args::command();
+        let mut flags: Vec<&str> = command
+            .get_arguments()
+            .filter(|arg| arg.get_long().is_some())
+            .map(|arg| arg.get_id().as_str())
+            .filter(|id| !["list", "list_encodings", "config", "help", "version"].contains(id))
+            .collect();
+        flags.sort_unstable();
+        let mut keys = ConfigFile::FLAGS.to_vec();
+        keys.sort_unstable();
+        assert_eq!(keys, flags);
+    }
+
+    #[test]
+    fn unreadable_config_names_the_file() {
+        let cli = ["live_preview", "--config", "missing.toml", "/front"];
+        let cli = cli.iter().map(OsString::from).collect();
+        let e = with_config_file(cli, read_config_file, |_| None).unwrap_err();
+        assert!(e.starts_with("missing.toml: "), "{}", e);
+    }
+
+    fn ros_args(cli: &[&str]) -> Result<Vec<String>, String> {
+        let cli = cli.iter().map(OsString::from).collect();
+        translate_ros_args(cli).map(|argv| {
+            argv.into_iter()
+                .map(|arg| arg.into_string().unwrap())
+                .collect()
+        })
+    }
+
+    #[test]
+    fn ros_args_become_flags_ahead_of_the_rest() {
+        let argv = ros_args(&[
+            "live_preview",
+            "/image",
+            "--ros-args",
+            "-r",
+            "__node:=viewer",
+            "-r",
+            "__ns:=/cam",
+            "--remap",
+            "/image:=/cam0/image",
+            "-p",
+            "use_sim_time:=true",
+            "--log-level",
+            "debug",
+            "--",
+            "--stats",
+        ])
+        .unwrap();
+        assert_eq!(
+            argv,
+            [
+                "live_preview",
+                "--node-name=viewer",
+                "--namespace=/cam",
+                "--remap=/image:=/cam0/image",
+                "/image",
+                "--stats",
+            ]
+        );
+    }
+
+    #[test]
+    fn command_line_without_ros_args_is_unchanged() {
+        let cli = ["live_preview", "--grid", "/a", "/b"];
+        assert_eq!(ros_args(&cli).unwrap(), cli);
+    }
+
+    #[test]
+    fn bad_ros_args_are_errors() {
+        let missing = ros_args(&["live_preview", "--ros-args", "-r"]).unwrap_err();
+        assert!(missing.contains("needs a value"), "{}", missing);
+        let param = ros_args(&["live_preview", "--ros-args", "-p", "fps"]).unwrap_err();
+        assert!(param.contains("NAME:=VALUE"), "{}", param);
+        let unknown = ros_args(&["live_preview", "--ros-args", "--bogus"]).unwrap_err();
+        assert!(unknown.contains("unsupported"), "{}", unknown);
+    }
+
+    #[test]
+    fn topic_qos_suffix_names_the_policies() {
+        assert_eq!(split_topic_qos("/image"), Ok(("/image", None, None)));
+        assert_eq!(
+            split_topic_qos("/thermal:reliable"),
+            Ok(("/thermal", Some(Reliability::Reliable), None))
+        );
+        assert_eq!(
+            split_topic_qos("/map:Transient-Local,best-effort"),
+            Ok((
+                "/map",
+                Some(Reliability::BestEffort),
+                Some(Durability::TransientLocal)
+            ))
+        );
+    }
+
+    #[test]
+    fn unknown_topic_qos_policy_is_an_error() {
+        let e = split_topic_qos("/image:fast").unwrap_err();
+        assert!(e.contains("\"fast\" for /image"), "{}", e);
+        assert!(parse_topic("/image:reliable,").is_err());
+    }
 }
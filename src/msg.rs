@@ -0,0 +1,147 @@
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Header {
+    pub sec: i32,
+    pub nanosec: u32,
+    pub frame_id: String,
+}
+
+impl Header {
+    /// The stamp as seconds since the Unix epoch.
+    pub fn stamp_secs(&self) -> f64 {
+        self.sec as f64 + self.nanosec as f64 * 1e-9
+    }
+
+    /// Seconds elapsed between the header stamp and `now`; negative when the stamp is in
+    /// the future, which indicates clock skew between machines.
+    pub fn age(&self, now: SystemTime) -> f64 {
+        let stamp = self.stamp_secs();
+        let now = now
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        now - stamp
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawImage {
+    pub header: Header,
+    pub height: u32,
+    pub width: u32,
+    pub encoding: String,
+    pub is_bigendian: u8,
+    pub step: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompressedImage {
+    pub header: Header,
+    pub format: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegionOfInterest {
+    pub x_offset: u32,
+    pub y_offset: u32,
+    pub height: u32,
+    pub width: u32,
+    pub do_rectify: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraInfo {
+    pub header: Header,
+    pub height: u32,
+    pub width: u32,
+    pub distortion_model: String,
+    pub d: Vec<f64>,
+    pub k: [f64; 9],
+    pub r: [f64; 9],
+    pub p: [f64; 12],
+    pub binning_x: u32,
+    pub binning_y: u32,
+    pub roi: RegionOfInterest,
+}
+
+/// vision_msgs/Point2D
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Point2D {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// vision_msgs/Pose2D
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pose2D {
+    pub position: Point2D,
+    pub theta: f64,
+}
+
+/// vision_msgs/BoundingBox2D, in pixels of the source image.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BoundingBox2D {
+    pub center: Pose2D,
+    pub size_x: f64,
+    pub size_y: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObjectHypothesis {
+    pub class_id: String,
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Quaternion {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub w: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pose {
+    pub position: Point,
+    pub orientation: Quaternion,
+}
+
+/// geometry_msgs/PoseWithCovariance. The 6x6 row-major covariance is nested because serde
+/// only implements fixed-size arrays up to 32 elements; CDR lays both out the same way.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoseWithCovariance {
+    pub pose: Pose,
+    pub covariance: [[f64; 6]; 6],
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObjectHypothesisWithPose {
+    pub hypothesis: ObjectHypothesis,
+    pub pose: PoseWithCovariance,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Detection2D {
+    pub header: Header,
+    pub results: Vec<ObjectHypothesisWithPose>,
+    pub bbox: BoundingBox2D,
+    pub id: String,
+}
+
+/// vision_msgs/Detection2DArray (vision_msgs 4.x, ROS 2 Humble and later).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Detection2DArray {
+    pub header: Header,
+    pub detections: Vec<Detection2D>,
+}
@@ -0,0 +1,357 @@
+use crate::msg::{CameraInfo, Detection2DArray};
+use crate::preview::{Orientation, Roi};
+use font8x8::{UnicodeFonts, BASIC_FONTS};
+use image::imageops::FilterType;
+use image::{GenericImageView, RgbImage};
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// Rolling average of the message arrival rate over the last `capacity` frames.
+pub(crate) struct FpsCounter {
+    arrivals: VecDeque<Instant>,
+    capacity: usize,
+}
+
+impl FpsCounter {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            arrivals: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub(crate) fn tick(&mut self, now: Instant) {
+        if self.arrivals.len() == self.capacity {
+            self.arrivals.pop_front();
+        }
+        self.arrivals.push_back(now);
+    }
+
+    pub(crate) fn fps(&self) -> f32 {
+        match (self.arrivals.front(), self.arrivals.back()) {
+            (Some(first), Some(last)) if last > first => {
+                (self.arrivals.len() - 1) as f32 / (*last - *first).as_secs_f32()
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+pub(crate) const GREEN: image::Rgb<u8> = image::Rgb([0, 255, 0]);
+pub(crate) const RED: image::Rgb<u8> = image::Rgb([255, 0, 0]);
+
+/// Draws a crosshair at the calibrated principal point, scaled from the calibration
+/// resolution to the `frame_size` of the decoded frame and then carried through the
+/// `crop` and display `orientation`.
+pub(crate) fn draw_principal_point(
+    canvas: &mut RgbImage,
+    info: &CameraInfo,
+    (src_w, src_h): (u32, u32),
+    crop: Roi,
+    orientation: &Orientation,
+) {
+    let (w, h) = canvas.dimensions();
+    let sx = if info.width > 0 {
+        src_w as f64 / info.width as f64
+    } else {
+        1.0
+    };
+    let sy = if info.height > 0 {
+        src_h as f64 / info.height as f64
+    } else {
+        1.0
+    };
+    let (cx, cy) = orientation.map_point(
+        (
+            info.k[2] * sx - crop.x as f64,
+            info.k[5] * sy - crop.y as f64,
+        ),
+        (crop.w as f64, crop.h as f64),
+    );
+    let cx = cx.round() as i64;
+    let cy = cy.round() as i64;
+    let arm = (w.min(h) / 20).max(5) as i64;
+    for d in -arm..=arm {
+        for (x, y) in [(cx + d, cy), (cx, cy + d)] {
+            if (0..w as i64).contains(&x) && (0..h as i64).contains(&y) {
+                canvas.put_pixel(x as u32, y as u32, RED);
+            }
+        }
+    }
+}
+
+/// Draws the ruler between two canvas `points`, or a cross at the first one while the
+/// second has not been placed.
+pub(crate) fn draw_ruler(canvas: &mut RgbImage, points: &[(f32, f32)], color: image::Rgb<u8>) {
+    let (w, h) = canvas.dimensions();
+    let width = (w / 640).max(1) as i64;
+    let mut plot = |x: f32, y: f32| {
+        let (x, y) = (x.round() as i64, y.round() as i64);
+        for py in y..y + width {
+            for px in x..x + width {
+                if (0..w as i64).contains(&px) && (0..h as i64).contains(&py) {
+                    canvas.put_pixel(px as u32, py as u32, color);
+                }
+            }
+        }
+    };
+    let arm = 4.0 * width as f32;
+    for &(x, y) in points {
+        for d in -arm as i64..=arm as i64 {
+            plot(x + d as f32, y);
+            plot(x, y + d as f32);
+        }
+    }
+    if let [(ax, ay), (bx, by)] = *points {
+        let steps = (bx - ax).abs().max((by - ay).abs()).ceil().max(1.0);
+        for i in 0..=steps as u32 {
+            let t = i as f32 / steps;
+            plot(ax + (bx - ax) * t, ay + (by - ay) * t);
+        }
+    }
+}
+
+/// Draws rule-of-thirds lines and a center crosshair, black over bright pixels and white
+/// over dark ones, so they stand out on any scene.
+pub(crate) fn draw_alignment_grid(canvas: &mut RgbImage) {
+    let (w, h) = canvas.dimensions();
+    let width = (w / 640).max(1);
+    let arm = w.min(h) / 10;
+    let on_line = |v: u32, at: u32| v >= at.saturating_sub(width / 2) && v < at + width.div_ceil(2);
+    let thirds = |size: u32| [size / 3, size * 2 / 3];
+    let (cx, cy) = (w / 2, h / 2);
+    for (x, y, p) in canvas.enumerate_pixels_mut() {
+        let third = thirds(w).iter().any(|&at| on_line(x, at))
+            || thirds(h).iter().any(|&at| on_line(y, at));
+        let cross =
+            (on_line(x, cx) && y.abs_diff(cy) <= arm) || (on_line(y, cy) && x.abs_diff(cx) <= arm);
+        if third || cross {
+            let [r, g, b] = p.0.map(u32::from);
+            let bright = r * 299 + g * 587 + b * 114 >= 128_000;
+            p.0 = if bright { [0; 3] } else { [255; 3] };
+        }
+    }
+}
+
+/// Draws overlay text lines stacked from the top-left corner.
+pub(crate) fn draw_lines(canvas: &mut RgbImage, lines: &[(String, image::Rgb<u8>)]) {
+    let line_h = 10 * (canvas.width() / 640).max(1);
+    for (i, (text, color)) in lines.iter().enumerate() {
+        draw_text(canvas, (8, 8 + i as u32 * line_h), text, *color);
+    }
+}
+
+/// Outlines the canvas with a `color` frame, thicker on large frames like the text.
+pub(crate) fn draw_border(canvas: &mut RgbImage, color: image::Rgb<u8>) {
+    let thickness = 4 * (canvas.width() / 640).max(1);
+    let (w, h) = canvas.dimensions();
+    for (x, y, p) in canvas.enumerate_pixels_mut() {
+        if x < thickness || y < thickness || x + thickness >= w || y + thickness >= h {
+            *p = color;
+        }
+    }
+}
+
+/// Draws `text` with the built-in 8x8 bitmap font on a dark background box, scaled up
+/// with the image so it stays readable on large frames.
+fn draw_text(canvas: &mut RgbImage, (x, y): (u32, u32), text: &str, color: image::Rgb<u8>) {
+    let scale = (canvas.width() / 640).max(1);
+    let glyph_size = 8 * scale;
+    let box_w = glyph_size * text.chars().count() as u32 + 2 * scale;
+    let box_h = glyph_size + 2 * scale;
+    for py in y..(y + box_h).min(canvas.height()) {
+        for px in x..(x + box_w).min(canvas.width()) {
+            let p = canvas.get_pixel_mut(px, py);
+            p.0 = p.0.map(|c| c / 3);
+        }
+    }
+    for (i, c) in text.chars().enumerate() {
+        let Some(glyph) = BASIC_FONTS.get(c) else {
+            continue;
+        };
+        let gx = x + scale + i as u32 * glyph_size;
+        for (row, bits) in glyph.iter().enumerate() {
+            for col in 0..8 {
+                if bits & (1 << col) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let px = gx + col * scale + dx;
+                        let py = y + scale + row as u32 * scale + dy;
+                        if px < canvas.width() && py < canvas.height() {
+                            canvas.put_pixel(px, py, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Width frames are shrunk to before measuring their sharpness, which keeps the cost flat
+/// and smooths out sensor noise.
+const FOCUS_WIDTH: u32 = 320;
+
+/// Variance of the Laplacian of a downsampled grayscale copy of `img`, which rises as the
+/// image comes into focus. Only comparable between frames of the same scene.
+pub(crate) fn sharpness(img: &image::DynamicImage) -> f64 {
+    let mut gray = img.to_luma8();
+    if gray.width() > FOCUS_WIDTH {
+        let height = (gray.height() as u64 * FOCUS_WIDTH as u64 / gray.width() as u64).max(1);
+        gray = image::imageops::resize(&gray, FOCUS_WIDTH, height as u32, FilterType::Triangle);
+    }
+    let (w, h) = gray.dimensions();
+    if w < 3 || h < 3 {
+        return 0.0;
+    }
+    let at = |x: u32, y: u32| gray.get_pixel(x, y).0[0] as f64;
+    let (mut sum, mut sum_sq) = (0.0, 0.0);
+    for y in 1..h - 1 {
+        for x in 1..w - 1 {
+            let laplacian =
+                4.0 * at(x, y) - at(x - 1, y) - at(x + 1, y) - at(x, y - 1) - at(x, y + 1);
+            sum += laplacian;
+            sum_sq += laplacian * laplacian;
+        }
+    }
+    let n = ((w - 2) * (h - 2)) as f64;
+    let mean = sum / n;
+    sum_sq / n - mean * mean
+}
+
+/// Largest number of pixels sampled for the histogram; bigger frames are subsampled on a
+/// regular grid.
+const HISTOGRAM_SAMPLES: u64 = 65_536;
+
+/// Counts of the 8-bit values of each channel of a frame: red, green and blue, or a single
+/// channel for mono and depth frames, whose values are the normalized data.
+pub(crate) struct Histogram {
+    channels: Vec<[u32; 256]>,
+}
+
+impl Histogram {
+    pub(crate) fn of(img: &image::DynamicImage) -> Self {
+        let channel_count = if img.color().has_color() { 3 } else { 1 };
+        let mut channels = vec![[0; 256]; channel_count];
+        let pixels = img.width() as u64 * img.height() as u64;
+        let stride = ((pixels as f64 / HISTOGRAM_SAMPLES as f64).sqrt().ceil() as u32).max(1);
+        for y in (0..img.height()).step_by(stride as usize) {
+            for x in (0..img.width()).step_by(stride as usize) {
+                let pixel = img.get_pixel(x, y).0;
+                for (counts, &value) in channels.iter_mut().zip(&pixel) {
+                    counts[value as usize] += 1;
+                }
+            }
+        }
+        Self { channels }
+    }
+}
+
+/// Draws `histogram` in the bottom-right corner, each channel in its own color and
+/// overlapping channels mixed, scaled to the most frequent value.
+pub(crate) fn draw_histogram(canvas: &mut RgbImage, histogram: &Histogram) {
+    let scale = (canvas.width() / 640).max(1);
+    let (panel_w, panel_h) = (256 * scale / 2, 64 * scale);
+    let (w, h) = canvas.dimensions();
+    if w < panel_w + 8 || h < panel_h + 8 {
+        return;
+    }
+    let (left, top) = (w - panel_w - 8, h - panel_h - 8);
+    let peak = histogram
+        .channels
+        .iter()
+        .flat_map(|counts| counts.iter().copied())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let colors: &[[u8; 3]] = match histogram.channels.len() {
+        1 => &[[255, 255, 255]],
+        _ => &[[255, 0, 0], [0, 255, 0], [0, 0, 255]],
+    };
+    for px in 0..panel_w {
+        let bin = (px * 256 / panel_w) as usize;
+        let bars: Vec<u32> = histogram
+            .channels
+            .iter()
+            .map(|counts| (counts[bin] as u64 * panel_h as u64 / peak as u64) as u32)
+            .collect();
+        for py in 0..panel_h {
+            let height = panel_h - py;
+            let p = canvas.get_pixel_mut(left + px, top + py);
+            let mut color = p.0.map(|c| c / 3);
+            for (&bar, channel_color) in bars.iter().zip(colors) {
+                if bar >= height {
+                    for (c, &add) in color.iter_mut().zip(channel_color) {
+                        *c = c.saturating_add(add);
+                    }
+                }
+            }
+            p.0 = color;
+        }
+    }
+}
+
+pub(crate) const YELLOW: image::Rgb<u8> = image::Rgb([255, 255, 0]);
+
+/// Draws each detection's bounding box and best class hypothesis. Boxes are in source
+/// frame pixels and are carried through the `crop` and display `orientation`.
+pub(crate) fn draw_detections(
+    canvas: &mut RgbImage,
+    detections: &Detection2DArray,
+    crop: Roi,
+    orientation: &Orientation,
+) {
+    let (w, h) = canvas.dimensions();
+    for detection in &detections.detections {
+        let bbox = &detection.bbox;
+        let (cx, cy) = (
+            bbox.center.position.x - crop.x as f64,
+            bbox.center.position.y - crop.y as f64,
+        );
+        let corners = [
+            (cx - bbox.size_x / 2.0, cy - bbox.size_y / 2.0),
+            (cx + bbox.size_x / 2.0, cy + bbox.size_y / 2.0),
+        ]
+        .map(|p| orientation.map_point(p, (crop.w as f64, crop.h as f64)));
+        let x0 = corners[0].0.min(corners[1].0).round() as i64;
+        let x1 = corners[0].0.max(corners[1].0).round() as i64;
+        let y0 = corners[0].1.min(corners[1].1).round() as i64;
+        let y1 = corners[0].1.max(corners[1].1).round() as i64;
+        draw_rect(canvas, (x0, y0), (x1, y1), YELLOW);
+
+        let best = detection
+            .results
+            .iter()
+            .max_by(|a, b| a.hypothesis.score.total_cmp(&b.hypothesis.score));
+        if let Some(best) = best {
+            if x0 < w as i64 && y0 < h as i64 {
+                let label = format!("{} {:.2}", best.hypothesis.class_id, best.hypothesis.score);
+                draw_text(canvas, (x0.max(0) as u32, y0.max(0) as u32), &label, YELLOW);
+            }
+        }
+    }
+}
+
+/// Outlines the rectangle from `(x0, y0)` to `(x1, y1)`, inclusive, clipped to the canvas.
+pub(crate) fn draw_rect(
+    canvas: &mut RgbImage,
+    (x0, y0): (i64, i64),
+    (x1, y1): (i64, i64),
+    color: image::Rgb<u8>,
+) {
+    let (w, h) = canvas.dimensions();
+    let mut put = |x: i64, y: i64| {
+        if (0..w as i64).contains(&x) && (0..h as i64).contains(&y) {
+            canvas.put_pixel(x as u32, y as u32, color);
+        }
+    };
+    for x in x0..=x1 {
+        put(x, y0);
+        put(x, y1);
+    }
+    for y in y0..=y1 {
+        put(x0, y);
+        put(x1, y);
+    }
+}
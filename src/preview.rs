@@ -0,0 +1,1644 @@
+use crate::compare::compare_topics;
+use crate::config::{ConfigError, PreviewConfig};
+use crate::convert::{is_monochrome, Origin, PreviewImage};
+use crate::cycle::cycle_topics;
+use crate::decode::Decoder;
+use crate::grid::grid_topics;
+use crate::msg::{CameraInfo, CompressedImage, Detection2DArray, Header, RawImage};
+use crate::overlay::{
+    draw_alignment_grid, draw_border, draw_detections, draw_histogram, draw_lines,
+    draw_principal_point, draw_rect, draw_ruler, sharpness, FpsCounter, Histogram, GREEN, RED,
+    YELLOW,
+};
+use crate::record::{topic_record_path, FrameHistory, GifBuffer, SequenceWriter, VideoRecorder};
+use crate::ros::{topic_name_to_ros, watch_matches, ImageType};
+use crate::serve::MjpegServer;
+use crate::stats::StreamStats;
+use crate::tone::ToneLut;
+use async_io::Timer;
+use clap::ValueEnum;
+use futures::channel::mpsc;
+use futures::future::{self, join_all, Either, LocalBoxFuture};
+use futures::stream::{self, LocalBoxStream};
+use futures::{Future, FutureExt, StreamExt};
+use image::imageops::FilterType;
+use image::GenericImageView;
+use log::{debug, error, info, warn};
+use ros2_client::ros2::{LogLevel, QosPolicies};
+use ros2_client::rosout;
+use serde::de::DeserializeOwned;
+use show_image::create_window;
+use show_image::event::{MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+use show_image::glam::{UVec2, Vec2};
+use show_image::{WindowHandle, WindowOptions, WindowProxy};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Frames older than this are flagged in the header overlay.
+const HEADER_AGE_WARN_SECS: f64 = 0.5;
+
+/// Logs the number of received frames at debug level every this many frames.
+const FRAME_COUNT_LOG_INTERVAL: u64 = 100;
+
+/// Minimum time between window title updates, so the fps does not flicker.
+const TITLE_REFRESH: Duration = Duration::from_secs(1);
+
+/// Minimum time between window resizes when the frame resolution keeps changing.
+const RESIZE_THROTTLE: Duration = Duration::from_millis(500);
+
+/// Crop rectangle in source frame pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Roi {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl Roi {
+    /// Intersects the ROI with a `w` x `h` frame, returning `None` if nothing is left.
+    fn clamp(self, w: u32, h: u32) -> Option<Roi> {
+        let x = self.x.min(w);
+        let y = self.y.min(h);
+        let roi = Roi {
+            x,
+            y,
+            w: self.w.min(w - x),
+            h: self.h.min(h - y),
+        };
+        (roi.w > 0 && roi.h > 0).then_some(roi)
+    }
+}
+
+/// The part of the frame shown while zoomed in: magnified `zoom` times, with its top-left
+/// corner at `origin`, both relative to the frame size.
+#[derive(Debug, Clone, Copy)]
+struct View {
+    zoom: f32,
+    origin: Vec2,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        View {
+            zoom: 1.0,
+            origin: Vec2::ZERO,
+        }
+    }
+}
+
+impl View {
+    const MAX_ZOOM: f32 = 32.0;
+
+    /// Zooms in by `factor`, keeping the frame point under `cursor` where it is. `cursor`
+    /// is a fraction of the view.
+    fn zoom_at(&mut self, factor: f32, cursor: Vec2) {
+        let cursor = cursor.clamp(Vec2::ZERO, Vec2::ONE);
+        let point = self.origin + cursor / self.zoom;
+        self.zoom = (self.zoom * factor).clamp(1.0, Self::MAX_ZOOM);
+        self.origin = point - cursor / self.zoom;
+        self.clamp();
+    }
+
+    /// Drags the content by `delta`, a fraction of the view.
+    fn pan(&mut self, delta: Vec2) {
+        self.origin -= delta / self.zoom;
+        self.clamp();
+    }
+
+    fn clamp(&mut self) {
+        let max = Vec2::splat(1.0 - 1.0 / self.zoom);
+        self.origin = self.origin.clamp(Vec2::ZERO, max);
+    }
+
+    /// The visible part of a `w` x `h` frame.
+    fn visible(&self, w: u32, h: u32) -> Roi {
+        let vw = ((w as f32 / self.zoom).round() as u32).clamp(1, w.max(1));
+        let vh = ((h as f32 / self.zoom).round() as u32).clamp(1, h.max(1));
+        Roi {
+            x: ((self.origin.x * w as f32) as u32).min(w.saturating_sub(vw)),
+            y: ((self.origin.y * h as f32) as u32).min(h.saturating_sub(vh)),
+            w: vw,
+            h: vh,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    #[value(name = "0")]
+    R0,
+    #[value(name = "90")]
+    R90,
+    #[value(name = "180")]
+    R180,
+    #[value(name = "270")]
+    R270,
+}
+
+/// Resampling filter used to scale frames to the window and to build composites.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Filter {
+    /// Crisp pixels, for pixel art and small sensors.
+    Nearest,
+    #[default]
+    Triangle,
+    /// Sharpest when shrinking, but the slowest.
+    Lanczos,
+}
+
+impl Filter {
+    pub(crate) fn filter_type(self) -> FilterType {
+        match self {
+            Filter::Nearest => FilterType::Nearest,
+            Filter::Triangle => FilterType::Triangle,
+            Filter::Lanczos => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// Clockwise rotation followed by flips, applied to each decoded frame before display.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Orientation {
+    pub rotation: Rotation,
+    pub flip_h: bool,
+    pub flip_v: bool,
+}
+
+impl Orientation {
+    fn apply(&self, img: image::DynamicImage) -> image::DynamicImage {
+        let img = match self.rotation {
+            Rotation::R0 => img,
+            Rotation::R90 => img.rotate90(),
+            Rotation::R180 => img.rotate180(),
+            Rotation::R270 => img.rotate270(),
+        };
+        let img = if self.flip_h { img.fliph() } else { img };
+        if self.flip_v {
+            img.flipv()
+        } else {
+            img
+        }
+    }
+
+    /// Maps a pixel position in a `w` x `h` source frame to the transformed frame.
+    pub(crate) fn map_point(&self, (x, y): (f64, f64), (w, h): (f64, f64)) -> (f64, f64) {
+        let ((x, y), (w, h)) = match self.rotation {
+            Rotation::R0 => ((x, y), (w, h)),
+            Rotation::R90 => ((h - 1.0 - y, x), (h, w)),
+            Rotation::R180 => ((w - 1.0 - x, h - 1.0 - y), (w, h)),
+            Rotation::R270 => ((y, w - 1.0 - x), (h, w)),
+        };
+        let x = if self.flip_h { w - 1.0 - x } else { x };
+        let y = if self.flip_v { h - 1.0 - y } else { y };
+        (x, y)
+    }
+
+    /// Inverse of [`Self::map_point`]: maps a position in the transformed frame back to
+    /// the `w` x `h` source frame.
+    fn unmap_point(&self, (x, y): (f64, f64), (w, h): (f64, f64)) -> (f64, f64) {
+        let (tw, th) = match self.rotation {
+            Rotation::R0 | Rotation::R180 => (w, h),
+            Rotation::R90 | Rotation::R270 => (h, w),
+        };
+        let x = if self.flip_h { tw - 1.0 - x } else { x };
+        let y = if self.flip_v { th - 1.0 - y } else { y };
+        match self.rotation {
+            Rotation::R0 => (x, y),
+            Rotation::R90 => (y, h - 1.0 - x),
+            Rotation::R180 => (w - 1.0 - x, h - 1.0 - y),
+            Rotation::R270 => (w - 1.0 - y, x),
+        }
+    }
+}
+
+/// Sets up one subscription and window per topic, or a single window showing the first two
+/// topics side by side in `compare` mode, blended with `overlay_alpha` or subtracted in
+/// `diff` mode, all of them tiled in `grid` mode or one at a time in `cycle` mode, and
+/// returns a future that renders them until every window is done. The future is `!Send`
+/// and has to be driven on the thread that `show_image::run_context` hands to the user
+/// task; see [`block_on`]. Fails if the mode cannot run with the configured topics.
+pub fn live_preview(
+    context: &ros2_client::Context,
+    config: PreviewConfig,
+) -> Result<impl Future<Output = ()>, ConfigError> {
+    config.validate()?;
+    let watch = watch_matches(context);
+    Ok(future::select(previews(context, config), watch.boxed_local()).map(|_| ()))
+}
+
+/// The windows of [`live_preview`] for the mode selected in `config`.
+fn previews(context: &ros2_client::Context, config: PreviewConfig) -> LocalBoxFuture<'static, ()> {
+    if config.compare || config.diff || config.overlay_alpha.is_some() {
+        let node = Rc::new(RefCell::new(new_node(context, &config)));
+        let config = Rc::new(config);
+        let compare = compare_topics(node, config.clone()).boxed_local();
+        return run_until_shutdown(compare, config.shutdown.clone()).boxed_local();
+    }
+    if config.grid {
+        let node = Rc::new(RefCell::new(new_node(context, &config)));
+        let config = Rc::new(config);
+        let grid = grid_topics(node, config.clone()).boxed_local();
+        return run_until_shutdown(grid, config.shutdown.clone()).boxed_local();
+    }
+    if config.cycle {
+        let node = Rc::new(RefCell::new(new_node(context, &config)));
+        let config = Rc::new(config);
+        let cycle = cycle_topics(node, config.clone()).boxed_local();
+        return run_until_shutdown(cycle, config.shutdown.clone()).boxed_local();
+    }
+    run_previews(
+        context,
+        config,
+        |node, (topic_name, image_type), config, companions| match image_type {
+            ImageType::Raw => {
+                preview_topic::<RawImage>(node, topic_name, config, companions).boxed_local()
+            }
+            ImageType::Compressed => {
+                preview_topic::<CompressedImage>(node, topic_name, config, companions).boxed_local()
+            }
+        },
+    )
+    .boxed_local()
+}
+
+/// Like [`live_preview`], but subscribes to every topic as the message type `T`, ignoring
+/// the [`ImageType`] in `config.topics`. This is how image-like messages other than
+/// `sensor_msgs/Image` and `sensor_msgs/CompressedImage` are previewed: implement
+/// [`PreviewImage`] for a serde struct that mirrors the message and call
+/// `live_preview_as::<MyMsg>`. See `examples/custom_message.rs`.
+pub fn live_preview_as<T: DeserializeOwned + PreviewImage + Send + 'static>(
+    context: &ros2_client::Context,
+    config: PreviewConfig,
+) -> impl Future<Output = ()> {
+    let watch = watch_matches(context);
+    let previews = run_previews(
+        context,
+        config,
+        |node, (topic_name, _), config, companions| {
+            preview_topic::<T>(node, topic_name, config, companions).boxed_local()
+        },
+    );
+    future::select(previews.boxed_local(), watch.boxed_local()).map(|_| ())
+}
+
+/// Creates the node, a window per topic via `preview`, the companion topic
+/// subscriptions and the MJPEG server, and drives them all until shutdown.
+fn run_previews(
+    context: &ros2_client::Context,
+    config: PreviewConfig,
+    preview: impl Fn(
+        Rc<RefCell<ros2_client::Node>>,
+        &(String, ImageType),
+        Rc<PreviewConfig>,
+        Companions,
+    ) -> LocalBoxFuture<'static, ()>,
+) -> impl Future<Output = ()> {
+    let node = Rc::new(RefCell::new(new_node(context, &config)));
+    let config = Rc::new(config);
+    let companions = Companions {
+        mjpeg: start_mjpeg(&config),
+        ..Default::default()
+    };
+    let previews: Vec<_> = config
+        .topics
+        .iter()
+        .map(|topic| preview(node.clone(), topic, config.clone(), companions.clone()))
+        .collect();
+    let mut companion_topics: Vec<LocalBoxFuture<()>> = Vec::new();
+    if let Some(camera_info_topic) = &config.camera_info_topic {
+        companion_topics.push(
+            follow_latest(
+                &mut node.borrow_mut(),
+                config.remapped(camera_info_topic),
+                ("sensor_msgs", "CameraInfo"),
+                config.qos.clone(),
+                companions.camera_info.clone(),
+            )
+            .boxed_local(),
+        );
+    }
+    if let Some(detections_topic) = &config.detections_topic {
+        companion_topics.push(
+            follow_latest(
+                &mut node.borrow_mut(),
+                config.remapped(detections_topic),
+                ("vision_msgs", "Detection2DArray"),
+                config.qos.clone(),
+                companions.detections.clone(),
+            )
+            .boxed_local(),
+        );
+    }
+
+    // The companion topics only matter while a window is open to draw them on.
+    let windows = future::select(join_all(previews), join_all(companion_topics));
+    run_until_shutdown(windows.map(|_| ()), config.shutdown.clone())
+}
+
+/// Applies the configured ROI and orientation to a decoded frame, returning the result
+/// and the crop that was used. Returns `None` if the ROI misses the frame entirely.
+pub(crate) fn crop_and_orient(
+    img: image::DynamicImage,
+    config: &PreviewConfig,
+    topic_name: &str,
+    warned_roi: &mut bool,
+) -> Option<(image::DynamicImage, Roi)> {
+    let frame_size = (img.width(), img.height());
+    let crop = match config.roi {
+        Some(roi) => match roi.clamp(img.width(), img.height()) {
+            Some(clamped) => {
+                if clamped != roi && !*warned_roi {
+                    warn!(
+                        "{}: ROI {:?} clamped to {:?} for {}x{} frames",
+                        topic_name, roi, clamped, frame_size.0, frame_size.1
+                    );
+                    *warned_roi = true;
+                }
+                clamped
+            }
+            None => {
+                warn!(
+                    "{}: ROI {:?} lies outside the {}x{} frame",
+                    topic_name, roi, frame_size.0, frame_size.1
+                );
+                return None;
+            }
+        },
+        None => Roi {
+            x: 0,
+            y: 0,
+            w: frame_size.0,
+            h: frame_size.1,
+        },
+    };
+    let img = if config.roi.is_some() {
+        img.crop_imm(crop.x, crop.y, crop.w, crop.h)
+    } else {
+        img
+    };
+    Some((config.orientation.apply(img), crop))
+}
+
+/// Creates the node that owns every subscription of a preview.
+pub(crate) fn new_node(
+    context: &ros2_client::Context,
+    config: &PreviewConfig,
+) -> ros2_client::Node {
+    let name =
+        ros2_client::NodeName::new(&config.namespace, &config.node_name).unwrap_or_else(|e| {
+            panic!(
+                "invalid node name {:?} in namespace {:?}: {:?}",
+                config.node_name, config.namespace, e
+            )
+        });
+    context
+        .new_node(
+            name,
+            ros2_client::NodeOptions::new().enable_rosout(config.rosout),
+        )
+        .unwrap()
+}
+
+/// Subscriptions to the image topic `topic_name` as `T` with its configured QoS, shared by
+/// every mode. Each call of the returned closure subscribes anew, for starting over after
+/// a timeout, and streams the messages as they are taken from the reader.
+pub(crate) fn subscriber<T: DeserializeOwned + PreviewImage + 'static>(
+    node: Rc<RefCell<ros2_client::Node>>,
+    topic_name: &str,
+    config: Rc<PreviewConfig>,
+) -> impl FnMut() -> LocalBoxStream<'static, Result<T, String>> {
+    let topic = node
+        .borrow_mut()
+        .create_topic(
+            &topic_name_to_ros(config.remapped(topic_name)).unwrap(),
+            ros2_client::MessageTypeName::new(T::package(), T::as_str()),
+            &ros2_client::DEFAULT_SUBSCRIPTION_QOS,
+        )
+        .unwrap();
+    let topic_name = topic_name.to_string();
+    move || {
+        let qos = config.qos_for(&topic_name);
+        let subscription = node
+            .borrow_mut()
+            .create_subscription::<T>(&topic, Some(qos.clone()))
+            .unwrap();
+        debug!(
+            "{}: subscribed to {} as {}/{} with {:?}",
+            topic_name,
+            config.remapped(&topic_name),
+            T::package(),
+            T::as_str(),
+            qos
+        );
+        stream::unfold(subscription, |subscription| async move {
+            let received = subscription.async_take().await;
+            let received = received
+                .map(|(msg, _info)| msg)
+                .map_err(|e| format!("{:?}", e));
+            Some((received, subscription))
+        })
+        .boxed_local()
+    }
+}
+
+/// The messages from `subscribe` as the composite and headless modes take them: receive
+/// errors are logged, `--stats` lines printed and only every `decimate`-th message kept.
+/// With a `timeout`, `subscribe` is called again after that long without a message. The
+/// single-topic window does the same in its own loop, which also draws the notices.
+pub(crate) fn received<T: PreviewImage + 'static>(
+    mut subscribe: impl FnMut() -> LocalBoxStream<'static, Result<T, String>> + 'static,
+    topic_name: &str,
+    config: Rc<PreviewConfig>,
+) -> LocalBoxStream<'static, T> {
+    let topic_name = topic_name.to_string();
+    let stream = subscribe();
+    let decimator = Decimator::new(config.decimate);
+    let stats = config.stats.then(|| StreamStats::new(100));
+    let state = (subscribe, stream, decimator, stats);
+    stream::unfold(
+        state,
+        move |(mut subscribe, mut stream, mut decimator, mut stats)| {
+            let topic_name = topic_name.clone();
+            let timeout = config.timeout;
+            async move {
+                loop {
+                    let next = match timeout {
+                        Some(timeout) => {
+                            match future::select(stream.next(), Timer::after(timeout)).await {
+                                Either::Left((next, _)) => next,
+                                Either::Right(_) => {
+                                    debug!(
+                                        "{}: no message for {:?}, subscribing again",
+                                        topic_name, timeout
+                                    );
+                                    stream = subscribe();
+                                    continue;
+                                }
+                            }
+                        }
+                        None => stream.next().await,
+                    };
+                    let msg = match next? {
+                        Ok(msg) => msg,
+                        Err(e) => {
+                            warn!("{}: receive error: {}", topic_name, e);
+                            continue;
+                        }
+                    };
+                    if decimator.received() == 0 {
+                        info!("{}: first frame received", topic_name);
+                    }
+                    let now = Instant::now();
+                    if let Some(stats) = stats.as_mut() {
+                        stats.arrival(now, msg.encoded_len());
+                        if let Some(line) = stats.report(now) {
+                            println!("{}: {}", topic_name, line);
+                        }
+                    }
+                    if decimator.keep() {
+                        return Some((msg, (subscribe, stream, decimator, stats)));
+                    }
+                }
+            }
+        },
+    )
+    .boxed_local()
+}
+
+/// Lets through at most one of `messages` per `interval`, dropping the others before they
+/// are decoded.
+pub(crate) fn throttled<T: 'static>(
+    messages: LocalBoxStream<'static, T>,
+    interval: Duration,
+) -> LocalBoxStream<'static, T> {
+    let mut last: Option<Instant> = None;
+    messages
+        .filter(move |_| {
+            let now = Instant::now();
+            let due = last.is_none_or(|t| now - t >= interval);
+            if due {
+                last = Some(now);
+            }
+            future::ready(due)
+        })
+        .boxed_local()
+}
+
+/// Keeps every `every`-th received message, starting with the first, for `--decimate`.
+pub(crate) struct Decimator {
+    every: u64,
+    received: u64,
+}
+
+impl Decimator {
+    pub(crate) fn new(every: u64) -> Self {
+        Decimator {
+            every: every.max(1),
+            received: 0,
+        }
+    }
+
+    /// Counts a received message and says whether it is kept.
+    pub(crate) fn keep(&mut self) -> bool {
+        self.received += 1;
+        (self.received - 1).is_multiple_of(self.every)
+    }
+
+    /// Messages counted so far.
+    pub(crate) fn received(&self) -> u64 {
+        self.received
+    }
+}
+
+/// Drives `future` until it completes or `shutdown` is set.
+pub(crate) async fn run_until_shutdown(
+    future: impl Future<Output = ()> + Unpin,
+    shutdown: Option<Arc<AtomicBool>>,
+) {
+    match shutdown {
+        Some(flag) => {
+            future::select(future, Box::pin(wait_for_flag(&flag))).await;
+        }
+        None => future.await,
+    }
+    // Dropping the subscriptions and the node here, while the runtime is still up, lets
+    // the participant announce that its readers are gone.
+}
+
+/// Resolves once `flag` is set, polling it every 100 ms.
+async fn wait_for_flag(flag: &AtomicBool) {
+    while !flag.load(Ordering::Relaxed) {
+        Timer::after(Duration::from_millis(100)).await;
+    }
+}
+
+/// Latest messages of the companion topics that are drawn over every window, and the
+/// MJPEG server the windows also publish their frames to.
+#[derive(Clone, Default)]
+pub(crate) struct Companions {
+    camera_info: Rc<RefCell<Option<CameraInfo>>>,
+    detections: Rc<RefCell<Option<Detection2DArray>>>,
+    mjpeg: Option<Arc<MjpegServer>>,
+}
+
+impl Companions {
+    /// Just the MJPEG server, for a window that has no node to subscribe with.
+    pub(crate) fn without_topics(config: &PreviewConfig) -> Self {
+        Companions {
+            mjpeg: start_mjpeg(config),
+            ..Default::default()
+        }
+    }
+}
+
+/// Starts the MJPEG server for `serve_port`. A port that cannot be bound is logged and
+/// the windows run without it.
+fn start_mjpeg(config: &PreviewConfig) -> Option<Arc<MjpegServer>> {
+    let port = config.serve_port?;
+    let topics: Vec<String> = config.topics.iter().map(|(t, _)| t.clone()).collect();
+    MjpegServer::start(port, &topics)
+        .map_err(|e| error!("Cannot serve MJPEG on port {}: {}", port, e))
+        .ok()
+}
+
+/// Keeps `latest` updated with the latest `package/type_name` message on `topic_name`.
+fn follow_latest<M: DeserializeOwned + 'static>(
+    node: &mut ros2_client::Node,
+    topic_name: &str,
+    (package, type_name): (&str, &str),
+    qos: QosPolicies,
+    latest: Rc<RefCell<Option<M>>>,
+) -> impl Future<Output = ()> {
+    let topic = node
+        .create_topic(
+            &topic_name_to_ros(topic_name).unwrap(),
+            ros2_client::MessageTypeName::new(package, type_name),
+            &ros2_client::DEFAULT_SUBSCRIPTION_QOS,
+        )
+        .unwrap();
+    let subscription = node.create_subscription::<M>(&topic, Some(qos)).unwrap();
+    let topic_name = topic_name.to_string();
+    async move {
+        let stream = subscription.async_stream();
+        futures::pin_mut!(stream);
+        while let Some(result) = stream.next().await {
+            match result {
+                Ok((msg, _info)) => *latest.borrow_mut() = Some(msg),
+                Err(e) => warn!("{}: receive error: {:?}", topic_name, e),
+            }
+        }
+    }
+}
+
+/// Drives `future` to completion on the current thread with the configured runtime.
+#[cfg(not(feature = "tokio-runtime"))]
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    smol::block_on(future)
+}
+
+/// Drives `future` to completion on the current thread with the configured runtime.
+#[cfg(feature = "tokio-runtime")]
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+/// Subscribes to `topic_name` and opens its window; see [`show_topic`]. With a
+/// `timeout`, the subscription is recreated whenever no frame arrives for that long so a
+/// restarted publisher gets matched again.
+fn preview_topic<T: DeserializeOwned + PreviewImage + Send + 'static>(
+    node: Rc<RefCell<ros2_client::Node>>,
+    topic_name: &str,
+    config: Rc<PreviewConfig>,
+    companions: Companions,
+) -> impl Future<Output = ()> {
+    let rosout = config.rosout.then(|| node.clone());
+    let republish = config.republish_topic.as_ref().map(|republish_topic| {
+        let republish_topic = if config.topics.len() > 1 {
+            format!(
+                "{}/{}",
+                republish_topic.trim_end_matches('/'),
+                topic_name.trim_start_matches('/')
+            )
+        } else {
+            republish_topic.clone()
+        };
+        let topic = node
+            .borrow_mut()
+            .create_topic(
+                &topic_name_to_ros(&republish_topic).unwrap(),
+                ros2_client::MessageTypeName::new("sensor_msgs", "Image"),
+                &ros2_client::DEFAULT_PUBLISHER_QOS,
+            )
+            .unwrap();
+        info!("{}: republishing to {}", topic_name, republish_topic);
+        node.borrow_mut()
+            .create_publisher::<RawImage>(&topic, None)
+            .unwrap()
+    });
+    let subscribe = {
+        let mut subscribe = subscriber::<T>(node.clone(), topic_name, config.clone());
+        let topic_name = topic_name.to_string();
+        let config = config.clone();
+        move || {
+            let messages = subscribe();
+            if config.rosout {
+                rosout!(
+                    node.borrow(),
+                    LogLevel::Info,
+                    "subscribed to {}",
+                    config.remapped(&topic_name)
+                );
+            }
+            messages
+        }
+    };
+    show_topic(subscribe, rosout, republish, topic_name, config, companions)
+}
+
+/// Opens the window of `topic_name` and renders the messages from the stream `subscribe`
+/// returns until it ends or the window goes away; `subscribe` is called again to start
+/// over after `timeout`. Frames are converted on a [`Decoder`] thread; while it is busy
+/// only the newest received frame waits. Progress is also logged to `rosout` and the
+/// processed frames are published with `republish` if given.
+pub(crate) fn show_topic<T: PreviewImage + Send + 'static>(
+    mut subscribe: impl FnMut() -> LocalBoxStream<'static, Result<T, String>> + 'static,
+    rosout: Option<Rc<RefCell<ros2_client::Node>>>,
+    republish: Option<ros2_client::Publisher<RawImage>>,
+    topic_name: &str,
+    config: Rc<PreviewConfig>,
+    companions: Companions,
+) -> impl Future<Output = ()> {
+    let options = WindowOptions {
+        preserve_aspect_ratio: true,
+        start_hidden: true,
+        fullscreen: config.fullscreen,
+        // Zoom and pan are done on the frame instead, so the overlay text stays legible.
+        default_controls: false,
+        ..Default::default()
+    };
+    let window = create_window(topic_name, options).unwrap();
+
+    let latest_frame: Arc<Mutex<Option<image::DynamicImage>>> = Arc::new(Mutex::new(None));
+    let show_header = Arc::new(AtomicBool::new(config.show_header));
+    let show_histogram = Arc::new(AtomicBool::new(false));
+    let grid_overlay = Arc::new(AtomicBool::new(config.grid_overlay));
+    let reset_focus = Arc::new(AtomicBool::new(false));
+    let error_frame = Arc::new(AtomicBool::new(config.error_frame));
+    let hovered_pixel: Arc<Mutex<Option<Vec2>>> = Arc::new(Mutex::new(None));
+    // Up to two ruler ends, in pixels of the frame before the zoom crop.
+    let ruler: Arc<Mutex<Vec<Vec2>>> = Arc::default();
+    let pip_region = Arc::new(Mutex::new(config.pip));
+    let paused = Arc::new(AtomicBool::new(false));
+    let export_gif = Arc::new(AtomicBool::new(false));
+    let view: Arc<Mutex<View>> = Arc::default();
+    let (scrub, mut scrub_steps) = mpsc::unbounded::<i64>();
+    {
+        let paused = paused.clone();
+        let export_gif = export_gif.clone();
+        let view = view.clone();
+        let latest_frame = latest_frame.clone();
+        let show_header = show_header.clone();
+        let show_histogram = show_histogram.clone();
+        let grid_overlay = grid_overlay.clone();
+        let reset_focus = reset_focus.clone();
+        let error_frame = error_frame.clone();
+        let hovered_pixel = hovered_pixel.clone();
+        let ruler = ruler.clone();
+        let pip_region = pip_region.clone();
+        // Where a shift + drag selecting the inset started, in frame pixels.
+        let mut pip_start: Option<Vec2> = None;
+        let topic_name = topic_name.to_string();
+        window
+            .add_event_handler(move |mut window, event, _control_flow| match event {
+                WindowEvent::KeyboardInput(event) if event.input.state.is_pressed() => {
+                    match event.input.key_code {
+                        Some(VirtualKeyCode::S) => {
+                            if let Some(frame) = latest_frame.lock().unwrap().as_ref() {
+                                save_frame(&topic_name, frame);
+                            }
+                        }
+                        Some(VirtualKeyCode::T) => {
+                            show_header.fetch_xor(true, Ordering::Relaxed);
+                        }
+                        Some(VirtualKeyCode::H) => {
+                            show_histogram.fetch_xor(true, Ordering::Relaxed);
+                        }
+                        Some(VirtualKeyCode::C) => {
+                            grid_overlay.fetch_xor(true, Ordering::Relaxed);
+                        }
+                        Some(VirtualKeyCode::R) => {
+                            reset_focus.store(true, Ordering::Relaxed);
+                        }
+                        Some(VirtualKeyCode::P) => {
+                            *pip_region.lock().unwrap() = None;
+                        }
+                        Some(VirtualKeyCode::E) => {
+                            error_frame.fetch_xor(true, Ordering::Relaxed);
+                        }
+                        Some(VirtualKeyCode::F) => {
+                            // preserve_aspect_ratio letterboxes the image in fullscreen.
+                            let fullscreen = window.is_fullscreen();
+                            window.set_fullscreen(!fullscreen);
+                        }
+                        Some(VirtualKeyCode::Space) => {
+                            // Say so right away: no frame may arrive to draw the notice.
+                            let was_paused = paused.fetch_xor(true, Ordering::Relaxed);
+                            if !was_paused {
+                                if let Some(frame) = with_notice(&latest_frame, "PAUSED") {
+                                    let frame = show_image::Image::from(frame);
+                                    if let Ok(frame) = frame.as_image_view() {
+                                        window.set_image(&topic_name, &frame);
+                                    }
+                                }
+                            }
+                        }
+                        Some(VirtualKeyCode::G) => {
+                            export_gif.store(true, Ordering::Relaxed);
+                        }
+                        Some(VirtualKeyCode::Key0 | VirtualKeyCode::Numpad0) => {
+                            *view.lock().unwrap() = View::default();
+                        }
+                        Some(VirtualKeyCode::Left) => {
+                            let _ = scrub.unbounded_send(-1);
+                        }
+                        Some(VirtualKeyCode::Right) => {
+                            let _ = scrub.unbounded_send(1);
+                        }
+                        _ => {}
+                    }
+                }
+                WindowEvent::MouseWheel(event) => {
+                    let delta = match event.delta {
+                        MouseScrollDelta::LineDelta(_, y) => y,
+                        MouseScrollDelta::PixelDelta(delta) => delta.y as f32 / 20.0,
+                    };
+                    let cursor = event
+                        .position
+                        .map_or(Vec2::splat(0.5), |p| window_to_normalized(&window, p));
+                    view.lock().unwrap().zoom_at(1.1f32.powf(delta), cursor);
+                }
+                WindowEvent::MouseMove(event) => {
+                    if event.buttons.is_pressed(MouseButton::Left) && pip_start.is_none() {
+                        let delta = window_to_normalized(&window, event.position)
+                            - window_to_normalized(&window, event.prev_position);
+                        view.lock().unwrap().pan(delta);
+                    }
+                    *hovered_pixel.lock().unwrap() = cursor_on_image(&window, event.position);
+                }
+                WindowEvent::MouseLeave(_) => {
+                    *hovered_pixel.lock().unwrap() = None;
+                }
+                WindowEvent::MouseButton(event)
+                    if event.button == MouseButton::Right && event.state.is_pressed() =>
+                {
+                    let point = cursor_on_image(&window, event.position)
+                        .and_then(|cursor| frame_point(cursor, &latest_frame, &view));
+                    if let Some(point) = point {
+                        let mut ruler = ruler.lock().unwrap();
+                        if ruler.len() == 2 {
+                            ruler.clear();
+                        }
+                        ruler.push(point);
+                    }
+                }
+                WindowEvent::MouseButton(event) if event.button == MouseButton::Left => {
+                    let cursor = window_to_normalized(&window, event.position);
+                    if event.state.is_pressed() {
+                        if event.modifiers.shift() && window.image_info().is_some() {
+                            pip_start = frame_point(cursor, &latest_frame, &view);
+                        }
+                    } else if let Some(start) = pip_start.take() {
+                        if let Some(end) = frame_point(cursor, &latest_frame, &view) {
+                            let (min, max) = (start.min(end), start.max(end));
+                            let size = (max - min).round();
+                            // A shift + click without a drag selects nothing.
+                            if size.x >= 2.0 && size.y >= 2.0 {
+                                *pip_region.lock().unwrap() = Some(Roi {
+                                    x: min.x as u32,
+                                    y: min.y as u32,
+                                    w: size.x as u32,
+                                    h: size.y as u32,
+                                });
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            })
+            .unwrap();
+    }
+
+    let topic_name = topic_name.to_string();
+    let mut fps_counter = FpsCounter::new(30);
+    let mut warned_roi = false;
+    let mut displayed_size: Option<(u32, u32)> = None;
+    let mut last_resize: Option<Instant> = None;
+    let mut last_render: Option<Instant> = None;
+    let mut frame_count: u64 = 0;
+    let mut decimator = Decimator::new(config.decimate);
+    let mut title = topic_name.clone();
+    let mut last_title_update: Option<Instant> = None;
+    let mut record_path = config.record.as_ref().map(|path| {
+        if config.topics.len() > 1 {
+            topic_record_path(path, &topic_name)
+        } else {
+            path.clone()
+        }
+    });
+    let mut recorder: Option<VideoRecorder> = None;
+    let mut gif_buffer = GifBuffer::new(config.gif_length, config.gif_fps);
+    let mut history = FrameHistory::new(config.buffer_frames);
+    let mut sequence = config.sequence_dir.as_ref().and_then(|dir| {
+        let dir = if config.topics.len() > 1 {
+            dir.join(topic_name.trim_start_matches('/').replace('/', "_"))
+        } else {
+            dir.clone()
+        };
+        SequenceWriter::create(&dir, config.force)
+            .map_err(|e| error!("{}: cannot write to {}: {}", topic_name, dir.display(), e))
+            .ok()
+    });
+    let mut showing_error = false;
+    let mut showing_stale = false;
+    let mut focus_peak: f64 = 0.0;
+    let mut last_arrival: Option<Instant> = None;
+    let tone_lut = ToneLut::new(config.brightness, config.gamma, config.gain);
+    let mut stream_stats = config.stats.then(|| StreamStats::new(100));
+    let mut decoder = Decoder::<T>::spawn(&topic_name, config.convert.clone());
+    let closed = window_closed(&window, &topic_name);
+    let inner_size = track_inner_size(&window);
+    let render = async move {
+        'resubscribe: loop {
+            let mut stream = subscribe();
+            // Header stamp of the last frame shown, for `monotonic`; forgotten on
+            // resubscribing as a restarted publisher may start over from an earlier one.
+            let mut last_stamp: Option<(i32, u32)> = None;
+            // `timeout` counts from the last message, or from subscribing before the first.
+            let mut waiting_since = Instant::now();
+            loop {
+                let next = future::select(
+                    future::select(stream.next(), decoder.next()),
+                    scrub_steps.next(),
+                );
+                let stale = config.stale.filter(|_| !showing_stale);
+                let stale_at = stale
+                    .zip(last_arrival)
+                    .map(|(stale, arrival)| arrival + stale);
+                let next = future::select(next, stale_at.map_or_else(Timer::never, Timer::at));
+                let next = match config.timeout {
+                    Some(timeout) => {
+                        match future::select(next, Timer::at(waiting_since + timeout)).await {
+                            Either::Left((next, _)) => next,
+                            Either::Right(_) => {
+                                show_notice(
+                                    &window,
+                                    &topic_name,
+                                    &latest_frame,
+                                    "waiting for publisher...",
+                                );
+                                continue 'resubscribe;
+                            }
+                        }
+                    }
+                    None => next.await,
+                };
+                let next = match next {
+                    Either::Left((next, _)) => next,
+                    Either::Right(_) => {
+                        let stale = config.stale.unwrap_or_default();
+                        show_stale(&window, &topic_name, &latest_frame, stale);
+                        showing_stale = true;
+                        continue;
+                    }
+                };
+                let next = match next {
+                    Either::Left((next, _)) => next,
+                    Either::Right((steps, _)) => {
+                        if let (Some(steps), true) = (steps, paused.load(Ordering::Relaxed)) {
+                            show_buffered(&window, &topic_name, &latest_frame, &mut history, steps);
+                        }
+                        continue;
+                    }
+                };
+                let (msg, img) = match next {
+                    Either::Left((received, _)) => {
+                        let Some(result) = received else {
+                            break 'resubscribe;
+                        };
+                        match result {
+                            Ok(msg) => {
+                                let now = Instant::now();
+                                last_arrival = Some(now);
+                                waiting_since = now;
+                                fps_counter.tick(now);
+                                frame_count += 1;
+                                if frame_count == 1 {
+                                    info!("{}: first frame received", topic_name);
+                                    if let Some(node) = &rosout {
+                                        rosout!(
+                                            node.borrow(),
+                                            LogLevel::Info,
+                                            "{}: first frame received",
+                                            topic_name
+                                        );
+                                    }
+                                } else if frame_count.is_multiple_of(FRAME_COUNT_LOG_INTERVAL) {
+                                    debug!("{}: {} frames received", topic_name, frame_count);
+                                }
+                                if let Some(stats) = stream_stats.as_mut() {
+                                    stats.arrival(now, msg.encoded_len());
+                                    if let Some(line) = stats.report(now) {
+                                        println!("{}: {}", topic_name, line);
+                                    }
+                                }
+                                if !decimator.keep() {
+                                    continue;
+                                }
+                                // Checked again once decoded, as frames still being
+                                // decoded may be shown in between.
+                                if config.monotonic && older_than(msg.header(), last_stamp) {
+                                    debug!(
+                                        "{}: dropping frame stamped {:.6}, older than the last \
+                                         one shown",
+                                        topic_name,
+                                        msg.header().stamp_secs()
+                                    );
+                                    continue;
+                                }
+                                if paused.load(Ordering::Relaxed) {
+                                    // Keep draining the subscription so resuming shows the
+                                    // newest frame instead of a backlog.
+                                    continue;
+                                }
+                                if let Some(max_fps) = config.max_fps {
+                                    let budget = Duration::from_secs_f64(1.0 / max_fps);
+                                    if last_render.is_some_and(|t| now - t < budget) {
+                                        continue;
+                                    }
+                                    last_render = Some(now);
+                                }
+                                decoder.push(msg);
+                            }
+                            Err(e) => warn!("{}: receive error: {}", topic_name, e),
+                        }
+                        continue;
+                    }
+                    Either::Right((Some(decoded), _)) => decoded,
+                    Either::Right((None, _)) => break 'resubscribe,
+                };
+                let img = match img {
+                    Ok(img) => img,
+                    Err(e) => {
+                        warn!("{}: skipping frame: {}", topic_name, e);
+                        if let Some(node) = &rosout {
+                            rosout!(
+                                node.borrow(),
+                                LogLevel::Warn,
+                                "{}: skipping frame: {}",
+                                topic_name,
+                                e
+                            );
+                        }
+                        if error_frame.load(Ordering::Relaxed) {
+                            show_error_frame(&window, &topic_name, &latest_frame, &e.to_string());
+                            showing_error = true;
+                        } else if showing_error {
+                            // Toggled off: back to the last good frame, saying it is stale.
+                            let notice = format!("decode failed: {}", e);
+                            show_notice(&window, &topic_name, &latest_frame, &notice);
+                        }
+                        continue;
+                    }
+                };
+                showing_error = false;
+                let frame_size = (img.width(), img.height());
+                if last_title_update.is_none_or(|t| t.elapsed() >= TITLE_REFRESH) {
+                    last_title_update = Some(Instant::now());
+                    let new_title =
+                        window_title(&topic_name, frame_size, msg.encoding(), fps_counter.fps());
+                    if new_title != title {
+                        title = new_title.clone();
+                        window.run_function(move |w| w.set_title(new_title));
+                    }
+                }
+                if config.monotonic && older_than(msg.header(), last_stamp) {
+                    continue;
+                }
+                let Some((mut img, crop)) =
+                    crop_and_orient(img, &config, &topic_name, &mut warned_roi)
+                else {
+                    continue;
+                };
+                let header = msg.header();
+                // A zero stamp says nothing about the order.
+                if (header.sec, header.nanosec) != (0, 0) {
+                    last_stamp = Some((header.sec, header.nanosec));
+                }
+                if let Some(lut) = &tone_lut {
+                    img = lut.apply(img, is_monochrome(msg.encoding()));
+                }
+                let (window_w, window_h) = window_size_for(&config, img.width(), img.height());
+                let image_size = (img.width(), img.height());
+                *latest_frame.lock().unwrap() = Some(img.clone());
+                history.push(&img, msg.header());
+                if let Some(publisher) = &republish {
+                    let processed = RawImage::from_image(&img, msg.header().clone());
+                    if let Err(e) = publisher.publish(processed) {
+                        warn!("{}: cannot republish: {}", topic_name, e);
+                    }
+                }
+                gif_buffer.push(Instant::now(), &img);
+                if export_gif.swap(false, Ordering::Relaxed) {
+                    gif_buffer.save(capture_path(&topic_name, "gif").into());
+                }
+                if recorder.is_none() {
+                    if let Some(path) = record_path.take() {
+                        match VideoRecorder::start(&path, image_size, config.record_fps) {
+                            Ok(started) => recorder = Some(started),
+                            Err(e) => {
+                                error!("{}: cannot record to {}: {}", topic_name, path.display(), e)
+                            }
+                        }
+                    }
+                }
+                if let Some(writer) = sequence.as_mut() {
+                    let arrival = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs_f64();
+                    if let Err(e) = writer.write(&img, msg.header().stamp_secs(), arrival) {
+                        error!(
+                            "{}: stopped writing the sequence to {}: {}",
+                            topic_name,
+                            writer.dir().display(),
+                            e
+                        );
+                        sequence = None;
+                    }
+                }
+                if let Some(active) = recorder.as_mut() {
+                    if let Err(e) = active.write(&img) {
+                        error!(
+                            "{}: stopped recording {}: {}",
+                            topic_name,
+                            active.path().display(),
+                            e
+                        );
+                        recorder = None;
+                    }
+                }
+
+                let visible = view.lock().unwrap().visible(img.width(), img.height());
+                let histogram = show_histogram
+                    .load(Ordering::Relaxed)
+                    .then(|| Histogram::of(&img));
+                let mut lines = Vec::new();
+                if config.show_fps {
+                    lines.push((format!("{:.1} fps", fps_counter.fps()), GREEN));
+                }
+                if config.focus_meter {
+                    let focus =
+                        sharpness(&img.crop_imm(visible.x, visible.y, visible.w, visible.h));
+                    if reset_focus.swap(false, Ordering::Relaxed) {
+                        focus_peak = 0.0;
+                    }
+                    focus_peak = focus_peak.max(focus);
+                    // Within 2% of the best seen counts as the peak.
+                    let color = if focus >= focus_peak * 0.98 {
+                        GREEN
+                    } else {
+                        YELLOW
+                    };
+                    lines.push((format!("focus {:.1} max {:.1}", focus, focus_peak), color));
+                }
+                if show_header.load(Ordering::Relaxed) {
+                    let header = msg.header();
+                    let age = header.age(SystemTime::now());
+                    let color = if age > HEADER_AGE_WARN_SECS {
+                        RED
+                    } else {
+                        GREEN
+                    };
+                    lines.push((
+                        format!(
+                            "{} {}.{:09} age {:.3}s",
+                            header.frame_id, header.sec, header.nanosec, age
+                        ),
+                        color,
+                    ));
+                }
+                if let Some(cursor) = *hovered_pixel.lock().unwrap() {
+                    let x = ((cursor.x * visible.w as f32) as u32).min(visible.w - 1) + visible.x;
+                    let y = ((cursor.y * visible.h as f32) as u32).min(visible.h - 1) + visible.y;
+                    let (sx, sy) = config
+                        .orientation
+                        .unmap_point((x as f64, y as f64), (crop.w as f64, crop.h as f64));
+                    let (sx, sy) = (sx as u32 + crop.x, sy as u32 + crop.y);
+                    // pixel_value reads the buffer, whose rows may run bottom-up.
+                    let buffer_y = match config.convert.origin {
+                        Origin::TopLeft => sy,
+                        Origin::BottomLeft => frame_size.1.saturating_sub(sy + 1),
+                    };
+                    let value = msg.pixel_value(sx, buffer_y).or_else(|| {
+                        (x < img.width() && y < img.height()).then(|| {
+                            let [r, g, b, _] = img.get_pixel(x, y).0;
+                            format!("rgb {} {} {}", r, g, b)
+                        })
+                    });
+                    if let Some(value) = value {
+                        lines.push((format!("({}, {}) {}", sx, sy, value), GREEN));
+                    }
+                }
+                let latest_info = companions.camera_info.borrow();
+                let latest_detections = companions.detections.borrow();
+                if let Some(info) = latest_info.as_ref() {
+                    lines.push((
+                        format!(
+                            "fx {:.1} fy {:.1} {}",
+                            info.k[0], info.k[4], info.distortion_model
+                        ),
+                        GREEN,
+                    ));
+                }
+                let ruler_ends = ruler.lock().unwrap().clone();
+                if let [a, b] = ruler_ends[..] {
+                    let source = |p: Vec2| {
+                        let size = (crop.w as f64, crop.h as f64);
+                        config
+                            .orientation
+                            .unmap_point((p.x as f64, p.y as f64), size)
+                    };
+                    let ((ax, ay), (bx, by)) = (source(a), source(b));
+                    let (dx, dy) = (bx - ax, by - ay);
+                    let mut text = format!("ruler {:.1} px", dx.hypot(dy));
+                    if let (Some(depth), Some(info)) = (config.ruler_depth, latest_info.as_ref()) {
+                        // Intrinsics are for the calibration resolution, like the
+                        // principal point.
+                        let scale = |size: u32, calibrated: u32| {
+                            if calibrated > 0 {
+                                size as f64 / calibrated as f64
+                            } else {
+                                1.0
+                            }
+                        };
+                        let fx = info.k[0] * scale(frame_size.0, info.width);
+                        let fy = info.k[4] * scale(frame_size.1, info.height);
+                        let depth = depth as f64;
+                        let meters = (dx * depth / fx).hypot(dy * depth / fy);
+                        text.push_str(&format!(" ~{:.3} m at {} m", meters, depth));
+                    }
+                    lines.push((text, YELLOW));
+                }
+                if latest_info.is_some() || latest_detections.is_some() {
+                    let mut canvas = img.into_rgb8();
+                    if let Some(detections) = latest_detections.as_ref() {
+                        draw_detections(&mut canvas, detections, crop, &config.orientation);
+                    }
+                    if let Some(info) = latest_info.as_ref() {
+                        draw_principal_point(
+                            &mut canvas,
+                            info,
+                            frame_size,
+                            crop,
+                            &config.orientation,
+                        );
+                    }
+                    img = image::DynamicImage::ImageRgb8(canvas);
+                }
+                drop(latest_info);
+                drop(latest_detections);
+                let inset = pip_region
+                    .lock()
+                    .unwrap()
+                    .and_then(|region| region.clamp(img.width(), img.height()))
+                    .map(|r| (r, img.crop_imm(r.x, r.y, r.w, r.h)));
+                if (visible.w, visible.h) != (img.width(), img.height()) {
+                    img = img.crop_imm(visible.x, visible.y, visible.w, visible.h);
+                }
+                let window_inner = inner_size.lock().unwrap().unwrap_or((window_w, window_h));
+                if let Some((region, inset)) = inset {
+                    img = picture_in_picture(img, &inset, region, visible, window_inner);
+                } else if let Some(filter) = config.filter {
+                    img = scale_to_window(img, filter, window_inner);
+                }
+                let alignment = grid_overlay.load(Ordering::Relaxed);
+                if !lines.is_empty() || histogram.is_some() || !ruler_ends.is_empty() || alignment {
+                    let mut canvas = img.into_rgb8();
+                    if alignment {
+                        draw_alignment_grid(&mut canvas);
+                    }
+                    // The ruler ends are frame pixels; the canvas shows the visible part.
+                    let (sx, sy) = (
+                        canvas.width() as f32 / visible.w as f32,
+                        canvas.height() as f32 / visible.h as f32,
+                    );
+                    let ends: Vec<(f32, f32)> = ruler_ends
+                        .iter()
+                        .map(|p| ((p.x - visible.x as f32) * sx, (p.y - visible.y as f32) * sy))
+                        .collect();
+                    draw_ruler(&mut canvas, &ends, YELLOW);
+                    draw_lines(&mut canvas, &lines);
+                    if let Some(histogram) = &histogram {
+                        draw_histogram(&mut canvas, histogram);
+                    }
+                    img = image::DynamicImage::ImageRgb8(canvas);
+                }
+
+                let resize = displayed_size != Some(image_size)
+                    && last_resize.is_none_or(|t| t.elapsed() >= RESIZE_THROTTLE);
+                if resize {
+                    displayed_size = Some(image_size);
+                    last_resize = Some(Instant::now());
+                }
+                window.run_function(move |mut w| {
+                    if resize && !w.is_fullscreen() {
+                        w.set_inner_size(UVec2::new(window_w, window_h));
+                    }
+                    if w.image_info().is_none() {
+                        w.set_visible(true);
+                    }
+                });
+                if let Some(mjpeg) = &companions.mjpeg {
+                    mjpeg.publish(&topic_name, &img);
+                }
+                if let Err(e) = window.set_image(&topic_name, img) {
+                    info!("{}: stopping preview: {}", topic_name, e);
+                    break 'resubscribe;
+                }
+                showing_stale = false;
+                if let Some(stats) = stream_stats.as_mut() {
+                    stats.displayed(Instant::now(), msg.header().age(SystemTime::now()));
+                }
+            }
+        }
+    };
+    future::select(render.boxed_local(), closed.boxed_local()).map(|_| ())
+}
+
+/// Resolves once the user closes `window`, so its task ends and drops its subscription
+/// even while the topic is silent.
+pub(crate) fn window_closed(window: &WindowProxy, name: &str) -> impl Future<Output = ()> {
+    let closed = Arc::new(AtomicBool::new(false));
+    let handler = {
+        let closed = closed.clone();
+        window.add_event_handler(move |_window, event, _control_flow| {
+            if let WindowEvent::CloseRequested(_) = event {
+                closed.store(true, Ordering::Relaxed);
+            }
+        })
+    };
+    if handler.is_err() {
+        // Already gone.
+        closed.store(true, Ordering::Relaxed);
+    }
+    let name = name.to_string();
+    async move {
+        wait_for_flag(&closed).await;
+        info!("{}: window closed", name);
+    }
+}
+
+/// Keeps the inner size of `window` in physical pixels, `None` until it is first resized.
+pub(crate) fn track_inner_size(window: &WindowProxy) -> Arc<Mutex<Option<(u32, u32)>>> {
+    let size = Arc::new(Mutex::new(None));
+    let tracked = size.clone();
+    // A window that is already gone has no size to track.
+    let _ = window.add_event_handler(move |_window, event, _control_flow| {
+        if let WindowEvent::Resized(event) = event {
+            *tracked.lock().unwrap() = Some((event.size.x, event.size.y));
+        }
+    });
+    size
+}
+
+/// Resizes `img` with `filter` to fit a window of `(width, height)`, so the window shows
+/// it pixel for pixel instead of scaling it bilinearly itself.
+pub(crate) fn scale_to_window(
+    img: image::DynamicImage,
+    filter: Filter,
+    (width, height): (u32, u32),
+) -> image::DynamicImage {
+    if width == 0 || height == 0 {
+        return img;
+    }
+    let scale = (width as f64 / img.width() as f64).min(height as f64 / img.height() as f64);
+    let w = ((img.width() as f64 * scale) as u32).max(1);
+    let h = ((img.height() as f64 * scale) as u32).max(1);
+    if (w, h) == (img.width(), img.height()) {
+        return img;
+    }
+    img.resize_exact(w, h, filter.filter_type())
+}
+
+/// `"/camera/image (1920x1080 bgr8 @ 29.9fps)"`.
+fn window_title(
+    topic_name: &str,
+    (width, height): (u32, u32),
+    encoding: Option<&str>,
+    fps: f32,
+) -> String {
+    match encoding {
+        Some(encoding) => format!(
+            "{} ({}x{} {} @ {:.1}fps)",
+            topic_name, width, height, encoding, fps
+        ),
+        None => format!("{} ({}x{} @ {:.1}fps)", topic_name, width, height, fps),
+    }
+}
+
+/// Maps a cursor position in physical window pixels to where it falls on the displayed
+/// image, as a fraction of its size, following the window's aspect-ratio fit. `None`
+/// beside the image. A fraction rather than a pixel, as the image may be pre-scaled.
+fn cursor_on_image(window: &WindowHandle, position: Vec2) -> Option<Vec2> {
+    window.image_info()?;
+    let cursor = window_to_normalized(window, position);
+    (cursor.x >= 0.0 && cursor.y >= 0.0 && cursor.x < 1.0 && cursor.y < 1.0).then_some(cursor)
+}
+
+/// A cursor position in physical window pixels as a fraction of the displayed image,
+/// outside 0..1 when it is beside the image.
+fn window_to_normalized(window: &WindowHandle, position: Vec2) -> Vec2 {
+    let virtual_pos = position / window.inner_size().as_vec2();
+    window
+        .effective_transform()
+        .inverse()
+        .transform_point2(virtual_pos)
+}
+
+/// `cursor`, a fraction of the displayed image, in pixels of the frame before the zoom
+/// crop, clamped to the frame. `None` before the first frame.
+fn frame_point(
+    cursor: Vec2,
+    latest_frame: &Mutex<Option<image::DynamicImage>>,
+    view: &Mutex<View>,
+) -> Option<Vec2> {
+    let (w, h) = latest_frame
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|frame| (frame.width(), frame.height()))?;
+    let visible = view.lock().unwrap().visible(w, h);
+    let origin = Vec2::new(visible.x as f32, visible.y as f32);
+    let size = Vec2::new(visible.w as f32, visible.h as f32);
+    Some(origin + cursor.clamp(Vec2::ZERO, Vec2::ONE) * size)
+}
+
+/// Scales the `context` view, the `visible` part of the frame, down to fit `max_size` and
+/// pastes `inset`, the frame's `region` at native resolution, into its bottom-right
+/// corner, cut to half the view each way around its center. The region is outlined on
+/// the context so it is clear where the inset comes from.
+fn picture_in_picture(
+    context: image::DynamicImage,
+    inset: &image::DynamicImage,
+    region: Roi,
+    visible: Roi,
+    (max_w, max_h): (u32, u32),
+) -> image::DynamicImage {
+    let context = if context.width() > max_w || context.height() > max_h {
+        context.thumbnail(max_w, max_h)
+    } else {
+        context
+    };
+    let mut canvas = context.into_rgb8();
+    let (w, h) = canvas.dimensions();
+    let (sx, sy) = (w as f64 / visible.w as f64, h as f64 / visible.h as f64);
+    let to_canvas = |x: u32, y: u32| {
+        (
+            ((x as f64 - visible.x as f64) * sx).round() as i64,
+            ((y as f64 - visible.y as f64) * sy).round() as i64,
+        )
+    };
+    let (right, bottom) = (region.x + region.w, region.y + region.h);
+    draw_rect(
+        &mut canvas,
+        to_canvas(region.x, region.y),
+        to_canvas(right, bottom),
+        YELLOW,
+    );
+
+    let (inset_w, inset_h) = (inset.width().min(w / 2), inset.height().min(h / 2));
+    if inset_w == 0 || inset_h == 0 {
+        return image::DynamicImage::ImageRgb8(canvas);
+    }
+    let cut = inset
+        .crop_imm(
+            (inset.width() - inset_w) / 2,
+            (inset.height() - inset_h) / 2,
+            inset_w,
+            inset_h,
+        )
+        .into_rgb8();
+    let margin = 8.min(w - inset_w).min(h - inset_h);
+    let (x, y) = (w - inset_w - margin, h - inset_h - margin);
+    image::imageops::replace(&mut canvas, &cut, x as i64, y as i64);
+    let (x, y) = (x as i64, y as i64);
+    let (right, bottom) = (x + inset_w as i64, y + inset_h as i64);
+    draw_rect(&mut canvas, (x - 1, y - 1), (right, bottom), YELLOW);
+    image::DynamicImage::ImageRgb8(canvas)
+}
+
+/// Shows a red frame the size of the last good one, which stays in `latest_frame`, with
+/// the conversion `error` on top.
+fn show_error_frame(
+    window: &WindowProxy,
+    topic_name: &str,
+    latest_frame: &Mutex<Option<image::DynamicImage>>,
+    error: &str,
+) {
+    let (width, height) = latest_frame
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map_or((640, 480), |frame| (frame.width(), frame.height()));
+    let mut canvas = image::RgbImage::from_pixel(width, height, image::Rgb([160, 0, 0]));
+    let lines = [
+        ("DECODE FAILED".to_string(), YELLOW),
+        (error.to_string(), YELLOW),
+    ];
+    draw_lines(&mut canvas, &lines);
+    let _ = window.set_image(topic_name, image::DynamicImage::ImageRgb8(canvas));
+}
+
+/// Whether `header` is stamped before `last`. Zero stamps are never older.
+fn older_than(header: &Header, last: Option<(i32, u32)>) -> bool {
+    let stamp = (header.sec, header.nanosec);
+    stamp != (0, 0) && last.is_some_and(|last| stamp < last)
+}
+
+/// Redraws the last frame with `notice` on top, e.g. while the subscription is being
+/// recreated.
+fn show_notice(
+    window: &WindowProxy,
+    topic_name: &str,
+    latest_frame: &Mutex<Option<image::DynamicImage>>,
+    notice: &str,
+) {
+    if let Some(frame) = with_notice(latest_frame, notice) {
+        let _ = window.set_image(topic_name, frame);
+    }
+}
+
+/// The last frame with `notice` drawn on top, if there is one.
+fn with_notice(
+    latest_frame: &Mutex<Option<image::DynamicImage>>,
+    notice: &str,
+) -> Option<image::DynamicImage> {
+    let mut canvas = latest_frame.lock().unwrap().clone()?.into_rgb8();
+    draw_lines(&mut canvas, &[(notice.to_string(), RED)]);
+    Some(image::DynamicImage::ImageRgb8(canvas))
+}
+
+/// Redraws the last frame outlined in red once no message has arrived for `stale`.
+fn show_stale(
+    window: &WindowProxy,
+    topic_name: &str,
+    latest_frame: &Mutex<Option<image::DynamicImage>>,
+    stale: Duration,
+) {
+    let Some(frame) = latest_frame.lock().unwrap().clone() else {
+        return;
+    };
+    let mut canvas = frame.into_rgb8();
+    draw_border(&mut canvas, RED);
+    let notice = format!("no frame for over {} ms", stale.as_millis());
+    draw_lines(&mut canvas, &[(notice, RED)]);
+    let _ = window.set_image(topic_name, image::DynamicImage::ImageRgb8(canvas));
+}
+
+/// Steps through `history` while paused and shows the frame reached, labelled with how
+/// far back it is and its stamp. `s` then saves that frame.
+fn show_buffered(
+    window: &WindowProxy,
+    topic_name: &str,
+    latest_frame: &Mutex<Option<image::DynamicImage>>,
+    history: &mut FrameHistory,
+    steps: i64,
+) {
+    let len = history.len();
+    let Some((frame, header, back)) = history.step(steps) else {
+        return;
+    };
+    *latest_frame.lock().unwrap() = Some(frame.clone());
+    let mut canvas = frame.to_rgb8();
+    let lines = [
+        (format!("PAUSED -{}/{}", back, len - 1), RED),
+        (format!("{}.{:09}", header.sec, header.nanosec), RED),
+    ];
+    draw_lines(&mut canvas, &lines);
+    let _ = window.set_image(topic_name, image::DynamicImage::ImageRgb8(canvas));
+}
+
+/// Writes `frame` to a PNG in the working directory named after the topic and the
+/// current time.
+pub(crate) fn save_frame(topic_name: &str, frame: &image::DynamicImage) {
+    let path = capture_path(topic_name, "png");
+    match frame.save(&path) {
+        Ok(()) => info!("Saved {}", path),
+        Err(e) => error!("Failed to save {}: {}", path, e),
+    }
+}
+
+/// `<topic>_<unix time>.<extension>` in the working directory.
+fn capture_path(topic_name: &str, extension: &str) -> String {
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    format!(
+        "{}_{}.{:03}.{}",
+        topic_name.trim_start_matches('/').replace('/', "_"),
+        stamp.as_secs(),
+        stamp.subsec_millis(),
+        extension
+    )
+}
+
+/// Window size for an `img_w` x `img_h` frame under the window settings of `config`,
+/// kept within `screen_size` when it is known.
+pub(crate) fn window_size_for(config: &PreviewConfig, img_w: u32, img_h: u32) -> (u32, u32) {
+    let size = window_size(img_w, img_h, config.window_width, config.max_window_height);
+    match config.screen_size {
+        Some(screen) => fit_within(size, screen),
+        None => size,
+    }
+}
+
+/// Shrinks `(w, h)` proportionally until it fits in `(max_w, max_h)`.
+fn fit_within((w, h): (u32, u32), (max_w, max_h): (u32, u32)) -> (u32, u32) {
+    if w <= max_w && h <= max_h {
+        return (w, h);
+    }
+    let scale = (max_w as f64 / w as f64).min(max_h as f64 / h as f64);
+    (
+        ((w as f64 * scale) as u32).max(1),
+        ((h as f64 * scale) as u32).max(1),
+    )
+}
+
+/// Scales the image to `window_w` wide, shrinking both sides proportionally if the result
+/// would be taller than `max_window_h`. A frame without pixels gets a square window.
+fn window_size(img_w: u32, img_h: u32, window_w: u32, max_window_h: Option<u32>) -> (u32, u32) {
+    if img_w == 0 || img_h == 0 {
+        let side = max_window_h.map_or(window_w, |max_h| max_h.min(window_w));
+        return (side, side);
+    }
+    let window_h = scale_side(img_h, window_w, img_w);
+    match max_window_h {
+        Some(max_h) if window_h > max_h => (scale_side(img_w, max_h, img_h), max_h),
+        _ => (window_w, window_h),
+    }
+}
+
+/// `side * to / from` without overflowing, and at least 1 so an extreme aspect ratio
+/// still gets a window.
+fn scale_side(side: u32, to: u32, from: u32) -> u32 {
+    (side as u64 * to as u64 / from as u64).clamp(1, u32::MAX as u64) as u32
+}
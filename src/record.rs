@@ -0,0 +1,266 @@
+use crate::msg::Header;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::imageops::FilterType;
+use image::{Delay, Frame, RgbaImage};
+use log::{error, info};
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Frames kept for a GIF are shrunk to at most this width to keep the file small.
+const GIF_MAX_WIDTH: u32 = 480;
+
+/// Pipes frames as raw RGB to an `ffmpeg` subprocess that encodes them into a video file.
+/// Without a fixed frame rate every frame is stamped with its arrival time, so the video
+/// keeps the real timing of the stream.
+pub(crate) struct VideoRecorder {
+    path: PathBuf,
+    size: (u32, u32),
+    child: Child,
+    stdin: Option<ChildStdin>,
+}
+
+impl VideoRecorder {
+    pub(crate) fn start(path: &Path, size: (u32, u32), fps: Option<f64>) -> std::io::Result<Self> {
+        let mut command = Command::new("ffmpeg");
+        command.args([
+            "-loglevel",
+            "error",
+            "-y",
+            "-f",
+            "rawvideo",
+            "-pix_fmt",
+            "rgb24",
+        ]);
+        command.args(["-video_size", &format!("{}x{}", size.0, size.1)]);
+        match fps {
+            Some(fps) => command.args(["-framerate", &fps.to_string()]),
+            None => command.args(["-use_wallclock_as_timestamps", "1"]),
+        };
+        command.args(["-i", "-"]);
+        if fps.is_none() {
+            command.args(["-fps_mode", "passthrough"]);
+        }
+        // yuv420p needs even dimensions.
+        command.args([
+            "-vf",
+            "pad=ceil(iw/2)*2:ceil(ih/2)*2",
+            "-pix_fmt",
+            "yuv420p",
+        ]);
+        command.arg(path);
+        let mut child = command.stdin(Stdio::piped()).spawn()?;
+        let stdin = child.stdin.take();
+        Ok(VideoRecorder {
+            path: path.to_path_buf(),
+            size,
+            child,
+            stdin,
+        })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends `frame`. The encoder cannot change resolution mid-file, so a frame of a
+    /// different size is an error.
+    pub(crate) fn write(&mut self, frame: &image::DynamicImage) -> std::io::Result<()> {
+        let size = (frame.width(), frame.height());
+        if size != self.size {
+            return Err(std::io::Error::other(format!(
+                "frame size changed from {}x{} to {}x{}",
+                self.size.0, self.size.1, size.0, size.1
+            )));
+        }
+        let stdin = self
+            .stdin
+            .as_mut()
+            .ok_or_else(|| std::io::Error::other("encoder already closed"))?;
+        stdin.write_all(frame.to_rgb8().as_raw())
+    }
+}
+
+impl Drop for VideoRecorder {
+    fn drop(&mut self) {
+        // Closing stdin tells ffmpeg to finish the file.
+        drop(self.stdin.take());
+        let _ = self.child.wait();
+    }
+}
+
+/// Where `topic_name` is recorded when `path` is shared by several topics: the topic is
+/// appended to the file stem, e.g. `out_camera_left.mp4`.
+pub(crate) fn topic_record_path(path: &Path, topic_name: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let topic = topic_name.trim_start_matches('/').replace('/', "_");
+    let file_name = match path.extension() {
+        Some(ext) => format!("{}_{}.{}", stem, topic, ext.to_string_lossy()),
+        None => format!("{}_{}", stem, topic),
+    };
+    path.with_file_name(file_name)
+}
+
+/// Writes frames as `000001.png`, `000002.png`, … into a directory together with a
+/// `timestamps.csv` of header stamps and arrival times, for encoding later.
+pub(crate) struct SequenceWriter {
+    dir: PathBuf,
+    csv: std::fs::File,
+    next_index: u64,
+}
+
+impl SequenceWriter {
+    /// Creates `dir` if needed. An existing non-empty directory is refused unless `force`,
+    /// so a previous sequence is not silently overwritten.
+    pub(crate) fn create(dir: &Path, force: bool) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        if !force && std::fs::read_dir(dir)?.next().is_some() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                "directory is not empty, pass --force to overwrite",
+            ));
+        }
+        let mut csv = std::fs::File::create(dir.join("timestamps.csv"))?;
+        writeln!(csv, "frame,header_stamp,arrival_time")?;
+        Ok(SequenceWriter {
+            dir: dir.to_path_buf(),
+            csv,
+            next_index: 1,
+        })
+    }
+
+    pub(crate) fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Writes the next frame. `stamp` and `arrival` are seconds since the Unix epoch.
+    pub(crate) fn write(
+        &mut self,
+        frame: &image::DynamicImage,
+        stamp: f64,
+        arrival: f64,
+    ) -> Result<(), image::ImageError> {
+        let file_name = format!("{:06}.png", self.next_index);
+        frame.save(self.dir.join(&file_name))?;
+        writeln!(self.csv, "{},{:.9},{:.9}", file_name, stamp, arrival)?;
+        self.next_index += 1;
+        Ok(())
+    }
+}
+
+/// The last few seconds of frames at a reduced rate and resolution, kept so a short GIF
+/// can be written on demand.
+pub(crate) struct GifBuffer {
+    frames: VecDeque<(Instant, RgbaImage)>,
+    length: Duration,
+    interval: Duration,
+}
+
+impl GifBuffer {
+    pub(crate) fn new(length: Duration, fps: f64) -> Self {
+        GifBuffer {
+            frames: VecDeque::new(),
+            length,
+            interval: Duration::from_secs_f64(1.0 / fps),
+        }
+    }
+
+    /// Keeps `frame` if at least one GIF frame interval has passed since the last one, and
+    /// forgets frames older than the buffer length.
+    pub(crate) fn push(&mut self, now: Instant, frame: &image::DynamicImage) {
+        if self
+            .frames
+            .back()
+            .is_some_and(|(t, _)| now - *t < self.interval)
+        {
+            return;
+        }
+        let frame = if frame.width() > GIF_MAX_WIDTH {
+            let height =
+                (frame.height() as u64 * GIF_MAX_WIDTH as u64 / frame.width() as u64).max(1) as u32;
+            frame.resize_exact(GIF_MAX_WIDTH, height, FilterType::Triangle)
+        } else {
+            frame.clone()
+        };
+        self.frames.push_back((now, frame.into_rgba8()));
+        while self
+            .frames
+            .front()
+            .is_some_and(|(t, _)| now - *t > self.length)
+        {
+            self.frames.pop_front();
+        }
+    }
+
+    /// Encodes the buffered frames to `path` on a separate thread, so the preview keeps
+    /// running while the GIF is quantized.
+    pub(crate) fn save(&self, path: PathBuf) {
+        let frames: Vec<RgbaImage> = self.frames.iter().map(|(_, f)| f.clone()).collect();
+        if frames.is_empty() {
+            return;
+        }
+        let delay = Delay::from_saturating_duration(self.interval);
+        std::thread::spawn(move || {
+            let result = std::fs::File::create(&path)
+                .map_err(image::ImageError::from)
+                .and_then(|file| {
+                    let mut encoder = GifEncoder::new(std::io::BufWriter::new(file));
+                    encoder.set_repeat(Repeat::Infinite)?;
+                    encoder.encode_frames(
+                        frames
+                            .into_iter()
+                            .map(|frame| Frame::from_parts(frame, 0, 0, delay)),
+                    )
+                });
+            match result {
+                Ok(()) => info!("Saved {}", path.display()),
+                Err(e) => error!("Failed to save {}: {}", path.display(), e),
+            }
+        });
+    }
+}
+
+/// The last `capacity` displayed frames with their headers, so a paused preview can step
+/// back through a burst. `back` is the shown frame, counted back from the newest.
+pub(crate) struct FrameHistory {
+    frames: VecDeque<(image::DynamicImage, Header)>,
+    capacity: usize,
+    back: usize,
+}
+
+impl FrameHistory {
+    pub(crate) fn new(capacity: usize) -> Self {
+        FrameHistory {
+            frames: VecDeque::with_capacity(capacity),
+            capacity,
+            back: 0,
+        }
+    }
+
+    /// Adds the newest frame, dropping the oldest when full, and returns to it.
+    pub(crate) fn push(&mut self, frame: &image::DynamicImage, header: &Header) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back((frame.clone(), header.clone()));
+        self.back = 0;
+    }
+
+    /// Moves `steps` frames towards the newest, or towards the oldest when negative, and
+    /// returns the frame there with its header and how far back it is.
+    pub(crate) fn step(&mut self, steps: i64) -> Option<(&image::DynamicImage, &Header, usize)> {
+        let oldest = self.frames.len().checked_sub(1)?;
+        self.back = (self.back as i64 - steps).clamp(0, oldest as i64) as usize;
+        let (frame, header) = &self.frames[oldest - self.back];
+        Some((frame, header, self.back))
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.frames.len()
+    }
+}
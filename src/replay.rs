@@ -0,0 +1,139 @@
+use crate::config::PreviewConfig;
+use crate::convert::{ConvertOptions, PreviewError, PreviewImage};
+use crate::msg::Header;
+use crate::preview::{run_until_shutdown, show_topic, Companions};
+use async_io::Timer;
+use futures::stream::{self, StreamExt};
+use futures::{future, Future, FutureExt};
+use log::info;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// One frame of a sequence written with `sequence_dir`, read from disk when it is
+/// converted so the PNG is decoded on the decoder thread.
+struct ReplayFrame {
+    header: Header,
+    path: PathBuf,
+}
+
+impl PreviewImage for ReplayFrame {
+    fn to_image(&self, _options: &ConvertOptions) -> Result<image::DynamicImage, PreviewError> {
+        image::open(&self.path).map_err(PreviewError::Decode)
+    }
+
+    fn header(&self) -> &Header {
+        &self.header
+    }
+
+    fn as_str() -> &'static str {
+        "ReplayFrame"
+    }
+
+    fn encoded_len(&self) -> usize {
+        std::fs::metadata(&self.path).map_or(0, |m| m.len() as usize)
+    }
+}
+
+/// A row of `timestamps.csv`: the frame file, its header stamp and when it arrived, in
+/// seconds since the Unix epoch.
+struct Entry {
+    file: String,
+    stamp: f64,
+    arrival: f64,
+}
+
+/// Plays back a sequence written with `sequence_dir` in a window, without ROS: the frames
+/// are shown with the spacing they arrived with, divided by `speed`, and go through the
+/// same render path as a live topic, so pausing, stepping, saving and recording work the
+/// same way. The last frame stays up until the window is closed.
+pub fn replay_sequence(
+    dir: &Path,
+    speed: f64,
+    config: PreviewConfig,
+) -> std::io::Result<impl Future<Output = ()>> {
+    let entries = Rc::new(read_timestamps(&dir.join("timestamps.csv"))?);
+    if entries.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "the sequence has no frames",
+        ));
+    }
+    info!("{}: replaying {} frames", dir.display(), entries.len());
+    let name = dir.display().to_string();
+    let subscribe = {
+        let dir = dir.to_path_buf();
+        let name = name.clone();
+        move || {
+            let start = Instant::now();
+            let first = entries[0].arrival;
+            let dir = dir.clone();
+            let frames = stream::iter(0..entries.len()).then({
+                let entries = entries.clone();
+                move |index| {
+                    let entry = &entries[index];
+                    let offset = ((entry.arrival - first) / speed).max(0.0);
+                    let due = start + Duration::from_secs_f64(offset);
+                    let frame = ReplayFrame {
+                        header: Header {
+                            sec: entry.stamp.floor() as i32,
+                            nanosec: (entry.stamp.fract() * 1e9) as u32,
+                            frame_id: entry.file.clone(),
+                        },
+                        path: dir.join(&entry.file),
+                    };
+                    async move {
+                        Timer::at(due).await;
+                        Ok(frame)
+                    }
+                }
+            });
+            let name = name.clone();
+            let hold = stream::once(async move {
+                info!("{}: end of the sequence", name);
+                future::pending().await
+            });
+            frames.chain(hold).boxed_local()
+        }
+    };
+    let config = Rc::new(config);
+    let companions = Companions::without_topics(&config);
+    let window = show_topic(subscribe, None, None, &name, config.clone(), companions);
+    Ok(run_until_shutdown(
+        window.boxed_local(),
+        config.shutdown.clone(),
+    ))
+}
+
+fn read_timestamps(path: &Path) -> std::io::Result<Vec<Entry>> {
+    let text = std::fs::read_to_string(path)?;
+    text.lines()
+        .enumerate()
+        .skip(1)
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let invalid = || {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "{}:{}: expected frame,header_stamp,arrival_time",
+                        path.display(),
+                        i + 1
+                    ),
+                )
+            };
+            let mut fields = line.split(',').map(str::trim);
+            let (Some(file), Some(stamp), Some(arrival)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(invalid());
+            };
+            Ok(Entry {
+                file: file.to_string(),
+                stamp: stamp.parse().map_err(|_| invalid())?,
+                arrival: arrival.parse().map_err(|_| invalid())?,
+            })
+        })
+        .collect()
+}
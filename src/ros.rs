@@ -0,0 +1,320 @@
+use clap::ValueEnum;
+use log::warn;
+use ros2_client::ros2::{policy, QosPolicies, QosPolicyBuilder};
+use rustdds::{DomainParticipantStatusEvent, RTPSEntity, StatusEvented, GUID};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// Applies the first `from:=to` rule in `remaps` whose `from` names `topic_name`. Names are
+/// compared as absolute, so `image` and `/image` match each other.
+pub fn remap_topic<'a>(remaps: &'a [(String, String)], topic_name: &'a str) -> &'a str {
+    let absolute = |name: &str| name.trim_start_matches('/').to_string();
+    remaps
+        .iter()
+        .find(|(from, _)| absolute(from) == absolute(topic_name))
+        .map_or(topic_name, |(_, to)| to.as_str())
+}
+
+/// Parses a topic given on the command line, treating names without a leading slash as
+/// absolute so they do not pick up the node's namespace.
+pub(crate) fn topic_name_to_ros(
+    topic_name: &str,
+) -> Result<ros2_client::Name, ros2_client::names::NameError> {
+    if topic_name.starts_with('/') {
+        ros2_client::Name::parse(topic_name)
+    } else {
+        ros2_client::Name::parse(&format!("/{}", topic_name))
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ImageType {
+    /// sensor_msgs/msg/Image
+    Raw,
+    /// sensor_msgs/msg/CompressedImage
+    Compressed,
+}
+
+impl ImageType {
+    /// Maps a discovered DDS type name, e.g. `sensor_msgs::msg::dds_::Image_`, to an image type.
+    pub fn from_dds_type(type_name: &str) -> Option<Self> {
+        match type_name {
+            "sensor_msgs::msg::dds_::Image_" => Some(ImageType::Raw),
+            "sensor_msgs::msg::dds_::CompressedImage_" => Some(ImageType::Compressed),
+            _ => None,
+        }
+    }
+
+    /// Guesses the message type from the image_transport naming convention.
+    pub fn from_topic(topic_name: &str) -> Self {
+        if topic_name.ends_with("/compressed") {
+            ImageType::Compressed
+        } else {
+            ImageType::Raw
+        }
+    }
+}
+
+/// Looks up the declared types of `topic_names` in the DDS discovery data, waiting up to
+/// `timeout` in all for their publishers to be discovered. Each entry is `None` if that
+/// topic is not advertised as an image or is advertised with conflicting types.
+pub fn discover_image_types(
+    context: &ros2_client::Context,
+    topic_names: &[&str],
+    timeout: Duration,
+) -> Vec<Option<ImageType>> {
+    let deadline = Instant::now() + timeout;
+    let mut found: Vec<Option<ImageType>> = vec![None; topic_names.len()];
+    // Topics still being looked for, with their DDS names.
+    let mut pending: Vec<(usize, String)> = topic_names
+        .iter()
+        .enumerate()
+        .filter_map(|(i, name)| Some((i, format!("rt{}", topic_name_to_ros(name).ok()?))))
+        .collect();
+    loop {
+        let discovered = context.discovered_topics();
+        pending.retain(|(i, dds_name)| {
+            let mut types: Vec<ImageType> = discovered
+                .iter()
+                .filter(|t| t.topic_name() == dds_name)
+                .filter_map(|t| ImageType::from_dds_type(t.type_name()))
+                .collect();
+            types.sort_unstable();
+            types.dedup();
+            match types.as_slice() {
+                [image_type] => {
+                    found[*i] = Some(*image_type);
+                    false
+                }
+                [] => true,
+                _ => {
+                    warn!("{} is advertised with several image types", topic_names[*i]);
+                    false
+                }
+            }
+        });
+        if pending.is_empty() || Instant::now() >= deadline {
+            return found;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reliability {
+    Reliable,
+    BestEffort,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Durability {
+    Volatile,
+    /// Also receive the last messages a latched publisher sent before we subscribed.
+    TransientLocal,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum History {
+    /// Queue only the newest `history_depth` messages.
+    KeepLast,
+    /// Queue every message until it is read, so no frame of a slow topic is missed.
+    KeepAll,
+}
+
+/// How long a reliable reader may block waiting for retransmits, unless configured.
+pub(crate) const DEFAULT_MAX_BLOCKING_TIME: Duration = Duration::from_millis(100);
+
+/// Subscription QoS. `history_depth` only applies to [`History::KeepLast`] and
+/// `max_blocking_time` only to [`Reliability::Reliable`]; best-effort readers never wait
+/// for retransmits.
+pub fn build_qos(
+    reliability: Reliability,
+    durability: Durability,
+    history: History,
+    history_depth: i32,
+    max_blocking_time: Duration,
+) -> QosPolicies {
+    QosPolicyBuilder::new()
+        .history(match history {
+            History::KeepLast => policy::History::KeepLast {
+                depth: history_depth,
+            },
+            History::KeepAll => policy::History::KeepAll,
+        })
+        .reliability(reliability_policy(reliability, max_blocking_time))
+        .durability(durability_policy(durability))
+        .build()
+}
+
+/// QoS setting only the given policies, for overriding those of [`build_qos`] on one topic
+/// with [`QosPolicies::modify_by`].
+pub fn qos_override(
+    reliability: Option<Reliability>,
+    durability: Option<Durability>,
+    max_blocking_time: Duration,
+) -> QosPolicies {
+    let mut builder = QosPolicyBuilder::new();
+    if let Some(reliability) = reliability {
+        builder = builder.reliability(reliability_policy(reliability, max_blocking_time));
+    }
+    if let Some(durability) = durability {
+        builder = builder.durability(durability_policy(durability));
+    }
+    builder.build()
+}
+
+fn reliability_policy(
+    reliability: Reliability,
+    max_blocking_time: Duration,
+) -> policy::Reliability {
+    match reliability {
+        Reliability::Reliable => policy::Reliability::Reliable {
+            max_blocking_time: max_blocking_time.into(),
+        },
+        Reliability::BestEffort => policy::Reliability::BestEffort,
+    }
+}
+
+fn durability_policy(durability: Durability) -> policy::Durability {
+    match durability {
+        Durability::Volatile => policy::Durability::Volatile,
+        Durability::TransientLocal => policy::Durability::TransientLocal,
+    }
+}
+
+/// Waits `wait` for discovery, then prints every advertised Image/CompressedImage topic
+/// with its current publisher count.
+pub fn list_image_topics(context: &ros2_client::Context, wait: Duration) {
+    let listener = context.domain_participant().status_listener();
+    let mut topics: BTreeMap<String, (ImageType, HashSet<GUID>)> = BTreeMap::new();
+    let deadline = Instant::now() + wait;
+    while Instant::now() < deadline {
+        while let Some(event) = listener.try_recv_status() {
+            match event {
+                DomainParticipantStatusEvent::WriterDetected { writer } => {
+                    if let Some(image_type) = ImageType::from_dds_type(&writer.type_name) {
+                        topics
+                            .entry(writer.topic_name)
+                            .or_insert_with(|| (image_type, HashSet::new()))
+                            .1
+                            .insert(writer.guid);
+                    }
+                }
+                DomainParticipantStatusEvent::WriterLost { guid, .. } => {
+                    topics.values_mut().for_each(|(_, writers)| {
+                        writers.remove(&guid);
+                    });
+                }
+                _ => {}
+            }
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    for topic in context.discovered_topics() {
+        if let Some(image_type) = ImageType::from_dds_type(topic.type_name()) {
+            topics
+                .entry(topic.topic_name().clone())
+                .or_insert_with(|| (image_type, HashSet::new()));
+        }
+    }
+    for (dds_name, (image_type, writers)) in topics {
+        let type_name = match image_type {
+            ImageType::Raw => "sensor_msgs/msg/Image",
+            ImageType::Compressed => "sensor_msgs/msg/CompressedImage",
+        };
+        println!(
+            "{} [{}] {} publisher(s)",
+            dds_name.strip_prefix("rt").unwrap_or(&dds_name),
+            type_name,
+            writers.len()
+        );
+    }
+}
+
+/// How long a subscription may go without a matched publisher before we say so.
+const NO_PUBLISHER_WARN_AFTER: Duration = Duration::from_secs(5);
+
+/// DDS topics of the node itself, which are not worth a diagnostic.
+const INTERNAL_TOPICS: [&str; 3] = ["rt/rosout", "rt/parameter_events", "ros_discovery_info"];
+
+/// Watches the participant's discovery events and explains why a subscription shows
+/// nothing: a publisher whose QoS cannot match ours, or no publisher at all after
+/// [`NO_PUBLISHER_WARN_AFTER`]. Must be created before the subscriptions so their
+/// discovery events are seen; never resolves.
+pub(crate) fn watch_matches(context: &ros2_client::Context) -> impl Future<Output = ()> {
+    let participant = context.domain_participant();
+    let listener = participant.status_listener();
+    let prefix = participant.guid().prefix;
+    async move {
+        // Local readers still waiting for a publisher, by topic, and when they appeared.
+        let mut waiting: HashMap<GUID, (String, Instant)> = HashMap::new();
+        let mut warned: HashSet<String> = HashSet::new();
+        loop {
+            while let Some(event) = listener.try_recv_status() {
+                match event {
+                    DomainParticipantStatusEvent::ReaderDetected { reader }
+                        if reader.guid.prefix == prefix
+                            && !INTERNAL_TOPICS.contains(&reader.topic_name.as_str()) =>
+                    {
+                        let topic = reader.topic_name;
+                        let topic = topic.strip_prefix("rt").unwrap_or(&topic).to_string();
+                        waiting.insert(reader.guid, (topic, Instant::now()));
+                    }
+                    DomainParticipantStatusEvent::RemoteWriterMatched { local_reader, .. } => {
+                        waiting.remove(&local_reader);
+                    }
+                    DomainParticipantStatusEvent::RemoteWriterQosIncompatible {
+                        local_reader,
+                        requested_qos,
+                        offered_qos,
+                        ..
+                    } => {
+                        if let Some((topic, _)) = waiting.get(&local_reader) {
+                            if warned.insert(topic.clone()) {
+                                warn!(
+                                    "{}: a publisher was found but its QoS does not match ours; {}",
+                                    topic,
+                                    qos_hint(&requested_qos, &offered_qos)
+                                );
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            let now = Instant::now();
+            for (topic, since) in waiting.values() {
+                if now - *since >= NO_PUBLISHER_WARN_AFTER && warned.insert(topic.clone()) {
+                    warn!(
+                        "{}: no publisher matched after {} s; check the name with --list, \
+                         or try --qos best-effort if the publisher is best-effort",
+                        topic,
+                        NO_PUBLISHER_WARN_AFTER.as_secs()
+                    );
+                }
+            }
+            async_io::Timer::after(Duration::from_millis(50)).await;
+        }
+    }
+}
+
+/// Suggests the flag that would make a reader with `requested` QoS match a writer
+/// offering `offered`.
+fn qos_hint(requested: &QosPolicies, offered: &QosPolicies) -> String {
+    let reliable = |qos: &QosPolicies| {
+        matches!(
+            qos.reliability(),
+            Some(policy::Reliability::Reliable { .. })
+        )
+    };
+    let transient =
+        |qos: &QosPolicies| !matches!(qos.durability(), None | Some(policy::Durability::Volatile));
+    if reliable(requested) && !reliable(offered) {
+        "it publishes best-effort, try --qos best-effort".to_string()
+    } else if transient(requested) && !transient(offered) {
+        "it is not latched, try --durability volatile".to_string()
+    } else {
+        format!("requested {:?}, offered {:?}", requested, offered)
+    }
+}
@@ -0,0 +1,141 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::RgbImage;
+use log::{debug, info, warn};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+
+/// JPEG quality of the streamed frames.
+const JPEG_QUALITY: u8 = 80;
+
+/// Serves the frames of every topic as an MJPEG stream over HTTP, for viewing in a
+/// browser: `/` streams the first topic and `/<topic>` any of them. Each client gets its
+/// own thread that encodes the newest frame whenever one arrives, so a slow client only
+/// skips frames.
+pub(crate) struct MjpegServer {
+    feeds: Vec<(String, Arc<Feed>)>,
+}
+
+/// Latest frame of one topic and the number of clients watching it.
+#[derive(Default)]
+struct Feed {
+    frame: Mutex<(u64, Option<Arc<RgbImage>>)>,
+    updated: Condvar,
+    clients: AtomicUsize,
+}
+
+impl MjpegServer {
+    /// Listens on `port` on all interfaces.
+    pub(crate) fn start(port: u16, topics: &[String]) -> std::io::Result<Arc<Self>> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        let server = Arc::new(MjpegServer {
+            feeds: topics
+                .iter()
+                .map(|topic| (topic.clone(), Arc::default()))
+                .collect(),
+        });
+        info!("Serving MJPEG on http://0.0.0.0:{}/", port);
+        let accepting = server.clone();
+        std::thread::Builder::new()
+            .name("mjpeg accept".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    let Ok(stream) = stream else {
+                        continue;
+                    };
+                    let server = accepting.clone();
+                    std::thread::spawn(move || {
+                        if let Err(e) = server.handle(stream) {
+                            debug!("mjpeg client disconnected: {}", e);
+                        }
+                    });
+                }
+            })?;
+        Ok(server)
+    }
+
+    /// Makes `frame` the newest frame of `topic_name`. Cheap while nobody is watching.
+    pub(crate) fn publish(&self, topic_name: &str, frame: &image::DynamicImage) {
+        let Some((_, feed)) = self.feeds.iter().find(|(t, _)| t == topic_name) else {
+            return;
+        };
+        if feed.clients.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let frame = Arc::new(frame.to_rgb8());
+        let mut latest = feed.frame.lock().unwrap();
+        *latest = (latest.0 + 1, Some(frame));
+        feed.updated.notify_all();
+    }
+
+    fn handle(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request = String::new();
+        reader.read_line(&mut request)?;
+        // The headers are not needed, but have to be read before replying.
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 2 {
+            header.clear();
+        }
+        let path = request.split_whitespace().nth(1).unwrap_or("/");
+        let feed = match path.trim_end_matches('/') {
+            "" => self.feeds.first(),
+            path => self.feeds.iter().find(|(topic, _)| topic == path),
+        };
+        let Some((topic_name, feed)) = feed else {
+            let topics: Vec<&str> = self.feeds.iter().map(|(t, _)| t.as_str()).collect();
+            let body = format!("unknown topic {}, serving {}\n", path, topics.join(", "));
+            write!(
+                stream,
+                "HTTP/1.0 404 Not Found\r\nContent-Type: text/plain\r\n\
+                 Content-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            )?;
+            return Ok(());
+        };
+        info!(
+            "{}: mjpeg client {} connected",
+            topic_name,
+            stream.peer_addr()?
+        );
+        write!(
+            stream,
+            "HTTP/1.0 200 OK\r\nContent-Type: multipart/x-mixed-replace; boundary=frame\r\n\
+             Cache-Control: no-cache\r\n\r\n"
+        )?;
+        feed.clients.fetch_add(1, Ordering::Relaxed);
+        let result = stream_frames(&mut stream, feed);
+        feed.clients.fetch_sub(1, Ordering::Relaxed);
+        result
+    }
+}
+
+/// Writes every new frame of `feed` as one JPEG part until the client goes away.
+fn stream_frames(stream: &mut TcpStream, feed: &Feed) -> std::io::Result<()> {
+    let mut shown = 0;
+    loop {
+        let frame = {
+            let mut latest = feed.frame.lock().unwrap();
+            while latest.0 == shown || latest.1.is_none() {
+                latest = feed.updated.wait(latest).unwrap();
+            }
+            shown = latest.0;
+            latest.1.clone().unwrap()
+        };
+        let mut jpeg = Vec::new();
+        if let Err(e) = JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY).encode_image(&*frame)
+        {
+            warn!("mjpeg: cannot encode frame: {}", e);
+            continue;
+        }
+        write!(
+            stream,
+            "--frame\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+            jpeg.len()
+        )?;
+        stream.write_all(&jpeg)?;
+        stream.write_all(b"\r\n")?;
+    }
+}
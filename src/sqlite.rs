@@ -0,0 +1,312 @@
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Leading magic of a sqlite3 database file.
+const SQLITE_MAGIC: &[u8; 16] = b"SQLite format 3\0";
+
+/// Page types of a table b-tree.
+const INTERIOR_TABLE_PAGE: u8 = 0x05;
+const LEAF_TABLE_PAGE: u8 = 0x0d;
+
+/// A column value of a table row.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+/// Read-only access to the tables of a sqlite3 database file, enough to index and read a
+/// rosbag2 bag without linking sqlite. Only the main file is read: rows still in a `-wal`
+/// file of a bag that was not closed cleanly are missed.
+pub(crate) struct Database {
+    file: File,
+    page_size: usize,
+    /// Bytes of each page not reserved for extensions.
+    usable: usize,
+}
+
+impl Database {
+    pub(crate) fn open(path: &Path) -> std::io::Result<Self> {
+        let mut file = File::open(path)?;
+        let mut header = [0; 100];
+        file.read_exact(&mut header)
+            .map_err(|_| invalid("not a sqlite3 database"))?;
+        if &header[..16] != SQLITE_MAGIC {
+            return Err(invalid("not a sqlite3 database"));
+        }
+        // 1 stands for 65536, which does not fit the field.
+        let page_size = match u16::from_be_bytes([header[16], header[17]]) {
+            1 => 65536,
+            size => size as usize,
+        };
+        let usable = page_size.saturating_sub(header[20] as usize);
+        if usable < 480 {
+            return Err(invalid("invalid page size"));
+        }
+        // Text is only read as UTF-8; 0 means the database has no text yet.
+        if !matches!(
+            u32::from_be_bytes(header[56..60].try_into().unwrap()),
+            0 | 1
+        ) {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "only UTF-8 databases can be read",
+            ));
+        }
+        Ok(Database {
+            file,
+            page_size,
+            usable,
+        })
+    }
+
+    /// The root page of the table `name`, from the schema table on page 1.
+    pub(crate) fn table(&mut self, name: &str) -> std::io::Result<Option<u32>> {
+        let mut root = None;
+        // The schema table has the columns type, name, tbl_name, rootpage and sql.
+        self.scan(1, 4, |_, values| {
+            if let [Value::Text(kind), Value::Text(table), _, Value::Integer(page)] = &*values {
+                if kind == "table" && table == name {
+                    root = u32::try_from(*page).ok();
+                }
+            }
+            Ok(())
+        })?;
+        Ok(root)
+    }
+
+    /// Calls `row` with the rowid and the first `columns` values of every row of the table
+    /// whose b-tree starts at page `root`, in rowid order. Overflow pages are only read
+    /// when the columns reach into them, so leading columns of rows with large blobs are
+    /// cheap to scan.
+    pub(crate) fn scan(
+        &mut self,
+        root: u32,
+        columns: usize,
+        mut row: impl FnMut(i64, Vec<Value>) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        // Pages left to visit, the next one last.
+        let mut pending = vec![root];
+        while let Some(number) = pending.pop() {
+            let page = self.page(number)?;
+            let header = header_offset(number);
+            let cells = cell_offsets(&page, header)?;
+            match page[header] {
+                INTERIOR_TABLE_PAGE => {
+                    pending.push(be_u32(&page, header + 8)?);
+                    for &cell in cells.iter().rev() {
+                        pending.push(be_u32(&page, cell)?);
+                    }
+                }
+                LEAF_TABLE_PAGE => {
+                    for cell in cells {
+                        let (rowid, values) = self.leaf_cell(&page, cell, columns)?;
+                        row(rowid, values)?;
+                    }
+                }
+                _ => return Err(invalid("not a table b-tree page")),
+            }
+        }
+        Ok(())
+    }
+
+    /// All values of row `rowid` of the table whose b-tree starts at page `root`.
+    pub(crate) fn row(&mut self, root: u32, rowid: i64) -> std::io::Result<Option<Vec<Value>>> {
+        let mut number = root;
+        loop {
+            let page = self.page(number)?;
+            let header = header_offset(number);
+            let cells = cell_offsets(&page, header)?;
+            match page[header] {
+                INTERIOR_TABLE_PAGE => {
+                    // The first child whose largest rowid is not below `rowid`, else the
+                    // right-most one.
+                    number = be_u32(&page, header + 8)?;
+                    for cell in cells {
+                        let (key, _) = varint(&page, cell + 4)?;
+                        if key >= rowid {
+                            number = be_u32(&page, cell)?;
+                            break;
+                        }
+                    }
+                }
+                LEAF_TABLE_PAGE => {
+                    for cell in cells {
+                        let (key, values) = self.leaf_cell(&page, cell, usize::MAX)?;
+                        if key == rowid {
+                            return Ok(Some(values));
+                        }
+                    }
+                    return Ok(None);
+                }
+                _ => return Err(invalid("not a table b-tree page")),
+            }
+        }
+    }
+
+    fn page(&mut self, number: u32) -> std::io::Result<Vec<u8>> {
+        if number == 0 {
+            return Err(invalid("page number 0"));
+        }
+        let mut page = vec![0; self.page_size];
+        self.file
+            .seek(SeekFrom::Start((number as u64 - 1) * self.page_size as u64))?;
+        self.file.read_exact(&mut page)?;
+        Ok(page)
+    }
+
+    /// The rowid and the first `columns` values of the leaf cell at offset `cell`.
+    fn leaf_cell(
+        &mut self,
+        page: &[u8],
+        cell: usize,
+        columns: usize,
+    ) -> std::io::Result<(i64, Vec<Value>)> {
+        let (size, len) = varint(page, cell)?;
+        let (rowid, rowid_len) = varint(page, cell + len)?;
+        let size = usize::try_from(size).map_err(|_| invalid("invalid payload size"))?;
+        let start = cell + len + rowid_len;
+        let local = self.local_size(size);
+        let mut payload = page
+            .get(start..start + local)
+            .ok_or_else(|| invalid("cell past the end of its page"))?
+            .to_vec();
+        if local < size {
+            if let Some(values) = record(&payload, columns)? {
+                return Ok((rowid, values));
+            }
+            let mut next = be_u32(page, start + local)?;
+            while payload.len() < size {
+                let overflow = self.page(next)?;
+                next = be_u32(&overflow, 0)?;
+                let take = (self.usable - 4).min(size - payload.len());
+                payload.extend_from_slice(&overflow[4..4 + take]);
+            }
+        }
+        let values = record(&payload, columns)?.ok_or_else(|| invalid("truncated record"))?;
+        Ok((rowid, values))
+    }
+
+    /// How many bytes of a `size` byte payload are stored in its leaf cell; the rest is on
+    /// overflow pages.
+    fn local_size(&self, size: usize) -> usize {
+        let max_local = self.usable - 35;
+        if size <= max_local {
+            return size;
+        }
+        let min_local = (self.usable - 12) * 32 / 255 - 23;
+        let local = min_local + (size - min_local) % (self.usable - 4);
+        if local <= max_local {
+            local
+        } else {
+            min_local
+        }
+    }
+}
+
+/// Page 1 starts with the 100 byte database header.
+fn header_offset(number: u32) -> usize {
+    if number == 1 {
+        100
+    } else {
+        0
+    }
+}
+
+/// The offsets of the cells of the b-tree page whose header is at `header`.
+fn cell_offsets(page: &[u8], header: usize) -> std::io::Result<Vec<usize>> {
+    let kind = *page.get(header).ok_or_else(|| invalid("truncated page"))?;
+    let count = be_u16(page, header + 3)? as usize;
+    // Interior pages have a 12 byte header, leaf pages an 8 byte one.
+    let pointers = header + if kind == INTERIOR_TABLE_PAGE { 12 } else { 8 };
+    (0..count)
+        .map(|i| be_u16(page, pointers + 2 * i).map(usize::from))
+        .collect()
+}
+
+/// The first `columns` values of a record, or `None` if `payload` ends before them.
+fn record(payload: &[u8], columns: usize) -> std::io::Result<Option<Vec<Value>>> {
+    let Ok((header_len, mut at)) = varint(payload, 0) else {
+        return Ok(None);
+    };
+    let header_len = usize::try_from(header_len).map_err(|_| invalid("invalid record"))?;
+    if header_len > payload.len() {
+        return Ok(None);
+    }
+    let mut body = header_len;
+    let mut values = Vec::new();
+    while at < header_len && values.len() < columns {
+        let (serial_type, len) = varint(payload, at)?;
+        at += len;
+        let size = match serial_type {
+            0 | 8 | 9 => 0,
+            1..=4 => serial_type as usize,
+            5 => 6,
+            6 | 7 => 8,
+            n if n >= 12 => (n as usize - 12) / 2,
+            _ => return Err(invalid("invalid serial type")),
+        };
+        let Some(bytes) = payload.get(body..body + size) else {
+            return Ok(None);
+        };
+        body += size;
+        values.push(match serial_type {
+            0 => Value::Null,
+            1..=6 => {
+                // Big-endian two's complement, sign-extended from its first byte.
+                let first = if bytes[0] & 0x80 != 0 { -1 } else { 0 };
+                Value::Integer(bytes.iter().fold(first, |n, &b| (n << 8) | b as i64))
+            }
+            7 => Value::Real(f64::from_be_bytes(bytes.try_into().unwrap())),
+            8 => Value::Integer(0),
+            9 => Value::Integer(1),
+            n if n % 2 == 0 => Value::Blob(bytes.to_vec()),
+            _ => Value::Text(
+                String::from_utf8(bytes.to_vec())
+                    .map_err(|e| Error::new(ErrorKind::InvalidData, e))?,
+            ),
+        });
+    }
+    Ok(Some(values))
+}
+
+/// A sqlite varint at `at`: big-endian 7 bits per byte, the ninth byte contributing all 8.
+/// Returns the value and its length.
+fn varint(bytes: &[u8], at: usize) -> std::io::Result<(i64, usize)> {
+    let mut value = 0u64;
+    for i in 0..9 {
+        let byte = *bytes
+            .get(at + i)
+            .ok_or_else(|| invalid("truncated varint"))?;
+        if i == 8 {
+            return Ok((((value << 8) | byte as u64) as i64, 9));
+        }
+        value = (value << 7) | (byte & 0x7f) as u64;
+        if byte & 0x80 == 0 {
+            return Ok((value as i64, i + 1));
+        }
+    }
+    unreachable!()
+}
+
+fn be_u16(bytes: &[u8], at: usize) -> std::io::Result<u16> {
+    bytes
+        .get(at..at + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .ok_or_else(|| invalid("truncated page"))
+}
+
+fn be_u32(bytes: &[u8], at: usize) -> std::io::Result<u32> {
+    bytes
+        .get(at..at + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| invalid("truncated page"))
+}
+
+fn invalid(what: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, what.to_string())
+}
@@ -0,0 +1,174 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How often `--stats` prints a summary line.
+pub(crate) const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Arrivals older than this are dropped from the rate and bandwidth window.
+const RATE_WINDOW: Duration = Duration::from_secs(2);
+
+/// Rolling receive statistics of one topic: message rate, encoded size, bandwidth and
+/// display rate over the last [`RATE_WINDOW`], and end-to-end latencies (display time
+/// minus header stamp) in seconds over the last `capacity` displayed frames.
+pub(crate) struct StreamStats {
+    arrivals: VecDeque<(Instant, usize)>,
+    displays: VecDeque<Instant>,
+    latencies: VecDeque<f64>,
+    capacity: usize,
+    last_report: Instant,
+}
+
+impl StreamStats {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            arrivals: VecDeque::new(),
+            displays: VecDeque::new(),
+            latencies: VecDeque::with_capacity(capacity),
+            capacity,
+            last_report: Instant::now(),
+        }
+    }
+
+    /// Records a received message of `bytes` encoded bytes.
+    pub(crate) fn arrival(&mut self, now: Instant, bytes: usize) {
+        while self
+            .arrivals
+            .front()
+            .is_some_and(|(t, _)| now - *t > RATE_WINDOW)
+        {
+            self.arrivals.pop_front();
+        }
+        self.arrivals.push_back((now, bytes));
+    }
+
+    /// Records a frame displayed at `now`, `latency` seconds after its header stamp.
+    pub(crate) fn displayed(&mut self, now: Instant, latency: f64) {
+        while self
+            .displays
+            .front()
+            .is_some_and(|t| now - *t > RATE_WINDOW)
+        {
+            self.displays.pop_front();
+        }
+        self.displays.push_back(now);
+        if self.latencies.len() == self.capacity {
+            self.latencies.pop_front();
+        }
+        self.latencies.push_back(latency);
+    }
+
+    /// Formats the current statistics once every [`STATS_INTERVAL`], `None` otherwise.
+    pub(crate) fn report(&mut self, now: Instant) -> Option<String> {
+        if now - self.last_report < STATS_INTERVAL || self.arrivals.is_empty() {
+            return None;
+        }
+        self.last_report = now;
+        let rate = rate_of(self.arrivals.iter().map(|(t, _)| *t));
+        let avg_bytes = self.arrivals.iter().map(|(_, bytes)| *bytes).sum::<usize>() as f64
+            / self.arrivals.len() as f64;
+        let mut line = format!(
+            "{:.1} Hz, avg {:.1} KiB, {:.2} Mbit/s",
+            rate,
+            avg_bytes / 1024.0,
+            rate * avg_bytes * 8.0 / 1e6
+        );
+        if !self.displays.is_empty() {
+            let shown = rate_of(self.displays.iter().copied());
+            line.push_str(&format!(", displayed {:.1} Hz", shown));
+        }
+        if !self.latencies.is_empty() {
+            let min = self.latencies.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = self
+                .latencies
+                .iter()
+                .copied()
+                .fold(f64::NEG_INFINITY, f64::max);
+            let avg = self.latencies.iter().sum::<f64>() / self.latencies.len() as f64;
+            line.push_str(&format!(
+                ", latency min {:.1} / avg {:.1} / max {:.1} ms",
+                min * 1e3,
+                avg * 1e3,
+                max * 1e3
+            ));
+            if min < 0.0 {
+                line.push_str(" (negative: publisher clock is ahead, check time sync)");
+            }
+        }
+        Some(line)
+    }
+}
+
+/// Events per second between the first and the last of `times`.
+fn rate_of(mut times: impl DoubleEndedIterator<Item = Instant> + ExactSizeIterator) -> f64 {
+    let count = times.len();
+    match (times.next(), times.next_back()) {
+        (Some(first), Some(last)) if last > first => {
+            (count - 1) as f64 / (last - first).as_secs_f64()
+        }
+        _ => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(start: Instant, ms: u64) -> Instant {
+        start + Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn report_gives_rate_size_and_bandwidth() {
+        let start = Instant::now();
+        let mut stats = StreamStats::new(4);
+        for i in 0..10 {
+            stats.arrival(ms(start, i * 100), 1280);
+        }
+        assert_eq!(
+            stats.report(ms(start, 1500)).as_deref(),
+            Some("10.0 Hz, avg 1.2 KiB, 0.10 Mbit/s")
+        );
+        // Only once per interval.
+        assert_eq!(stats.report(ms(start, 1600)), None);
+    }
+
+    #[test]
+    fn old_arrivals_leave_the_rate_window() {
+        let start = Instant::now();
+        let mut stats = StreamStats::new(4);
+        stats.arrival(start, 1_000_000);
+        for i in 0..5 {
+            stats.arrival(ms(start, 3000 + i * 50), 1024);
+        }
+        let report = stats.report(ms(start, 3500)).unwrap();
+        assert!(report.starts_with("20.0 Hz, avg 1.0 KiB"), "{}", report);
+    }
+
+    #[test]
+    fn report_gives_display_rate_and_latency() {
+        let start = Instant::now();
+        let mut stats = StreamStats::new(3);
+        for (i, latency) in [0.5, 0.010, 0.020, 0.030].into_iter().enumerate() {
+            stats.arrival(ms(start, i as u64 * 250), 100);
+            stats.displayed(ms(start, i as u64 * 250), latency);
+        }
+        let report = stats.report(ms(start, 1500)).unwrap();
+        // The first latency fell out of the last three.
+        assert!(
+            report.ends_with(", displayed 4.0 Hz, latency min 10.0 / avg 20.0 / max 30.0 ms"),
+            "{}",
+            report
+        );
+    }
+
+    #[test]
+    fn negative_latency_is_flagged() {
+        let start = Instant::now();
+        let mut stats = StreamStats::new(3);
+        stats.arrival(start, 100);
+        stats.displayed(start, -0.005);
+        let report = stats.report(ms(start, 1500)).unwrap();
+        assert!(report.contains("min -5.0"), "{}", report);
+        assert!(report.contains("publisher clock is ahead"), "{}", report);
+    }
+}
@@ -0,0 +1,156 @@
+use log::warn;
+use std::cell::Cell;
+
+/// 256-entry lookup tables applying `--gamma`, then `--brightness` and the per-channel
+/// `--gain` to 8-bit channels, so every tone adjustment is a single pass.
+pub(crate) struct ToneLut {
+    /// One table per RGB channel.
+    tables: [[u8; 256]; 3],
+    /// For single-channel frames, which the gain does not apply to.
+    luma: [u8; 256],
+    gain: [f32; 3],
+    warned_gain: Cell<bool>,
+}
+
+impl ToneLut {
+    /// Builds the tables, or returns `None` when all settings are neutral (1.0).
+    /// Output is `255 * brightness * gain * (v / 255)^(1 / gamma)`, so a gamma above 1
+    /// lifts the shadows and a brightness above 1 scales everything up.
+    pub(crate) fn new(brightness: f32, gamma: f32, gain: [f32; 3]) -> Option<Self> {
+        if brightness == 1.0 && gamma == 1.0 && gain == [1.0; 3] {
+            return None;
+        }
+        let table = |factor: f32| {
+            let mut table = [0; 256];
+            for (v, out) in table.iter_mut().enumerate() {
+                let normalized = (v as f32 / 255.0).powf(1.0 / gamma);
+                *out = (normalized * factor * 255.0).round().clamp(0.0, 255.0) as u8;
+            }
+            table
+        };
+        Some(ToneLut {
+            tables: gain.map(|g| table(brightness * g)),
+            luma: table(brightness),
+            gain,
+            warned_gain: Cell::new(false),
+        })
+    }
+
+    /// Maps the color channels of `img`, leaving alpha untouched. Formats other than
+    /// 8-bit luma/RGB/RGBA are converted to RGB first. `monochrome` says the frame was
+    /// published as mono or depth, see `convert::is_monochrome`: it does not get the gain even
+    /// when a colormap has made it RGB.
+    pub(crate) fn apply(&self, img: image::DynamicImage, monochrome: bool) -> image::DynamicImage {
+        let tables = if monochrome || matches!(img, image::DynamicImage::ImageLuma8(_)) {
+            if self.gain != [1.0; 3] && !self.warned_gain.replace(true) {
+                warn!(
+                    "--gain {:?} is ignored for mono and depth frames",
+                    self.gain
+                );
+            }
+            [&self.luma; 3]
+        } else {
+            self.tables.each_ref()
+        };
+        match img {
+            image::DynamicImage::ImageLuma8(mut buf) => {
+                buf.pixels_mut()
+                    .for_each(|p| p.0[0] = self.luma[p.0[0] as usize]);
+                image::DynamicImage::ImageLuma8(buf)
+            }
+            image::DynamicImage::ImageRgba8(mut buf) => {
+                buf.pixels_mut().for_each(|p| {
+                    for (c, table) in p.0[..3].iter_mut().zip(tables) {
+                        *c = table[*c as usize];
+                    }
+                });
+                image::DynamicImage::ImageRgba8(buf)
+            }
+            img => {
+                let mut buf = img.into_rgb8();
+                buf.pixels_mut().for_each(|p| {
+                    for (c, table) in p.0.iter_mut().zip(tables) {
+                        *c = table[*c as usize];
+                    }
+                });
+                image::DynamicImage::ImageRgb8(buf)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert::{is_monochrome, ConvertOptions, PreviewImage};
+    use crate::msg::{Header, RawImage};
+
+    fn rgb(pixels: &[[u8; 3]]) -> image::DynamicImage {
+        let data = pixels.iter().flatten().copied().collect();
+        image::DynamicImage::ImageRgb8(
+            image::RgbImage::from_raw(pixels.len() as u32, 1, data).unwrap(),
+        )
+    }
+
+    #[test]
+    fn neutral_settings_build_no_tables() {
+        assert!(ToneLut::new(1.0, 1.0, [1.0; 3]).is_none());
+    }
+
+    #[test]
+    fn gamma_one_keeps_the_values() {
+        // Only blue is gained, so red and green go through a gamma 1, brightness 1 table.
+        let lut = ToneLut::new(1.0, 1.0, [1.0, 1.0, 0.5]).unwrap();
+        let out = lut
+            .apply(rgb(&[[0, 128, 200], [255, 17, 254]]), false)
+            .into_rgb8();
+        assert_eq!(out.get_pixel(0, 0).0, [0, 128, 100]);
+        assert_eq!(out.get_pixel(1, 0).0, [255, 17, 127]);
+    }
+
+    #[test]
+    fn brightness_clamps_at_255() {
+        let lut = ToneLut::new(2.0, 1.0, [1.0; 3]).unwrap();
+        let out = lut
+            .apply(rgb(&[[100, 127, 128], [200, 255, 0]]), false)
+            .into_rgb8();
+        assert_eq!(out.get_pixel(0, 0).0, [200, 254, 255]);
+        assert_eq!(out.get_pixel(1, 0).0, [255, 255, 0]);
+    }
+
+    #[test]
+    fn alpha_is_untouched() {
+        let lut = ToneLut::new(0.5, 1.0, [1.0; 3]).unwrap();
+        let img = image::RgbaImage::from_raw(1, 1, vec![200, 100, 50, 77]).unwrap();
+        let out = lut
+            .apply(image::DynamicImage::ImageRgba8(img), false)
+            .into_rgba8();
+        assert_eq!(out.get_pixel(0, 0).0, [100, 50, 25, 77]);
+    }
+
+    #[test]
+    fn colormapped_depth_ignores_the_gain() {
+        let depth = RawImage {
+            header: Header {
+                sec: 0,
+                nanosec: 0,
+                frame_id: String::new(),
+            },
+            height: 1,
+            width: 3,
+            encoding: "16UC1".to_string(),
+            is_bigendian: 0,
+            step: 6,
+            data: [500u16, 1500, 3000]
+                .iter()
+                .flat_map(|d| d.to_le_bytes())
+                .collect(),
+        };
+        let colormapped = depth.to_image(&ConvertOptions::default()).unwrap();
+        assert!(matches!(colormapped, image::DynamicImage::ImageRgb8(_)));
+        let lut = ToneLut::new(1.0, 1.0, [2.0, 1.0, 0.5]).unwrap();
+        let out = lut.apply(colormapped.clone(), is_monochrome(depth.encoding()));
+        assert_eq!(out, colormapped);
+        assert!(lut.warned_gain.get());
+    }
+}